@@ -0,0 +1,128 @@
+// confucius-derive/src/lib.rs
+//! Derive macro for `confucius::FromConfig`
+//!
+//! See the `from_config` module of `confucius` for the supported attributes.
+//! This crate is re-exported by `confucius` with the `derive` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, ExprPath, Fields, LitStr, Type};
+
+/// Derives `confucius::FromConfig` for a struct with named fields.
+#[proc_macro_derive(FromConfig, attributes(config))]
+pub fn derive_from_config(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// How a missing field is filled.
+enum DefaultValue {
+    /// `#[config(default)]`: with `Default::default()`
+    Trait,
+    /// `#[config(default = "path")]`: with the result of the named function
+    Function(ExprPath),
+}
+
+/// The `#[config(...)]` attributes of the struct or of a field.
+#[derive(Default)]
+struct ConfigAttrs {
+    section: Option<String>,
+    key: Option<String>,
+    default: Option<DefaultValue>,
+}
+
+/// Parses the `#[config(...)]` attributes.
+fn parse_attrs(attrs: &[Attribute]) -> syn::Result<ConfigAttrs> {
+    let mut result = ConfigAttrs::default();
+
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("config")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("section") {
+                result.section = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("key") {
+                result.key = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("default") {
+                result.default = Some(if meta.input.peek(syn::Token![=]) {
+                    DefaultValue::Function(meta.value()?.parse::<LitStr>()?.parse()?)
+                } else {
+                    DefaultValue::Trait
+                });
+            } else {
+                return Err(meta.error("unknown config attribute, expected `section`, `key` or `default`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(result)
+}
+
+/// Checks whether a type is written as `Option<...>`.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(type_path) => type_path.path.segments.last().is_some_and(|s| s.ident == "Option"),
+        _ => false,
+    }
+}
+
+/// Generates the `FromConfig` implementation.
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(input, "FromConfig can only be derived for structs"));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(input, "FromConfig requires named fields"));
+    };
+
+    let struct_attrs = parse_attrs(&input.attrs)?;
+
+    let mut initializers = Vec::new();
+    for field in &fields.named {
+        let ident = field.ident.as_ref().expect("named field");
+        let attrs = parse_attrs(&field.attrs)?;
+
+        let Some(section) = attrs.section.or_else(|| struct_attrs.section.clone()) else {
+            return Err(syn::Error::new_spanned(
+                field, "missing section: use #[config(section = \"...\")] on the field or the struct"
+            ));
+        };
+        let key = attrs.key.unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_string());
+
+        let value = match attrs.default {
+            Some(default) => {
+                let default = match default {
+                    DefaultValue::Trait => quote! { ::std::default::Default::default() },
+                    DefaultValue::Function(path) => quote! { #path() },
+                };
+                quote! {
+                    match ::confucius::__private::optional_field(config, #section, #key)? {
+                        ::std::option::Option::Some(value) => value,
+                        ::std::option::Option::None => #default,
+                    }
+                }
+            },
+            None if is_option(&field.ty) => quote! {
+                ::confucius::__private::optional_field(config, #section, #key)?
+            },
+            None => quote! {
+                ::confucius::__private::required_field(config, #section, #key)?
+            },
+        };
+
+        initializers.push(quote! { #ident: #value });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::confucius::FromConfig for #name #ty_generics #where_clause {
+            fn from_config(config: &::confucius::Config) -> ::std::result::Result<Self, ::confucius::ConfigError> {
+                ::std::result::Result::Ok(Self {
+                    #(#initializers),*
+                })
+            }
+        }
+    })
+}
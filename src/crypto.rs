@@ -0,0 +1,151 @@
+// src/crypto.rs
+//! Transparent encryption of configuration values at rest
+//!
+//! Values stored as `enc:<ciphertext>` are decrypted when the configuration is
+//! loaded and encrypted again when it is saved. The actual cryptography is left
+//! to the application, which plugs it in through the `Encryptor` and `Decryptor`
+//! traits.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{Config, ConfigError, ConfigValue};
+
+/// Prefix identifying an encrypted value in a configuration file.
+pub const ENCRYPTED_PREFIX: &str = "enc:";
+
+/// Encrypts plaintext values before they are written to disk.
+pub trait Encryptor: Send + Sync {
+    /// Encrypts a plaintext value, returning the ciphertext (without the `enc:` prefix).
+    fn encrypt(&self, plaintext: &str) -> Result<String, String>;
+}
+
+/// Decrypts values read from disk.
+pub trait Decryptor: Send + Sync {
+    /// Decrypts a ciphertext (without the `enc:` prefix), returning the plaintext.
+    fn decrypt(&self, ciphertext: &str) -> Result<String, String>;
+}
+
+/// Encryption state attached to a `Config`.
+#[derive(Clone, Default)]
+pub(crate) struct ValueCrypto {
+    /// Encryptor used when saving.
+    encryptor: Option<Arc<dyn Encryptor>>,
+    /// Decryptor used when loading.
+    decryptor: Option<Arc<dyn Decryptor>>,
+    /// Keys (section, key) whose values must be stored encrypted.
+    encrypted_keys: HashSet<(String, String)>,
+}
+
+impl fmt::Debug for ValueCrypto {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValueCrypto")
+            .field("encryptor", &self.encryptor.is_some())
+            .field("decryptor", &self.decryptor.is_some())
+            .field("encrypted_keys", &self.encrypted_keys)
+            .finish()
+    }
+}
+
+impl ValueCrypto {
+    /// Sets the encryptor used when saving.
+    pub fn set_encryptor(&mut self, encryptor: Arc<dyn Encryptor>) {
+        self.encryptor = Some(encryptor);
+    }
+
+    /// Sets the decryptor used when loading.
+    pub fn set_decryptor(&mut self, decryptor: Arc<dyn Decryptor>) {
+        self.decryptor = Some(decryptor);
+    }
+
+    /// Marks a key as encrypted.
+    pub fn mark(&mut self, section: &str, key: &str) {
+        self.encrypted_keys.insert((section.to_string(), key.to_string()));
+    }
+
+    /// Checks whether a key is marked as encrypted.
+    pub fn is_marked(&self, section: &str, key: &str) -> bool {
+        self.encrypted_keys.contains(&(section.to_string(), key.to_string()))
+    }
+
+    /// Checks whether any key is marked as encrypted.
+    pub fn has_marked(&self) -> bool {
+        !self.encrypted_keys.is_empty()
+    }
+}
+
+/// Decrypts every `enc:` string value of a configuration in place.
+///
+/// Each decrypted key is marked as encrypted, so that it is encrypted again
+/// when the configuration is saved. If no decryptor is set, values are left
+/// untouched.
+///
+/// # Arguments
+///
+/// * `config` - A mutable reference to the `Config` instance to decrypt.
+///
+/// # Returns
+///
+/// * `Ok(())` - If all encrypted values are successfully decrypted.
+/// * `Err(ConfigError)` - If the decryptor fails on a value.
+pub fn decrypt_config(config: &mut Config) -> Result<(), ConfigError> {
+    let Some(decryptor) = config.crypto.decryptor.clone() else {
+        return Ok(());
+    };
+
+    let mut decrypted_keys = Vec::new();
+    for (section, keys) in config.values.iter_mut() {
+        for (key, value) in keys.iter_mut() {
+            if let ConfigValue::String(s) = value
+                && let Some(ciphertext) = s.strip_prefix(ENCRYPTED_PREFIX)
+            {
+                let plaintext = decryptor.decrypt(ciphertext).map_err(|e| {
+                    ConfigError::EncryptionError(format!("Cannot decrypt {}.{}: {}", section, key, e))
+                })?;
+                *value = ConfigValue::String(plaintext);
+                decrypted_keys.push((section.clone(), key.clone()));
+            }
+        }
+    }
+
+    config.crypto.encrypted_keys.extend(decrypted_keys);
+    Ok(())
+}
+
+/// Returns a copy of the configuration with the marked values encrypted.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance to encrypt.
+///
+/// # Returns
+///
+/// * `Ok(Config)` - A copy of the configuration ready to be written to disk.
+/// * `Err(ConfigError)` - If no encryptor is set, a marked value is not a string,
+///   or the encryptor fails.
+pub fn encrypt_config(config: &Config) -> Result<Config, ConfigError> {
+    let encryptor = config.crypto.encryptor.clone().ok_or_else(|| {
+        ConfigError::EncryptionError("Encrypted values present but no encryptor set".to_string())
+    })?;
+
+    let mut encrypted = config.clone();
+    for (section, key) in &config.crypto.encrypted_keys {
+        let Some(value) = encrypted.values.get_mut(section).and_then(|keys| keys.get_mut(key)) else {
+            continue;
+        };
+
+        let ConfigValue::String(plaintext) = value else {
+            return Err(ConfigError::EncryptionError(format!(
+                "Only string values can be encrypted: {}.{}", section, key
+            )));
+        };
+
+        let ciphertext = encryptor.encrypt(plaintext).map_err(|e| {
+            ConfigError::EncryptionError(format!("Cannot encrypt {}.{}: {}", section, key, e))
+        })?;
+        *value = ConfigValue::String(format!("{}{}", ENCRYPTED_PREFIX, ciphertext));
+    }
+
+    Ok(encrypted)
+}
@@ -1,12 +1,11 @@
 //! Implementation of the parser and writer for the INI format.
 
-use std::fs::{self, File};
-use std::io::{BufRead, Write};
+use std::collections::HashMap;
 use std::path::Path;
 use regex::Regex;
 
-use crate::{Config, ConfigError, ConfigValue};
-use crate::include;
+use crate::{Config, ConfigError, ConfigValue, ValidationSchema, ValueSource};
+use crate::include::{self, IncludeOrder};
 use crate::utils;
 
 /// Parses an INI file and updates the provided configuration.
@@ -25,10 +24,13 @@ use crate::utils;
 /// * `Ok(())` - If the parsing is successful.
 /// * `Err(ConfigError)` - If an error occurs during parsing.
 pub fn parse_ini(config: &mut Config, content: &str, path: &Path) -> Result<(), ConfigError> {
-    let mut current_section = "default".to_string();
+    let mut current_section = config.root_section.clone();
+    // The tables of a `[parent.child]` subsection, prefixed to its keys
+    let mut current_table: Vec<String> = Vec::new();
     let section_regex = Regex::new(r"^\s*\[(.*?)\]\s*$").unwrap();
-    let kv_regex = Regex::new(r"^\s*(.*?)\s*=\s*(.*?)\s*$").unwrap();
-    let include_regex = Regex::new(r"^\s*include\s*=\s*(.*?)\s*$").unwrap();
+    let separator = regex::escape(&config.ini_separator.to_string());
+    let include_regex = Regex::new(&format!(r"^\s*(include(?:_before|_after)?\??)\s*{}\s*(.*?)\s*$", separator)).unwrap();
+    let heredoc_regex = Regex::new(&format!(r"^\s*(.*?)\s*{}\s*<<([A-Za-z_][A-Za-z0-9_]*)$", separator)).unwrap();
 
     // Skip the first line if it contains the format (#!config/...)
     let lines_to_process = if content.lines().next().unwrap_or("").starts_with("#!config/") {
@@ -37,36 +39,278 @@ pub fn parse_ini(config: &mut Config, content: &str, path: &Path) -> Result<(),
         content.lines().collect::<Vec<_>>()
     };
 
-    for line in lines_to_process {
-        // Remove comments from the line
-        let line = utils::strip_comments(line);
-        if line.is_empty() {
-            continue;
-        }
+    // Remove comments and empty lines, keeping heredoc values verbatim
+    let lines = logical_lines(&lines_to_process, &heredoc_regex, path)?;
+
+    // include_before directives are applied first, wherever they appear
+    process_includes_with_order(config, &lines, &include_regex, path, IncludeOrder::Before)?;
 
-        // Check if it is an include directive
-        if let Some(cap) = include_regex.captures(&line) {
-            let include_path = cap.get(1).unwrap().as_str();
-            process_include(config, include_path, path)?;
+    for line in &lines {
+        let line = match line {
+            IniLine::Text(line) => line,
+            IniLine::Heredoc { key, value } => {
+                let key = table_key(&current_table, key, config.ini_dotted_keys);
+                set_dotted(config, &current_section, &key, ConfigValue::String(value.clone()), path)?;
+                continue;
+            },
+        };
+
+        // Check if it is an include directive (`include?=` skips missing files)
+        if let Some(cap) = include_regex.captures(line) {
+            if let Some(directive) = include::parse_include_key(&cap[1])
+                && directive.order == IncludeOrder::Inline {
+                process_include(config, &cap[2], path, directive.optional)?;
+            }
             continue;
         }
 
         // Check if it is a section
-        if let Some(cap) = section_regex.captures(&line) {
-            current_section = cap.get(1).unwrap().as_str().to_string();
+        if let Some(cap) = section_regex.captures(line) {
+            (current_section, current_table) = split_section_header(cap.get(1).unwrap().as_str());
+            if !current_table.is_empty() {
+                // The subsection exists even without keys
+                let section_values = config.values.entry(current_section.clone()).or_default();
+                nested_table(section_values, &current_section, &current_table, path)?;
+            }
             continue;
         }
 
         // Otherwise, it is a key-value pair
-        if let Some(cap) = kv_regex.captures(&line) {
-            let key = cap.get(1).unwrap().as_str();
-            let value_str = cap.get(2).unwrap().as_str();
-
+        if let Some((key, value_str)) = split_entry(line, config.ini_separator) {
             // Convert the value to the appropriate type
-            let value = parse_value(value_str);
+            let value = if config.ini_auto_type {
+                parse_value(value_str, config.decimal_separator)
+            } else {
+                ConfigValue::String(utils::unquote(value_str))
+            };
 
-            // Insert into the configuration
-            config.set(&current_section, key, value);
+            // Insert into the configuration, dotted keys as nested tables if enabled
+            let key = table_key(&current_table, key, config.ini_dotted_keys);
+            set_dotted(config, &current_section, &key, value, path)?;
+        }
+    }
+
+    // include_after directives are applied last, overriding the keys of this file
+    process_includes_with_order(config, &lines, &include_regex, path, IncludeOrder::After)?;
+
+    Ok(())
+}
+
+/// A logical line of an INI file.
+enum IniLine {
+    /// A line without comments
+    Text(String),
+    /// A `key = <<TERMINATOR` heredoc, with the literal lines up to the terminator
+    Heredoc { key: String, value: String },
+}
+
+/// Splits the content of an INI file into logical lines.
+///
+/// Comments and empty lines are removed, except inside heredocs: the lines
+/// following `key = <<END` up to a line containing only `END` are kept as they
+/// are and joined with newlines into the value of the key.
+///
+/// # Arguments
+///
+/// * `raw_lines` - The lines of the INI file.
+/// * `heredoc_regex` - The regex matching the first line of a heredoc.
+/// * `path` - The path of the INI file, used in error messages.
+///
+/// # Returns
+///
+/// * `Ok(Vec<IniLine>)` - The logical lines of the file.
+/// * `Err(ConfigError)` - If a heredoc is not terminated.
+fn logical_lines(raw_lines: &[&str], heredoc_regex: &Regex, path: &Path) -> Result<Vec<IniLine>, ConfigError> {
+    let mut lines = Vec::new();
+    let mut raw_lines = raw_lines.iter();
+
+    while let Some(raw_line) = raw_lines.next() {
+        let line = utils::strip_comments(raw_line);
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some(cap) = heredoc_regex.captures(&line) else {
+            lines.push(IniLine::Text(line));
+            continue;
+        };
+
+        let terminator = &cap[2];
+        let mut body = Vec::new();
+        loop {
+            match raw_lines.next() {
+                Some(body_line) if body_line.trim() == terminator => break,
+                Some(body_line) => body.push(*body_line),
+                None => return Err(ConfigError::ParseError(format!(
+                    "Unterminated heredoc for key {} in {}: missing {}", &cap[1], path.display(), terminator
+                ))),
+            }
+        }
+
+        lines.push(IniLine::Heredoc { key: cap[1].to_string(), value: body.join("\n") });
+    }
+
+    Ok(lines)
+}
+
+/// Splits a line into its key and value at the first separator outside quotes.
+///
+/// Returns `None` if the line has no separator.
+fn split_entry(line: &str, separator: char) -> Option<(&str, &str)> {
+    let mut in_quotes = false;
+    let mut chars = line.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '\\' if in_quotes => {
+                chars.next();
+            },
+            c if c == separator && !in_quotes => {
+                return Some((line[..i].trim(), line[i + c.len_utf8()..].trim()));
+            },
+            _ => {},
+        }
+    }
+
+    None
+}
+
+/// Splits a dotted key or section name into its segments.
+///
+/// Segments are separated by dots outside quotes, and quoted segments are
+/// unquoted, so `"a.b".c` names the key `c` of the table `a.b`. A name with
+/// empty segments (e.g. `a..b`) is a single segment, kept as it is.
+fn split_key_path(name: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut chars = name.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '\\' if in_quotes => {
+                chars.next();
+            },
+            '.' if !in_quotes => {
+                segments.push(name[start..i].trim());
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    segments.push(name[start..].trim());
+
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return vec![name.to_string()];
+    }
+    segments.into_iter().map(utils::unquote).collect()
+}
+
+/// Splits a section header into its section and, for a `[parent.child]`
+/// subsection, the path of its table within the section.
+///
+/// Headers with empty segments (e.g. `[.a]`) name a plain section.
+fn split_section_header(name: &str) -> (String, Vec<String>) {
+    let mut segments = split_key_path(name);
+    let section = segments.remove(0);
+    (section, segments)
+}
+
+/// Prefixes the segments of a key with the tables of the current subsection.
+///
+/// The key itself is split on its dots only with dotted keys (see
+/// `Config::set_ini_dotted_keys`); otherwise it is a single segment.
+fn table_key(table: &[String], key: &str, dotted: bool) -> Vec<String> {
+    let segments = if dotted { split_key_path(key) } else { vec![utils::unquote(key)] };
+    table.iter().cloned().chain(segments).collect()
+}
+
+/// Returns the table at a path of nested tables, creating the missing ones.
+///
+/// # Arguments
+///
+/// * `table` - The values of the section containing the path.
+/// * `section` - The name of the section, used in error messages.
+/// * `segments` - The names of the nested tables.
+/// * `path` - The path of the INI file, used in error messages.
+///
+/// # Returns
+///
+/// * `Ok(&mut HashMap)` - The innermost table.
+/// * `Err(ConfigError)` - A `ConfigError::ParseError` if a value on the path
+///   is not a table, which is kept rather than replaced.
+fn nested_table<'a>(mut table: &'a mut HashMap<String, ConfigValue>, section: &str,
+                    segments: &[String], path: &Path) -> Result<&'a mut HashMap<String, ConfigValue>, ConfigError> {
+    let mut current_path = section.to_string();
+    for segment in segments {
+        current_path = format!("{}.{}", current_path, segment);
+        let entry = table.entry(segment.clone())
+            .or_insert_with(|| ConfigValue::Table(HashMap::new()));
+        let ConfigValue::Table(nested) = entry else {
+            return Err(ConfigError::ParseError(format!(
+                "{} is not a table in {}: cannot set keys below it", current_path, path.display()
+            )));
+        };
+        table = nested;
+    }
+    Ok(table)
+}
+
+/// Sets a value, storing a dotted key (e.g. `app.name`) in nested tables.
+///
+/// Each segment but the last names a table, created if needed; a non-table
+/// value in the way is an error (e.g. `a = 1` followed by `a.b = 2`).
+///
+/// # Arguments
+///
+/// * `config` - A mutable reference to the `Config` instance to update.
+/// * `section` - The section containing the key.
+/// * `segments` - The segments of the key, as split by `split_key_path`.
+/// * `value` - The value to set.
+/// * `path` - The path of the INI file, recorded as the source of the value.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the value is set.
+/// * `Err(ConfigError)` - If a value on the path of the key is not a table.
+fn set_dotted(config: &mut Config, section: &str, segments: &[String], value: ConfigValue,
+              path: &Path) -> Result<(), ConfigError> {
+    let (last, tables) = segments.split_last().unwrap();
+    if tables.is_empty() {
+        config.set_from_file(section, last, value, path);
+        return Ok(());
+    }
+
+    let table = nested_table(config.values.entry(section.to_string()).or_default(), section, tables, path)?;
+    table.insert(last.clone(), value);
+    config.record_source(section, &segments[0], ValueSource::File(path.to_path_buf()));
+    Ok(())
+}
+
+/// Processes the include directives of an INI file applied in the given order.
+///
+/// # Arguments
+///
+/// * `config` - A mutable reference to the `Config` instance to update.
+/// * `lines` - The logical lines of the INI file.
+/// * `include_regex` - The regex matching an include directive.
+/// * `base_path` - The base path of the current INI file.
+/// * `order` - The order of the directives to process.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the includes are processed successfully.
+/// * `Err(ConfigError)` - If an error occurs during processing.
+fn process_includes_with_order(config: &mut Config, lines: &[IniLine], include_regex: &Regex,
+                               base_path: &Path, order: IncludeOrder) -> Result<(), ConfigError> {
+    for line in lines {
+        if let IniLine::Text(line) = line
+            && let Some(cap) = include_regex.captures(line)
+            && let Some(directive) = include::parse_include_key(&cap[1])
+            && directive.order == order {
+            process_include(config, &cap[2], base_path, directive.optional)?;
         }
     }
 
@@ -75,6 +319,31 @@ pub fn parse_ini(config: &mut Config, content: &str, path: &Path) -> Result<(),
 
 /// Processes an include directive in an INI file.
 ///
+/// The value is a comma-separated list of paths or glob patterns, e.g.
+/// `include = a.conf, b.conf, conf.d/*.conf`, included in order.
+///
+/// # Arguments
+///
+/// * `config` - A mutable reference to the `Config` instance to update.
+/// * `include_value` - The value of the directive.
+/// * `base_path` - The base path of the current INI file.
+/// * `optional` - Whether missing files are skipped instead of reported as errors.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the include is processed successfully.
+/// * `Err(ConfigError)` - If an error occurs during processing.
+fn process_include(config: &mut Config, include_value: &str, base_path: &Path,
+                   optional: bool) -> Result<(), ConfigError> {
+    for include_path in include_value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        process_single_include(config, include_path, base_path, optional)?;
+    }
+
+    Ok(())
+}
+
+/// Processes a single path or glob pattern of an include directive.
+///
 /// This function handles both single file includes and glob patterns, resolving
 /// the paths and parsing the included files.
 ///
@@ -83,25 +352,38 @@ pub fn parse_ini(config: &mut Config, content: &str, path: &Path) -> Result<(),
 /// * `config` - A mutable reference to the `Config` instance to update.
 /// * `include_path` - The path or glob pattern of the file(s) to include.
 /// * `base_path` - The base path of the current INI file.
+/// * `optional` - Whether missing files are skipped instead of reported as errors.
 ///
 /// # Returns
 ///
 /// * `Ok(())` - If the include is processed successfully.
 /// * `Err(ConfigError)` - If an error occurs during processing.
-fn process_include(config: &mut Config, include_path: &str, base_path: &Path) -> Result<(), ConfigError> {
+fn process_single_include(config: &mut Config, include_path: &str, base_path: &Path,
+                          optional: bool) -> Result<(), ConfigError> {
+    // ${VAR} tokens are expanded from the environment
+    let include_path = include::expand_env_vars(include_path)?;
+
+    // A format prefix such as `toml:` overrides the format of the included files
+    let (format, include_path) = include::split_format_prefix(config, &include_path);
+
     // If the include is a glob pattern, include all matching files
     if include_path.contains('*') {
-        include::process_glob_include(config, include_path, base_path)?;
+        include::process_glob_include(config, include_path, base_path, optional, format)?;
     } else {
         // Otherwise, include a single file
         let resolved_path = utils::resolve_path(base_path, include_path);
         if resolved_path.exists() {
-            let content = fs::read_to_string(&resolved_path)
-                .map_err(|e| ConfigError::IncludeError(format!("Error reading included file {}: {}",
-                                                               resolved_path.display(), e)))?;
+            // A file already read by this load, possibly through another path, is skipped
+            if !include::mark_included(config, &resolved_path) {
+                return Ok(());
+            }
+            let content = include::read_included_file(config, &resolved_path)?;
 
-            parse_ini(config, &content, &resolved_path)?;
-        } else {
+            match format {
+                Some(format) => include::parse_content(config, format, &content, &resolved_path)?,
+                None => parse_ini(config, &content, &resolved_path)?,
+            }
+        } else if !optional {
             return Err(ConfigError::IncludeError(format!("Included file not found: {}",
                                                          resolved_path.display())));
         }
@@ -118,21 +400,20 @@ fn process_include(config: &mut Config, include_path: &str, base_path: &Path) ->
 /// # Arguments
 ///
 /// * `value_str` - The string to convert.
+/// * `decimal_separator` - The decimal separator accepted in floats besides `.`.
 ///
 /// # Returns
 ///
 /// A `ConfigValue` representing the parsed value.
-fn parse_value(value_str: &str) -> ConfigValue {
+fn parse_value(value_str: &str, decimal_separator: char) -> ConfigValue {
     // If it is quoted, it is a string
     if utils::is_quoted(value_str) {
         return ConfigValue::String(utils::unquote(value_str));
     }
 
     // Try to convert to boolean
-    match value_str.to_lowercase().as_str() {
-        "true" | "yes" | "on" | "1" => return ConfigValue::Boolean(true),
-        "false" | "no" | "off" | "0" => return ConfigValue::Boolean(false),
-        _ => {}
+    if let Some(b) = utils::parse_bool(value_str) {
+        return ConfigValue::Boolean(b);
     }
 
     // Try to convert to integer
@@ -145,6 +426,12 @@ fn parse_value(value_str: &str) -> ConfigValue {
         return ConfigValue::Float(f);
     }
 
+    // Try again with the configured decimal separator, e.g. `3,14`
+    if decimal_separator != '.' && value_str.matches(decimal_separator).count() == 1
+        && let Ok(f) = value_str.replace(decimal_separator, ".").parse::<f64>() {
+        return ConfigValue::Float(f);
+    }
+
     // Otherwise, it is a string
     ConfigValue::String(value_str.to_string())
 }
@@ -164,31 +451,264 @@ fn parse_value(value_str: &str) -> ConfigValue {
 /// * `Ok(())` - If the writing is successful.
 /// * `Err(ConfigError)` - If an error occurs during writing.
 pub fn write_ini(config: &Config, path: &Path) -> Result<(), ConfigError> {
-    let mut file = File::create(path).map_err(ConfigError::Io)?;
+    let content = render_ini(config, None)?;
+    utils::write_atomic(path, content.as_bytes()).map_err(ConfigError::Io)
+}
 
+/// Serializes the configuration into the INI format.
+///
+/// If a schema is provided, the description of each documented field is
+/// written as a `#` comment above its key.
+///
+/// Values are converted as follows, since INI only has scalar values:
+/// - Tables held by a section are written as subsections (`[section.app]`),
+///   after the other keys of the section. Tables nested deeper are written as
+///   subsections of their own (`[section.app.limits]`), or as dotted keys in
+///   their subsection (`limits.workers = 4`) with dotted keys enabled (see
+///   `Config::set_ini_dotted_keys`).
+/// - Arrays of scalars are written as a quoted, comma-separated string, which
+///   is read back as a string.
+/// - Null is written as an empty value.
+/// - Arrays containing arrays or tables, and with dotted keys empty tables
+///   below a subsection, cannot be represented, and are reported as a
+///   `ConfigError::TypeError`.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance to serialize.
+/// * `schema` - An optional `ValidationSchema` providing field descriptions.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The serialized INI document.
+/// * `Err(ConfigError)` - If an error occurs during serialization.
+pub(crate) fn render_ini(config: &Config, schema: Option<&ValidationSchema>) -> Result<String, ConfigError> {
     // Write the format header
-    writeln!(file, "#!config/ini").map_err(ConfigError::Io)?;
+    let mut output = String::from("#!config/ini\n");
 
     // For each section
     for (section, values) in &config.values {
-        // Skip the default section if it is empty
-        if section == "default" && values.is_empty() {
+        // Skip the root section if it is empty
+        if *section == config.root_section && values.is_empty() {
             continue;
         }
 
-        // Write the section header
-        writeln!(file, "\n[{}]", section).map_err(ConfigError::Io)?;
+        let (tables, keys): (Vec<_>, Vec<_>) = values.iter()
+            .partition(|(_, value)| matches!(value, ConfigValue::Table(_)));
+
+        // Write the section header, unless the section only holds subsections
+        let header = format_section_name(section, config.nested_sections, config.ini_separator);
+        if !keys.is_empty() || tables.is_empty() {
+            output.push_str(&format!("\n[{}]\n", header));
+        }
+
+        // Write each key-value pair, preceded by its description if any
+        let width = entries_width(config, keys.iter().copied());
+        for (key, value) in keys {
+            if let Some(description) = schema.and_then(|s| s.field_description(section, key)) {
+                output.push_str(&utils::format_comment(description));
+            }
+
+            let key_str = quote_key(key, config.ini_separator, config.ini_dotted_keys);
+            render_value(&mut output, section, &key_str, value, config.ini_separator, width)?;
+        }
 
-        // Write each key-value pair
-        for (key, value) in values {
-            let value_str = format_value(value);
-            writeln!(file, "{} = {}", key, value_str).map_err(ConfigError::Io)?;
+        // Write the tables as [section.table] subsections
+        let mut tables: Vec<(&String, &ConfigValue)> = tables;
+        tables.sort_by(|a, b| a.0.cmp(b.0));
+        for (table_name, table) in tables {
+            let ConfigValue::Table(entries) = table else { unreachable!() };
+            let table_path = format!("{}.{}", header, quote_key(table_name, config.ini_separator, true));
+
+            output.push('\n');
+            if let Some(description) = schema.and_then(|s| s.field_description(section, table_name)) {
+                output.push_str(&utils::format_comment(description));
+            }
+            render_subsection(&mut output, config, &table_path, entries)?;
         }
     }
 
+    Ok(output)
+}
+
+/// Writes a table as a `[path]` subsection.
+///
+/// With dotted keys (see `Config::set_ini_dotted_keys`), the tables it holds
+/// are written as dotted keys; otherwise they are written as subsections of
+/// their own, after its other keys.
+///
+/// # Arguments
+///
+/// * `output` - The string the lines are appended to.
+/// * `config` - The configuration being written, for its INI settings.
+/// * `table_path` - The header of the subsection, e.g. `section.app`.
+/// * `entries` - The entries of the table.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the subsection is written.
+/// * `Err(ConfigError)` - If a value cannot be represented in INI.
+fn render_subsection(output: &mut String, config: &Config, table_path: &str,
+                     entries: &HashMap<String, ConfigValue>) -> Result<(), ConfigError> {
+    output.push_str(&format!("[{}]\n", table_path));
+
+    let (mut tables, mut keys): (Vec<_>, Vec<_>) = entries.iter()
+        .partition(|(_, value)| !config.ini_dotted_keys && matches!(value, ConfigValue::Table(_)));
+    keys.sort_by(|a, b| a.0.cmp(b.0));
+    let width = entries_width(config, keys.iter().copied());
+    for (sub_key, value) in keys {
+        let sub_key_str = quote_key(sub_key, config.ini_separator, config.ini_dotted_keys);
+        render_value(output, table_path, &sub_key_str, value, config.ini_separator, width)?;
+    }
+
+    tables.sort_by(|a, b| a.0.cmp(b.0));
+    for (table_name, table) in tables {
+        let ConfigValue::Table(nested) = table else { unreachable!() };
+        output.push('\n');
+        let nested_path = format!("{}.{}", table_path, quote_key(table_name, config.ini_separator, true));
+        render_subsection(output, config, &nested_path, nested)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the width of the widest key written for some entries of a section,
+/// or 0 if the values are not aligned (see `Config::set_ini_align`).
+///
+/// Tables count with the dotted keys of their entries.
+fn entries_width<'a>(config: &Config, entries: impl Iterator<Item = (&'a String, &'a ConfigValue)>) -> usize {
+    if !config.ini_align {
+        return 0;
+    }
+
+    entries
+        .map(|(key, value)| {
+            key_width(&quote_key(key, config.ini_separator, config.ini_dotted_keys), value, config.ini_separator)
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Returns the width of a written key, or of the widest dotted key of a table.
+fn key_width(key: &str, value: &ConfigValue, separator: char) -> usize {
+    match value {
+        ConfigValue::Table(table) => table.iter()
+            .map(|(sub_key, sub_value)| {
+                key_width(&format!("{}.{}", key, quote_key(sub_key, separator, true)), sub_value, separator)
+            })
+            .max()
+            .unwrap_or(0),
+        _ => key.chars().count(),
+    }
+}
+
+/// Writes a key-value pair, flattening tables into dotted keys.
+///
+/// A table such as `app = { name = "demo" }` is written as `app.name = "demo"`,
+/// which the parser reads back as a table. Multi-line strings are written as
+/// heredocs.
+///
+/// # Arguments
+///
+/// * `output` - The string the lines are appended to.
+/// * `section` - The section or subsection of the key, used in error messages.
+/// * `key` - The key as written, including the dotted prefix of any enclosing table.
+/// * `value` - The value to write.
+/// * `separator` - The key-value separator character.
+/// * `width` - The width keys are padded to, to align the values.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the value is written.
+/// * `Err(ConfigError)` - If the value cannot be represented in INI.
+fn render_value(output: &mut String, section: &str, key: &str, value: &ConfigValue,
+                separator: char, width: usize) -> Result<(), ConfigError> {
+    match value {
+        ConfigValue::Table(table) if table.is_empty() => {
+            return Err(ConfigError::TypeError(format!(
+                "Cannot write {}.{} in INI format: empty tables are not supported", section, key
+            )));
+        },
+        ConfigValue::Table(table) => {
+            let mut sub_keys: Vec<&String> = table.keys().collect();
+            sub_keys.sort();
+
+            for sub_key in sub_keys {
+                let sub_key_str = format!("{}.{}", key, quote_key(sub_key, separator, true));
+                render_value(output, section, &sub_key_str, &table[sub_key], separator, width)?;
+            }
+        },
+        ConfigValue::Array(items) if items.iter().any(|item| matches!(item, ConfigValue::Array(_) | ConfigValue::Table(_))) => {
+            return Err(ConfigError::TypeError(format!(
+                "Cannot write {}.{} in INI format: arrays can only contain scalar values", section, key
+            )));
+        },
+        // Multi-line strings are written as heredocs, keeping their content literal
+        ConfigValue::String(s) if s.contains('\n') => {
+            let terminator = heredoc_terminator(s);
+            output.push_str(&format_entry(key, separator, &format!("<<{}", terminator), width));
+            output.push_str(&format!("{}\n{}\n", s, terminator));
+        },
+        _ => output.push_str(&format_entry(key, separator, &format_value(value), width)),
+    }
+
     Ok(())
 }
 
+/// Chooses a heredoc terminator (`END`, `END1`, ...) not found as a line of the value.
+fn heredoc_terminator(value: &str) -> String {
+    (0..)
+        .map(|i| if i == 0 { "END".to_string() } else { format!("END{}", i) })
+        .find(|terminator| value.lines().all(|line| line.trim() != terminator))
+        .unwrap()
+}
+
+/// Quotes a key or section name that would otherwise be read back differently.
+///
+/// Names containing the separator, quotes, brackets or comment characters,
+/// names with surrounding whitespace, empty names and names of include
+/// directives are written between double quotes, e.g. `"a=b" = 1`. So are
+/// names containing dots, when `dotted` tells that dots separate tables in
+/// them (section headers, and keys with dotted keys enabled).
+fn quote_key(name: &str, separator: char, dotted: bool) -> String {
+    let needs_quotes = name.is_empty()
+        || name.trim() != name
+        || name.chars().any(|c| c == separator || c.is_control() || matches!(c, '"' | '#' | '[' | ']'))
+        || (dotted && name.contains('.'))
+        || include::parse_include_key(name).is_some();
+
+    if needs_quotes {
+        format!("\"{}\"", utils::escape(name))
+    } else {
+        name.to_string()
+    }
+}
+
+/// Formats a section name for its header.
+///
+/// With nested sections (see `Config::set_nested_sections`), a dotted section
+/// name such as `server.tls` is written as a subsection, which is read back as
+/// the same section; otherwise the dots are quoted like any other character.
+fn format_section_name(section: &str, nested_sections: bool, separator: char) -> String {
+    if nested_sections && section.split('.').all(|segment| !segment.is_empty()) {
+        section.split('.').map(|segment| quote_key(segment, separator, true)).collect::<Vec<_>>().join(".")
+    } else {
+        quote_key(section, separator, true)
+    }
+}
+
+/// Formats a key-value line, e.g. `key = value` or `key: value`.
+///
+/// The key is padded to `width` characters, before `=` or after other separators.
+fn format_entry(key: &str, separator: char, value_str: &str, width: usize) -> String {
+    let padding = " ".repeat(width.saturating_sub(key.chars().count()));
+    if separator == '=' {
+        format!("{}{} = {}\n", key, padding, value_str)
+    } else {
+        format!("{}{}{} {}\n", key, separator, padding, value_str)
+    }
+}
+
 /// Formats a `ConfigValue` as a string.
 ///
 /// This function converts a `ConfigValue` into its string representation
@@ -203,9 +723,11 @@ pub fn write_ini(config: &Config, path: &Path) -> Result<(), ConfigError> {
 /// A string representing the formatted value.
 fn format_value(value: &ConfigValue) -> String {
     match value {
-        ConfigValue::String(s) => format!("\"{}\"", s),
+        ConfigValue::String(s) => format!("\"{}\"", utils::escape(s)),
         ConfigValue::Integer(i) => i.to_string(),
         ConfigValue::Float(f) => f.to_string(),
+        // INI has no null, so it is written as an empty value
+        ConfigValue::Null => String::new(),
         ConfigValue::Boolean(b) => {
             if *b {
                 "true".to_string()
@@ -214,13 +736,17 @@ fn format_value(value: &ConfigValue) -> String {
             }
         },
         ConfigValue::Array(a) => {
-            // INI format does not support arrays, so join as a string
-            let items: Vec<String> = a.iter().map(format_value).collect();
-            format!("\"{}\"", items.join(", "))
-        },
-        ConfigValue::Table(t) => {
-            // INI format does not support nested tables, so convert to a string
-            format!("\"{}\"", format!("{:?}", t))
+            // INI format does not support arrays, so join as a string, without
+            // quoting the items themselves
+            let items: Vec<String> = a.iter()
+                .map(|item| match item {
+                    ConfigValue::String(s) => s.clone(),
+                    _ => format_value(item),
+                })
+                .collect();
+            format!("\"{}\"", utils::escape(&items.join(", ")))
         },
+        // Tables are flattened by render_value, and never reach this point
+        ConfigValue::Table(_) => unreachable!("tables are written as dotted keys"),
     }
 }
\ No newline at end of file
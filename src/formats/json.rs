@@ -1,13 +1,12 @@
 //! Implementation of the parser and writer for the JSON format.
 
-use std::fs::{self, File};
-use std::io::Write;
 use std::path::Path;
 use std::collections::HashMap;
 use serde_json::{Value as JsonValue, Map as JsonMap};
 
-use crate::{Config, ConfigError, ConfigValue};
-use crate::include;
+use crate::{Config, ConfigError, ConfigValue, ROOT_ARRAY_KEY};
+use crate::formats;
+use crate::include::{self, IncludeOrder};
 use crate::utils;
 
 /// Parses a JSON file and updates the provided configuration.
@@ -26,40 +25,61 @@ use crate::utils;
 /// * `Ok(())` - If the parsing is successful.
 /// * `Err(ConfigError)` - If an error occurs during parsing.
 pub fn parse_json(config: &mut Config, content: &str, path: &Path) -> Result<(), ConfigError> {
-    let content_to_parse = if content.lines().next().unwrap_or("").starts_with("#!config/") {
-        content.lines().skip(1).collect::<Vec<_>>().join("\n")
-    } else {
-        content.to_string()
-    };
+    let content_to_parse = utils::strip_format_header(content);
 
-    let parsed_json: JsonValue = serde_json::from_str(&content_to_parse)
+    let parsed_json: JsonValue = serde_json::from_str(content_to_parse)
         .map_err(|e| ConfigError::ParseError(format!("JSON parsing error: {}", e)))?;
 
-    if let JsonValue::Object(obj) = parsed_json {
-        if let Some(include_value) = obj.get("include") {
-            process_includes(config, include_value, path)?;
-        }
+    let root_section = config.root_section.clone();
 
-        for (section_name, section_value) in &obj {
-            if section_name == "include" {
-                continue;
+    match parsed_json {
+        JsonValue::Object(obj) => {
+            // Includes are applied before the keys of this file, except include_after
+            for (key, include_value) in &obj {
+                if let Some(directive) = include::parse_include_key(key)
+                    && directive.order != IncludeOrder::After {
+                    process_includes(config, include_value, path, directive.optional)?;
+                }
             }
 
-            match section_value {
-                JsonValue::Object(section_obj) => {
-                    for (key, value) in section_obj {
-                        let config_value = json_value_to_config_value(value);
-                        config.set(section_name, key, config_value);
+            for (section_name, section_value) in &obj {
+                if include::parse_include_key(section_name).is_some() {
+                    continue;
+                }
+
+                match section_value {
+                    JsonValue::Object(section_obj) => {
+                        for (key, value) in section_obj {
+                            let config_value = json_value_to_config_value(value);
+                            config.set_from_file(section_name, key, config_value, path);
+                        }
+                    },
+                    _ => {
+                        let config_value = json_value_to_config_value(section_value);
+                        config.set_from_file(&root_section, section_name, config_value, path);
                     }
-                },
-                _ => {
-                    let config_value = json_value_to_config_value(section_value);
-                    config.set("default", section_name, config_value);
                 }
             }
+
+            // include_after is applied last, overriding the keys of this file
+            for (key, include_value) in &obj {
+                if let Some(directive) = include::parse_include_key(key)
+                    && directive.order == IncludeOrder::After {
+                    process_includes(config, include_value, path, directive.optional)?;
+                }
+            }
+        },
+        // A root array is stored as a whole in the root section, and written
+        // back as the root if it is the one of the loaded file
+        JsonValue::Array(_) => {
+            config.set_from_file(&root_section, ROOT_ARRAY_KEY, json_value_to_config_value(&parsed_json), path);
+            if config.config_file_path.as_deref() == Some(path) {
+                config.root_array = true;
+            }
+        },
+        _ => {
+            return Err(ConfigError::ParseError("The JSON file must have an object or array structure at the root".to_string()));
         }
-    } else {
-        return Err(ConfigError::ParseError("The JSON file must have an object structure at the root".to_string()));
     }
 
     Ok(())
@@ -101,7 +121,7 @@ fn json_value_to_config_value(value: &JsonValue) -> ConfigValue {
             }
             ConfigValue::Table(config_map)
         },
-        JsonValue::Null => ConfigValue::String("".to_string()),
+        JsonValue::Null => ConfigValue::Null,
     }
 }
 
@@ -115,20 +135,22 @@ fn json_value_to_config_value(value: &JsonValue) -> ConfigValue {
 /// * `config` - A mutable reference to the `Config` instance to update.
 /// * `include_value` - The JSON value representing the include directive.
 /// * `base_path` - The base path of the current JSON file.
+/// * `optional` - Whether missing files are skipped instead of reported as errors.
 ///
 /// # Returns
 ///
 /// * `Ok(())` - If the include is processed successfully.
 /// * `Err(ConfigError)` - If an error occurs during processing.
-fn process_includes(config: &mut Config, include_value: &JsonValue, base_path: &Path) -> Result<(), ConfigError> {
+fn process_includes(config: &mut Config, include_value: &JsonValue, base_path: &Path,
+                    optional: bool) -> Result<(), ConfigError> {
     match include_value {
         JsonValue::String(include_path) => {
-            process_single_include(config, include_path, base_path)?;
+            process_single_include(config, include_path, base_path, optional)?;
         },
         JsonValue::Array(includes) => {
             for include_item in includes {
                 if let JsonValue::String(include_path) = include_item {
-                    process_single_include(config, include_path, base_path)?;
+                    process_single_include(config, include_path, base_path, optional)?;
                 } else {
                     return Err(ConfigError::IncludeError(
                         "Includes must be strings".to_string()
@@ -156,23 +178,32 @@ fn process_includes(config: &mut Config, include_value: &JsonValue, base_path: &
 /// * `config` - A mutable reference to the `Config` instance to update.
 /// * `include_path` - The path of the file to include.
 /// * `base_path` - The base path of the current JSON file.
+/// * `optional` - Whether missing files are skipped instead of reported as errors.
 ///
 /// # Returns
 ///
 /// * `Ok(())` - If the include is processed successfully.
 /// * `Err(ConfigError)` - If an error occurs during processing.
-fn process_single_include(config: &mut Config, include_path: &str, base_path: &Path) -> Result<(), ConfigError> {
+fn process_single_include(config: &mut Config, include_path: &str, base_path: &Path,
+                          optional: bool) -> Result<(), ConfigError> {
+    let include_path = include::expand_env_vars(include_path)?;
+    let (format, include_path) = include::split_format_prefix(config, &include_path);
+
     if include_path.contains('*') {
-        include::process_glob_include(config, include_path, base_path)?;
+        include::process_glob_include(config, include_path, base_path, optional, format)?;
     } else {
         let resolved_path = utils::resolve_path(base_path, include_path);
         if resolved_path.exists() {
-            let content = fs::read_to_string(&resolved_path)
-                .map_err(|e| ConfigError::IncludeError(format!("Error reading included file {}: {}",
-                                                               resolved_path.display(), e)))?;
+            // A file already read by this load, possibly through another path, is skipped
+            if !include::mark_included(config, &resolved_path) {
+                return Ok(());
+            }
+            let content = include::read_included_file(config, &resolved_path)?;
 
             let first_line = content.lines().next().unwrap_or("");
-            if first_line.starts_with("#!config/json") {
+            if let Some(format) = format {
+                include::parse_content(config, format, &content, &resolved_path)?;
+            } else if first_line.starts_with("#!config/json") {
                 parse_json(config, &content, &resolved_path)?;
             } else if first_line.starts_with("#!config/yaml") {
                 crate::formats::yaml::parse_yaml(config, &content, &resolved_path)?;
@@ -195,7 +226,7 @@ fn process_single_include(config: &mut Config, include_path: &str, base_path: &P
                     }
                 }
             }
-        } else {
+        } else if !optional {
             return Err(ConfigError::IncludeError(format!("Included file not found: {}",
                                                          resolved_path.display())));
         }
@@ -227,6 +258,7 @@ fn config_value_to_json_value(value: &ConfigValue) -> JsonValue {
             }
         },
         ConfigValue::Boolean(b) => JsonValue::Bool(*b),
+        ConfigValue::Null => JsonValue::Null,
         ConfigValue::Array(arr) => {
             let values: Vec<JsonValue> = arr.iter()
                 .map(config_value_to_json_value)
@@ -235,7 +267,7 @@ fn config_value_to_json_value(value: &ConfigValue) -> JsonValue {
         },
         ConfigValue::Table(table) => {
             let mut json_obj = JsonMap::new();
-            for (k, v) in table {
+            for (k, v) in sorted_entries(table) {
                 json_obj.insert(k.clone(), config_value_to_json_value(v));
             }
             JsonValue::Object(json_obj)
@@ -258,20 +290,48 @@ fn config_value_to_json_value(value: &ConfigValue) -> JsonValue {
 /// * `Ok(())` - If the writing is successful.
 /// * `Err(ConfigError)` - If an error occurs during writing.
 pub fn write_json(config: &Config, path: &Path) -> Result<(), ConfigError> {
-    let mut file = File::create(path).map_err(ConfigError::Io)?;
+    let content = render_json(config)?;
+    utils::write_atomic(path, content.as_bytes()).map_err(ConfigError::Io)
+}
 
-    writeln!(file, "#!config/json").map_err(ConfigError::Io)?;
+/// Serializes the configuration into the JSON format.
+///
+/// JSON has no comment syntax, so field descriptions are never written.
+/// Sections and keys are sorted, so the output is deterministic; it is indented
+/// unless compact output was requested with `Config::set_json_pretty`. The
+/// keys of the root section are written at the document root, unless they
+/// would be read back differently (see `formats::can_flatten_root`): the root
+/// section is then written as an object named after it. A root array of the
+/// loaded file is written back as the document root (see `formats::root_array`).
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance to serialize.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The serialized JSON document.
+/// * `Err(ConfigError)` - If an error occurs during serialization.
+pub(crate) fn render_json(config: &Config) -> Result<String, ConfigError> {
+    // A root array of the loaded file is written back as the root
+    if let Some(items) = formats::root_array(config) {
+        let root = JsonValue::Array(items.iter().map(config_value_to_json_value).collect());
+        return serialize_json(config, &root);
+    }
 
     let mut root_obj = JsonMap::new();
+    let flatten_root = formats::can_flatten_root(config);
 
-    for (section, values) in &config.values {
-        if section == "default" {
-            for (key, value) in values {
+    // Sections and keys are inserted in order, so that the output is sorted
+    // whatever the ordering of the JSON maps
+    for (section, values) in sorted_entries(&config.values) {
+        if *section == config.root_section && flatten_root {
+            for (key, value) in sorted_entries(values) {
                 root_obj.insert(key.clone(), config_value_to_json_value(value));
             }
         } else {
             let mut section_obj = JsonMap::new();
-            for (key, value) in values {
+            for (key, value) in sorted_entries(values) {
                 section_obj.insert(key.clone(), config_value_to_json_value(value));
             }
 
@@ -281,10 +341,24 @@ pub fn write_json(config: &Config, path: &Path) -> Result<(), ConfigError> {
         }
     }
 
-    let json_string = serde_json::to_string_pretty(&JsonValue::Object(root_obj))
+    serialize_json(config, &JsonValue::Object(root_obj))
+}
+
+/// Serializes a JSON document, indented unless compact output was requested.
+fn serialize_json(config: &Config, root: &JsonValue) -> Result<String, ConfigError> {
+    let json_string = if config.json_pretty {
+        serde_json::to_string_pretty(root)
+    } else {
+        serde_json::to_string(root)
+    }
         .map_err(|e| ConfigError::Generic(format!("JSON serialization error: {}", e)))?;
 
-    write!(file, "{}", json_string).map_err(ConfigError::Io)?;
+    Ok(format!("#!config/json\n{}", json_string))
+}
 
-    Ok(())
-}
\ No newline at end of file
+/// Returns the entries of a map sorted by key.
+fn sorted_entries<V>(map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries: Vec<(&String, &V)> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
@@ -7,4 +7,161 @@
 pub mod ini;  // Submodule for INI format handling.
 pub mod toml; // Submodule for TOML format handling.
 pub mod yaml; // Submodule for YAML format handling.
-pub mod json; // Submodule for JSON format handling.
\ No newline at end of file
+pub mod json; // Submodule for JSON format handling.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{Config, ConfigError, ConfigValue};
+use crate::include;
+
+/// Trait for the handlers of custom configuration formats.
+///
+/// A handler is registered on a `Config` with `Config::register_format` and is
+/// then used like a built-in format: for files starting with `#!config/NAME`
+/// or with the `NAME` extension, and when saving with `ConfigFormat::Custom(NAME)`.
+pub trait FormatHandler: Send + Sync {
+    /// Parses the content of a file and updates the configuration.
+    ///
+    /// The content is passed without the `#!config/NAME` header line, if any.
+    /// Values should be stored with `Config::set`.
+    fn parse(&self, config: &mut Config, content: &str, path: &Path) -> Result<(), ConfigError>;
+
+    /// Writes the configuration to a file.
+    fn write(&self, config: &Config, path: &Path) -> Result<(), ConfigError>;
+}
+
+/// Custom format handlers attached to a `Config`, by format name.
+#[derive(Clone, Default)]
+pub(crate) struct FormatRegistry {
+    handlers: HashMap<String, Arc<dyn FormatHandler>>,
+}
+
+impl fmt::Debug for FormatRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+impl FormatRegistry {
+    /// Registers the handler of a format, replacing any previous one.
+    pub(crate) fn register(&mut self, name: &str, handler: Arc<dyn FormatHandler>) {
+        self.handlers.insert(name.to_string(), handler);
+    }
+
+    /// Returns the registered name matching a format name, compared case-insensitively.
+    pub(crate) fn find(&self, name: &str) -> Option<String> {
+        self.handlers.keys().find(|registered| registered.eq_ignore_ascii_case(name)).cloned()
+    }
+
+    /// Returns the handler of a format.
+    pub(crate) fn get(&self, name: &str) -> Result<Arc<dyn FormatHandler>, ConfigError> {
+        self.handlers.get(name)
+            .cloned()
+            .ok_or_else(|| ConfigError::UnsupportedFormat(name.to_string()))
+    }
+}
+
+/// Checks whether the keys of the root section can be written at the document root.
+///
+/// Root keys are read back as sections when their value is a table, as include
+/// directives when they are named like one, and clash with sections of the same
+/// name. In these cases the structured writers (TOML, YAML, JSON) write the root
+/// section as a regular section named after it instead, so that it round-trips
+/// unchanged.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance to serialize.
+///
+/// # Returns
+///
+/// `true` if the root section can be flattened without ambiguity.
+pub(crate) fn can_flatten_root(config: &Config) -> bool {
+    let Some(root_values) = config.values.get(&config.root_section) else {
+        return true;
+    };
+
+    root_values.iter().all(|(key, value)| {
+        !matches!(value, ConfigValue::Table(_))
+            && include::parse_include_key(key).is_none()
+            && config.values.get(key).is_none_or(|section| section.is_empty())
+    })
+}
+
+/// Returns the array to write as the document root, if any.
+///
+/// A JSON array or YAML sequence at the root of the loaded file is stored
+/// under `ROOT_ARRAY_KEY` in the root section. It is written back as the
+/// document root as long as it is the only value of the configuration;
+/// otherwise it is written as a key, like any other.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance to serialize.
+///
+/// # Returns
+///
+/// The items of the root array, or `None` if the document root is a mapping.
+pub(crate) fn root_array(config: &Config) -> Option<&Vec<ConfigValue>> {
+    if !config.root_array || config.values.iter().any(|(section, values)| {
+        *section != config.root_section && !values.is_empty()
+    }) {
+        return None;
+    }
+
+    match config.values.get(&config.root_section) {
+        Some(values) if values.len() == 1 => match values.get(crate::ROOT_ARRAY_KEY) {
+            Some(ConfigValue::Array(items)) => Some(items),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Checks whether an entry of a loaded document is left as it is on save.
+///
+/// Include directives are not stored in the configuration, and the keys last
+/// written by an included file belong to that file: the writers that update
+/// the loaded document in place neither remove, rewrite nor add them, so that
+/// the directives stay and the included values are not copied into the main
+/// file.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance to serialize.
+/// * `flatten_root` - Whether the root section is written at the document root.
+/// * `path` - The path of the entry from the document root: `[key]` for the
+///   top-level entries, `[section, key]` for the keys of a section.
+///
+/// # Returns
+///
+/// `true` if the entry must be kept as it is in the document.
+pub(crate) fn is_kept_entry(config: &Config, flatten_root: bool, path: &[&str]) -> bool {
+    let root_section = config.root_section.as_str();
+
+    match path {
+        [key] if include::parse_include_key(key).is_some() => true,
+        [key] if flatten_root && *key != root_section
+            && config.values.get(*key).is_none_or(|section| section.is_empty()) => {
+            from_other_file(config, root_section, key)
+        },
+        [section] => config.values.get(*section).is_some_and(|values| {
+            !values.is_empty() && values.keys().all(|key| from_other_file(config, section, key))
+        }),
+        [section, key] => {
+            !(flatten_root && *section == root_section) && from_other_file(config, section, key)
+        },
+        _ => false,
+    }
+}
+
+/// Checks whether a key was last written by a file other than the main one.
+fn from_other_file(config: &Config, section: &str, key: &str) -> bool {
+    match config.sources.get(&(section.to_string(), key.to_string())) {
+        Some(crate::ValueSource::File(path)) => config.config_file_path.as_deref() != Some(path.as_path()),
+        _ => false,
+    }
+}
@@ -1,13 +1,13 @@
 //! Implementation of the parser and writer for the TOML format.
 
-use std::fs::{self, File};
-use std::io::Write;
 use std::path::Path;
 use std::collections::HashMap;
 use toml::{Value as TomlValue, Table as TomlTable};
+use toml_edit::DocumentMut;
 
-use crate::{Config, ConfigError, ConfigValue};
-use crate::include;
+use crate::{Config, ConfigError, ConfigValue, ValidationSchema};
+use crate::formats;
+use crate::include::{self, IncludeOrder};
 use crate::utils;
 
 /// Parses a TOML file and updates the provided configuration.
@@ -26,21 +26,21 @@ use crate::utils;
 /// * `Ok(())` - If the parsing is successful.
 /// * `Err(ConfigError)` - If an error occurs during parsing.
 pub fn parse_toml(config: &mut Config, content: &str, path: &Path) -> Result<(), ConfigError> {
-    let content_to_parse = if content.lines().next().unwrap_or("").starts_with("#!config/") {
-        content.lines().skip(1).collect::<Vec<_>>().join("\n")
-    } else {
-        content.to_string()
-    };
+    let content_to_parse = utils::strip_format_header(content);
 
     let parsed_toml: TomlTable = content_to_parse.parse()
         .map_err(|e| ConfigError::ParseError(format!("Error in TOML parsing: {}", e)))?;
 
-    if let Some(include_value) = parsed_toml.get("include") {
-        process_includes(config, include_value, path)?;
+    // Includes are applied before the keys of this file, except include_after
+    for (key, include_value) in &parsed_toml {
+        if let Some(directive) = include::parse_include_key(key)
+            && directive.order != IncludeOrder::After {
+            process_includes(config, include_value, path, directive.optional)?;
+        }
     }
 
     for (section_name, section_value) in &parsed_toml {
-        if section_name == "include" {
+        if include::parse_include_key(section_name).is_some() {
             continue;
         }
 
@@ -48,16 +48,25 @@ pub fn parse_toml(config: &mut Config, content: &str, path: &Path) -> Result<(),
             TomlValue::Table(table) => {
                 for (key, value) in table {
                     let config_value = toml_value_to_config_value(value);
-                    config.set(section_name, key, config_value);
+                    config.set_from_file(section_name, key, config_value, path);
                 }
             },
             _ => {
                 let config_value = toml_value_to_config_value(section_value);
-                config.set("default", section_name, config_value);
+                let root_section = config.root_section.clone();
+                config.set_from_file(&root_section, section_name, config_value, path);
             }
         }
     }
 
+    // include_after is applied last, overriding the keys of this file
+    for (key, include_value) in &parsed_toml {
+        if let Some(directive) = include::parse_include_key(key)
+            && directive.order == IncludeOrder::After {
+            process_includes(config, include_value, path, directive.optional)?;
+        }
+    }
+
     Ok(())
 }
 
@@ -106,20 +115,22 @@ fn toml_value_to_config_value(value: &TomlValue) -> ConfigValue {
 /// * `config` - A mutable reference to the `Config` instance to update.
 /// * `include_value` - The TOML value representing the include directive.
 /// * `base_path` - The base path of the current TOML file.
+/// * `optional` - Whether missing files are skipped instead of reported as errors.
 ///
 /// # Returns
 ///
 /// * `Ok(())` - If the include is processed successfully.
 /// * `Err(ConfigError)` - If an error occurs during processing.
-fn process_includes(config: &mut Config, include_value: &TomlValue, base_path: &Path) -> Result<(), ConfigError> {
+fn process_includes(config: &mut Config, include_value: &TomlValue, base_path: &Path,
+                    optional: bool) -> Result<(), ConfigError> {
     match include_value {
         TomlValue::String(include_path) => {
-            process_single_include(config, include_path, base_path)?;
+            process_single_include(config, include_path, base_path, optional)?;
         },
         TomlValue::Array(includes) => {
             for include_item in includes {
                 if let TomlValue::String(include_path) = include_item {
-                    process_single_include(config, include_path, base_path)?;
+                    process_single_include(config, include_path, base_path, optional)?;
                 } else {
                     return Err(ConfigError::IncludeError(
                         "Includes must be strings".to_string()
@@ -147,22 +158,32 @@ fn process_includes(config: &mut Config, include_value: &TomlValue, base_path: &
 /// * `config` - A mutable reference to the `Config` instance to update.
 /// * `include_path` - The path of the file to include.
 /// * `base_path` - The base path of the current TOML file.
+/// * `optional` - Whether missing files are skipped instead of reported as errors.
 ///
 /// # Returns
 ///
 /// * `Ok(())` - If the include is processed successfully.
 /// * `Err(ConfigError)` - If an error occurs during processing.
-fn process_single_include(config: &mut Config, include_path: &str, base_path: &Path) -> Result<(), ConfigError> {
+fn process_single_include(config: &mut Config, include_path: &str, base_path: &Path,
+                          optional: bool) -> Result<(), ConfigError> {
+    let include_path = include::expand_env_vars(include_path)?;
+    let (format, include_path) = include::split_format_prefix(config, &include_path);
+
     if include_path.contains('*') {
-        include::process_glob_include(config, include_path, base_path)?;
+        include::process_glob_include(config, include_path, base_path, optional, format)?;
     } else {
         let resolved_path = utils::resolve_path(base_path, include_path);
         if resolved_path.exists() {
-            let content = fs::read_to_string(&resolved_path)
-                .map_err(|e| ConfigError::IncludeError(format!("Error reading included file {}: {}", resolved_path.display(), e)))?;
+            // A file already read by this load, possibly through another path, is skipped
+            if !include::mark_included(config, &resolved_path) {
+                return Ok(());
+            }
+            let content = include::read_included_file(config, &resolved_path)?;
 
             let first_line = content.lines().next().unwrap_or("");
-            if first_line.starts_with("#!config/toml") {
+            if let Some(format) = format {
+                include::parse_content(config, format, &content, &resolved_path)?;
+            } else if first_line.starts_with("#!config/toml") {
                 parse_toml(config, &content, &resolved_path)?;
             } else if first_line.starts_with("#!config/ini") {
                 crate::formats::ini::parse_ini(config, &content, &resolved_path)?;
@@ -182,7 +203,7 @@ fn process_single_include(config: &mut Config, include_path: &str, base_path: &P
                     }
                 }
             }
-        } else {
+        } else if !optional {
             return Err(ConfigError::IncludeError(format!("Included file not found: {}", resolved_path.display())));
         }
     }
@@ -208,6 +229,8 @@ fn config_value_to_toml_value(value: &ConfigValue) -> TomlValue {
         ConfigValue::Integer(i) => TomlValue::Integer(*i),
         ConfigValue::Float(f) => TomlValue::Float(*f),
         ConfigValue::Boolean(b) => TomlValue::Boolean(*b),
+        // TOML has no null, so it is written as an empty string
+        ConfigValue::Null => TomlValue::String(String::new()),
         ConfigValue::Array(arr) => {
             let values: Vec<TomlValue> = arr.iter()
                 .map(config_value_to_toml_value)
@@ -239,14 +262,59 @@ fn config_value_to_toml_value(value: &ConfigValue) -> TomlValue {
 /// * `Ok(())` - If the writing is successful.
 /// * `Err(ConfigError)` - If an error occurs during writing.
 pub fn write_toml(config: &Config, path: &Path) -> Result<(), ConfigError> {
-    let mut file = File::create(path).map_err(ConfigError::Io)?;
+    let content = render_toml(config, None, config.is_loaded_file(path))?;
+    utils::write_atomic(path, content.as_bytes()).map_err(ConfigError::Io)
+}
+
+/// Serializes the configuration into the TOML format.
+///
+/// The keys of the root section are written at the document root, unless they
+/// would be read back differently (see `formats::can_flatten_root`): the root
+/// section is then written as a table named after it.
+///
+/// If the configuration was loaded from a TOML file, the values are written
+/// into that document, so its comments and layout are kept: unchanged values
+/// are left as they are, changed values are replaced in place, removed keys
+/// are dropped and new keys are appended.
+///
+/// If a schema is provided, the description of each documented field is
+/// written as a `#` comment above its key (or above its table header, for
+/// table values).
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance to serialize.
+/// * `schema` - An optional `ValidationSchema` providing field descriptions.
+/// * `in_place` - Whether the configuration is saved to the file it was loaded from.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The serialized TOML document.
+/// * `Err(ConfigError)` - If an error occurs during serialization.
+pub(crate) fn render_toml(config: &Config, schema: Option<&ValidationSchema>, in_place: bool) -> Result<String, ConfigError> {
+    let root_table = to_toml_table(config);
+
+    let mut toml_string = match &config.toml_document {
+        Some(source) => update_document(config, source, &root_table, in_place)?,
+        None => toml::to_string_pretty(&root_table)
+            .map_err(|e| ConfigError::Generic(format!("Error in TOML serialization: {}", e)))?,
+    };
+
+    if let Some(schema) = schema {
+        toml_string = add_descriptions(config, &toml_string, schema)?;
+    }
 
-    writeln!(file, "#!config/toml").map_err(ConfigError::Io)?;
+    Ok(format!("#!config/toml\n{}\n", toml_string.trim_end()))
+}
 
+/// Converts the configuration into a TOML table, with the keys of the root
+/// section at the top level and the other sections as tables.
+fn to_toml_table(config: &Config) -> TomlTable {
     let mut root_table = TomlTable::new();
+    let flatten_root = formats::can_flatten_root(config);
 
     for (section, values) in &config.values {
-        if section == "default" {
+        if *section == config.root_section && flatten_root {
             for (key, value) in values {
                 root_table.insert(key.clone(), config_value_to_toml_value(value));
             }
@@ -262,10 +330,162 @@ pub fn write_toml(config: &Config, path: &Path) -> Result<(), ConfigError> {
         }
     }
 
-    let toml_string = toml::to_string_pretty(&root_table)
+    root_table
+}
+
+/// Writes the values of a TOML table into an existing TOML document.
+///
+/// When saving in place, include directives and the keys read from included
+/// files are left as they are (see `formats::is_kept_entry`); otherwise the
+/// directives are dropped and every value is written.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance to serialize.
+/// * `source` - The source of the document, possibly with the format header.
+/// * `root_table` - The values to write.
+/// * `in_place` - Whether the document is saved to the file it was loaded from.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The updated document, without the format header.
+/// * `Err(ConfigError)` - If the document cannot be parsed.
+fn update_document(config: &Config, source: &str, root_table: &TomlTable, in_place: bool) -> Result<String, ConfigError> {
+    let mut document: DocumentMut = utils::strip_format_header(source).parse()
         .map_err(|e| ConfigError::Generic(format!("Error in TOML serialization: {}", e)))?;
 
-    writeln!(file, "{}", toml_string).map_err(ConfigError::Io)?;
+    let flatten_root = formats::can_flatten_root(config);
+    let is_kept = |path: &[&str]| in_place && formats::is_kept_entry(config, flatten_root, path);
+    sync_table(document.as_table_mut(), root_table, &[], &is_kept);
+    Ok(document.to_string())
+}
 
-    Ok(())
-}
\ No newline at end of file
+/// Makes a `toml_edit` table hold the given values, keeping its decorations.
+///
+/// `path` is the path of the table from the document root, and the entries
+/// for which `is_kept` returns `true` are neither removed, rewritten nor added.
+fn sync_table(table: &mut toml_edit::Table, values: &TomlTable, path: &[&str],
+              is_kept: &dyn Fn(&[&str]) -> bool) {
+    let kept = |key: &str| {
+        let mut entry_path = path.to_vec();
+        entry_path.push(key);
+        is_kept(&entry_path)
+    };
+
+    let removed: Vec<String> = table.iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !values.contains_key(key) && !kept(key))
+        .collect();
+    for key in removed {
+        table.remove(&key);
+    }
+
+    for (key, value) in values {
+        if kept(key) {
+            continue;
+        }
+
+        match table.get_mut(key) {
+            Some(toml_edit::Item::Table(sub_table)) if value.is_table() => {
+                let mut sub_path = path.to_vec();
+                sub_path.push(key);
+                sync_table(sub_table, value.as_table().unwrap(), &sub_path, is_kept);
+            },
+            Some(item) => {
+                if !item_equals(item, value) {
+                    let mut new_value = to_edit_value(value);
+                    // Keep the spacing and trailing comment of the replaced value
+                    if let Some(old_value) = item.as_value() {
+                        *new_value.decor_mut() = old_value.decor().clone();
+                    }
+                    *item = toml_edit::Item::Value(new_value);
+                }
+            },
+            None => {
+                table.insert(key, to_edit_item(value));
+            },
+        }
+    }
+}
+
+/// Checks whether a `toml_edit` item holds the given value.
+fn item_equals(item: &toml_edit::Item, value: &TomlValue) -> bool {
+    item.as_value()
+        .and_then(|edit_value| format!("v = {}", edit_value).parse::<TomlTable>().ok())
+        .is_some_and(|parsed| parsed.get("v") == Some(value))
+}
+
+/// Converts a TOML value into a `toml_edit` item, writing tables as `[header]` blocks.
+fn to_edit_item(value: &TomlValue) -> toml_edit::Item {
+    match value {
+        TomlValue::Table(values) => {
+            let mut table = toml_edit::Table::new();
+            for (key, value) in values {
+                table.insert(key, to_edit_item(value));
+            }
+            toml_edit::Item::Table(table)
+        },
+        _ => toml_edit::Item::Value(to_edit_value(value)),
+    }
+}
+
+/// Converts a TOML value into a `toml_edit` value.
+fn to_edit_value(value: &TomlValue) -> toml_edit::Value {
+    let document: DocumentMut = format!("v = {}", value).parse()
+        .expect("a serialized TOML value is valid TOML");
+    document["v"].as_value().cloned().expect("a serialized TOML value is a value")
+}
+
+/// Adds the schema descriptions as comments to a serialized TOML document.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance that was serialized.
+/// * `toml_string` - The serialized TOML document.
+/// * `schema` - The `ValidationSchema` providing field descriptions.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The TOML document with the descriptions as comments.
+/// * `Err(ConfigError)` - If the serialized document cannot be edited.
+fn add_descriptions(config: &Config, toml_string: &str, schema: &ValidationSchema) -> Result<String, ConfigError> {
+    let mut document: DocumentMut = toml_string.parse()
+        .map_err(|e| ConfigError::Generic(format!("Error in TOML serialization: {}", e)))?;
+
+    let flatten_root = formats::can_flatten_root(config);
+    for (section, values) in &config.values {
+        let table = if *section == config.root_section && flatten_root {
+            Some(document.as_table_mut())
+        } else {
+            document.get_mut(section).and_then(|item| item.as_table_mut())
+        };
+
+        let Some(table) = table else {
+            continue;
+        };
+
+        for key in values.keys() {
+            if let Some(description) = schema.field_description(section, key) {
+                describe_key(table, key, &utils::format_comment(description));
+            }
+        }
+    }
+
+    Ok(document.to_string())
+}
+
+/// Prepends a comment to a key of a TOML table.
+///
+/// Table values are rendered as `[header]` blocks, so their comment is placed
+/// above the header; all the other values get the comment above the key.
+fn describe_key(table: &mut toml_edit::Table, key: &str, comment: &str) {
+    if let Some(sub_table) = table.get_mut(key).and_then(|item| item.as_table_mut()) {
+        let decor = sub_table.decor_mut();
+        let prefix = decor.prefix().and_then(|p| p.as_str()).unwrap_or("").to_string();
+        decor.set_prefix(format!("{}{}", prefix, comment));
+    } else if let Some(mut key_mut) = table.key_mut(key) {
+        let decor = key_mut.leaf_decor_mut();
+        let prefix = decor.prefix().and_then(|p| p.as_str()).unwrap_or("").to_string();
+        decor.set_prefix(format!("{}{}", prefix, comment));
+    }
+}
@@ -1,13 +1,13 @@
 //! Implementation of the parser and writer for the YAML format.
 
-use std::fs::{self, File};
-use std::io::Write;
 use std::path::Path;
 use std::collections::HashMap;
+use std::ops::Range;
 use serde_yaml::{Value as YamlValue, Mapping as YamlMapping};
 
-use crate::{Config, ConfigError, ConfigValue};
-use crate::include;
+use crate::{Config, ConfigError, ConfigValue, ValidationSchema, ROOT_ARRAY_KEY};
+use crate::formats;
+use crate::include::{self, IncludeOrder};
 use crate::utils;
 
 /// Parses a YAML file and updates the provided configuration.
@@ -26,44 +26,65 @@ use crate::utils;
 /// * `Ok(())` - If the parsing is successful.
 /// * `Err(ConfigError)` - If an error occurs during parsing.
 pub fn parse_yaml(config: &mut Config, content: &str, path: &Path) -> Result<(), ConfigError> {
-    let content_to_parse = if content.lines().next().unwrap_or("").starts_with("#!config/") {
-        content.lines().skip(1).collect::<Vec<_>>().join("\n")
-    } else {
-        content.to_string()
-    };
+    let content_to_parse = utils::strip_format_header(content);
 
-    let parsed_yaml: YamlValue = serde_yaml::from_str(&content_to_parse)
+    let parsed_yaml: YamlValue = serde_yaml::from_str(content_to_parse)
         .map_err(|e| ConfigError::ParseError(format!("Errore nel parsing YAML: {}", e)))?;
 
-    if let YamlValue::Mapping(mapping) = parsed_yaml {
-        if let Some(include_value) = mapping.get(&YamlValue::String("include".to_string())) {
-            process_includes(config, include_value, path)?;
-        }
+    let root_section = config.root_section.clone();
 
-        for (key_value, value) in &mapping {
-            if let YamlValue::String(section_name) = key_value {
-                if section_name == "include" {
-                    continue;
+    match parsed_yaml {
+        YamlValue::Mapping(mapping) => {
+            // Includes are applied before the keys of this file, except include_after
+            for (key_value, include_value) in &mapping {
+                if let Some(directive) = key_value.as_str().and_then(include::parse_include_key)
+                    && directive.order != IncludeOrder::After {
+                    process_includes(config, include_value, path, directive.optional)?;
                 }
+            }
+
+            for (key_value, value) in &mapping {
+                if let Some(section_name) = yaml_key_to_string(key_value) {
+                    if include::parse_include_key(&section_name).is_some() {
+                        continue;
+                    }
 
-                match value {
-                    YamlValue::Mapping(section_mapping) => {
-                        for (sub_key_value, sub_value) in section_mapping {
-                            if let YamlValue::String(key) = sub_key_value {
-                                let config_value = yaml_value_to_config_value(sub_value);
-                                config.set(section_name, key, config_value);
+                    match value {
+                        YamlValue::Mapping(section_mapping) => {
+                            for (sub_key_value, sub_value) in section_mapping {
+                                if let Some(key) = yaml_key_to_string(sub_key_value) {
+                                    let config_value = yaml_value_to_config_value(sub_value);
+                                    config.set_from_file(&section_name, &key, config_value, path);
+                                }
                             }
+                        },
+                        _ => {
+                            let config_value = yaml_value_to_config_value(value);
+                            config.set_from_file(&root_section, &section_name, config_value, path);
                         }
-                    },
-                    _ => {
-                        let config_value = yaml_value_to_config_value(value);
-                        config.set("default", section_name, config_value);
                     }
                 }
             }
+
+            // include_after is applied last, overriding the keys of this file
+            for (key_value, include_value) in &mapping {
+                if let Some(directive) = key_value.as_str().and_then(include::parse_include_key)
+                    && directive.order == IncludeOrder::After {
+                    process_includes(config, include_value, path, directive.optional)?;
+                }
+            }
+        },
+        // A root sequence is stored as a whole in the root section, and written
+        // back as the root if it is the one of the loaded file
+        YamlValue::Sequence(_) => {
+            config.set_from_file(&root_section, ROOT_ARRAY_KEY, yaml_value_to_config_value(&parsed_yaml), path);
+            if config.config_file_path.as_deref() == Some(path) {
+                config.root_array = true;
+            }
+        },
+        _ => {
+            return Err(ConfigError::ParseError("Il file YAML deve avere una struttura ad oggetto o a sequenza nella root".to_string()));
         }
-    } else {
-        return Err(ConfigError::ParseError("Il file YAML deve avere una struttura ad oggetto nella root".to_string()));
     }
 
     Ok(())
@@ -103,19 +124,38 @@ fn yaml_value_to_config_value(value: &YamlValue) -> ConfigValue {
         YamlValue::Mapping(map) => {
             let mut config_map = HashMap::new();
             for (k, v) in map {
-                if let YamlValue::String(key) = k {
-                    config_map.insert(key.clone(), yaml_value_to_config_value(v));
-                } else {
-                    config_map.insert(k.as_str().unwrap().to_string(), yaml_value_to_config_value(v));
+                if let Some(key) = yaml_key_to_string(k) {
+                    config_map.insert(key, yaml_value_to_config_value(v));
                 }
             }
             ConfigValue::Table(config_map)
         },
-        YamlValue::Null => ConfigValue::String("".to_string()),
+        YamlValue::Null => ConfigValue::Null,
         _ => ConfigValue::String("".to_string()),
     }
 }
 
+/// Converts a YAML mapping key into a string.
+///
+/// Besides string keys, numeric and boolean keys (e.g. `8080: web`) are
+/// accepted and converted to their textual representation.
+///
+/// # Arguments
+///
+/// * `key` - A reference to the YAML key to convert.
+///
+/// # Returns
+///
+/// An `Option<String>` containing the key, or `None` if the key is not a scalar.
+fn yaml_key_to_string(key: &YamlValue) -> Option<String> {
+    match key {
+        YamlValue::String(s) => Some(s.clone()),
+        YamlValue::Number(n) => Some(n.to_string()),
+        YamlValue::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
 /// Processes include directives in a YAML file.
 ///
 /// This function handles both single file includes and arrays of include paths,
@@ -126,20 +166,22 @@ fn yaml_value_to_config_value(value: &YamlValue) -> ConfigValue {
 /// * `config` - A mutable reference to the `Config` instance to update.
 /// * `include_value` - The YAML value representing the include directive.
 /// * `base_path` - The base path of the current YAML file.
+/// * `optional` - Whether missing files are skipped instead of reported as errors.
 ///
 /// # Returns
 ///
 /// * `Ok(())` - If the include is processed successfully.
 /// * `Err(ConfigError)` - If an error occurs during processing.
-fn process_includes(config: &mut Config, include_value: &YamlValue, base_path: &Path) -> Result<(), ConfigError> {
+fn process_includes(config: &mut Config, include_value: &YamlValue, base_path: &Path,
+                    optional: bool) -> Result<(), ConfigError> {
     match include_value {
         YamlValue::String(include_path) => {
-            process_single_include(config, include_path, base_path)?;
+            process_single_include(config, include_path, base_path, optional)?;
         },
         YamlValue::Sequence(includes) => {
             for include_item in includes {
                 if let YamlValue::String(include_path) = include_item {
-                    process_single_include(config, include_path, base_path)?;
+                    process_single_include(config, include_path, base_path, optional)?;
                 } else {
                     return Err(ConfigError::IncludeError(
                         "Le inclusioni devono essere stringhe".to_string()
@@ -167,23 +209,32 @@ fn process_includes(config: &mut Config, include_value: &YamlValue, base_path: &
 /// * `config` - A mutable reference to the `Config` instance to update.
 /// * `include_path` - The path of the file to include.
 /// * `base_path` - The base path of the current YAML file.
+/// * `optional` - Whether missing files are skipped instead of reported as errors.
 ///
 /// # Returns
 ///
 /// * `Ok(())` - If the include is processed successfully.
 /// * `Err(ConfigError)` - If an error occurs during processing.
-fn process_single_include(config: &mut Config, include_path: &str, base_path: &Path) -> Result<(), ConfigError> {
+fn process_single_include(config: &mut Config, include_path: &str, base_path: &Path,
+                          optional: bool) -> Result<(), ConfigError> {
+    let include_path = include::expand_env_vars(include_path)?;
+    let (format, include_path) = include::split_format_prefix(config, &include_path);
+
     if include_path.contains('*') {
-        include::process_glob_include(config, include_path, base_path)?;
+        include::process_glob_include(config, include_path, base_path, optional, format)?;
     } else {
         let resolved_path = utils::resolve_path(base_path, include_path);
         if resolved_path.exists() {
-            let content = fs::read_to_string(&resolved_path)
-                .map_err(|e| ConfigError::IncludeError(format!("Errore di lettura del file incluso {}: {}",
-                                                               resolved_path.display(), e)))?;
+            // A file already read by this load, possibly through another path, is skipped
+            if !include::mark_included(config, &resolved_path) {
+                return Ok(());
+            }
+            let content = include::read_included_file(config, &resolved_path)?;
 
             let first_line = content.lines().next().unwrap_or("");
-            if first_line.starts_with("#!config/yaml") {
+            if let Some(format) = format {
+                include::parse_content(config, format, &content, &resolved_path)?;
+            } else if first_line.starts_with("#!config/yaml") {
                 parse_yaml(config, &content, &resolved_path)?;
             } else if first_line.starts_with("#!config/toml") {
                 crate::formats::toml::parse_toml(config, &content, &resolved_path)?;
@@ -203,7 +254,7 @@ fn process_single_include(config: &mut Config, include_path: &str, base_path: &P
                     }
                 }
             }
-        } else {
+        } else if !optional {
             return Err(ConfigError::IncludeError(format!("File incluso non trovato: {}",
                                                          resolved_path.display())));
         }
@@ -234,6 +285,7 @@ fn config_value_to_yaml_value(value: &ConfigValue) -> YamlValue {
             serde_yaml::to_value(f).unwrap_or(YamlValue::Null)
         },
         ConfigValue::Boolean(b) => YamlValue::Bool(*b),
+        ConfigValue::Null => YamlValue::Null,
         ConfigValue::Array(arr) => {
             let values: Vec<YamlValue> = arr.iter()
                 .map(config_value_to_yaml_value)
@@ -268,42 +320,606 @@ fn config_value_to_yaml_value(value: &ConfigValue) -> YamlValue {
 /// * `Ok(())` - If the writing is successful.
 /// * `Err(ConfigError)` - If an error occurs during writing.
 pub fn write_yaml(config: &Config, path: &Path) -> Result<(), ConfigError> {
-    let mut file = File::create(path).map_err(ConfigError::Io)?;
+    let content = render_yaml(config, None, config.is_loaded_file(path))?;
+    utils::write_atomic(path, content.as_bytes()).map_err(ConfigError::Io)
+}
+
+/// Serializes the configuration into the YAML format.
+///
+/// The document is built one key at a time, so that the description of each
+/// documented field can be written as a `#` comment above its key when a
+/// schema is provided. Arrays of scalars are written in flow style
+/// (`[a, b, c]`) if requested with `Config::set_yaml_flow_arrays`.
+///
+/// If the configuration was loaded from a YAML file, the values are written
+/// into its source instead, so that its comments and layout are kept: see
+/// `update_document`. A root sequence of the loaded file is written back as
+/// the document root (see `formats::root_array`).
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance to serialize.
+/// * `schema` - An optional `ValidationSchema` providing field descriptions.
+/// * `in_place` - Whether the configuration is saved to the file it was loaded from.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The serialized YAML document.
+/// * `Err(ConfigError)` - If an error occurs during serialization.
+pub(crate) fn render_yaml(config: &Config, schema: Option<&ValidationSchema>, in_place: bool) -> Result<String, ConfigError> {
+    // A root sequence of the loaded file is written back as the root
+    if let Some(items) = formats::root_array(config) {
+        let items: Vec<YamlValue> = items.iter().map(config_value_to_yaml_value).collect();
+        return Ok(format!("#!config/yaml\n{}", to_yaml_string(&YamlValue::Sequence(items))?));
+    }
 
-    writeln!(file, "#!config/yaml").map_err(ConfigError::Io)?;
+    if let Some(updated) = config.yaml_document.as_deref()
+        .and_then(|source| update_document(config, source, schema, in_place)) {
+        return Ok(updated);
+    }
 
-    let mut root_mapping = YamlMapping::new();
+    let mut output = String::from("#!config/yaml\n");
+    let flatten_root = formats::can_flatten_root(config);
 
     for (section, values) in &config.values {
-        if section == "default" {
+        if *section == config.root_section && flatten_root {
             for (key, value) in values {
-                root_mapping.insert(
-                    YamlValue::String(key.clone()),
-                    config_value_to_yaml_value(value)
-                );
+                output.push_str(&render_entry(config, section, key, value, schema, "")?);
             }
+        } else if !values.is_empty() {
+            output.push_str(&render_section(config, section, values, schema)?);
+        }
+    }
+
+    // An empty document must still be a mapping to be loaded back
+    if output.lines().count() == 1 {
+        output.push_str("{}\n");
+    }
+
+    Ok(output)
+}
+
+/// Serializes a section as a mapping, with its keys indented below its name.
+fn render_section(config: &Config, section: &str, values: &HashMap<String, ConfigValue>,
+                  schema: Option<&ValidationSchema>) -> Result<String, ConfigError> {
+    let section_key = to_yaml_string(&YamlValue::String(section.to_string()))?;
+    let mut rendered = format!("{}:\n", section_key.trim_end());
+
+    for (key, value) in values {
+        rendered.push_str(&render_entry(config, section, key, value, schema, "  ")?);
+    }
+
+    Ok(rendered)
+}
+
+/// Serializes a single key-value pair, preceded by its description if any.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance holding the output options.
+/// * `section` - The section containing the key.
+/// * `key` - The key to serialize.
+/// * `value` - The value to serialize.
+/// * `schema` - An optional `ValidationSchema` providing field descriptions.
+/// * `indent` - The indentation prepended to every line.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The serialized entry.
+/// * `Err(ConfigError)` - If an error occurs during serialization.
+fn render_entry(config: &Config, section: &str, key: &str, value: &ConfigValue,
+                schema: Option<&ValidationSchema>, indent: &str) -> Result<String, ConfigError> {
+    let description = schema.and_then(|s| s.field_description(section, key));
+    render_key(config, key, value, description, indent)
+}
+
+/// Serializes a key-value pair, preceded by a description if any.
+fn render_key(config: &Config, key: &str, value: &ConfigValue, description: Option<&str>,
+              indent: &str) -> Result<String, ConfigError> {
+    let mut entry = YamlMapping::new();
+    entry.insert(YamlValue::String(key.to_string()), config_value_to_yaml_value(value));
+
+    let mut rendered = String::new();
+    if let Some(description) = description {
+        rendered.push_str(&utils::format_comment(description));
+    }
+    if config.yaml_flow_arrays {
+        render_block_mapping(&mut rendered, &entry, 0, true)?;
+    } else {
+        rendered.push_str(&to_yaml_string(&YamlValue::Mapping(entry))?);
+    }
+
+    Ok(rendered.lines().map(|line| format!("{}{}\n", indent, line)).collect())
+}
+
+/// Writes a mapping in block style, with arrays of scalars in flow style.
+///
+/// serde_yaml only writes block style, so the structure is written here, and
+/// serde_yaml is only used for keys and scalars.
+///
+/// # Arguments
+///
+/// * `output` - The string the lines are appended to.
+/// * `mapping` - The mapping to write.
+/// * `indent` - The indentation of the keys of the mapping, in spaces.
+/// * `flow` - Whether arrays of scalars are written in flow style: only those
+///   outside of any array are, so that an array nested in a block array is
+///   written in block style as well.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the mapping is written.
+/// * `Err(ConfigError)` - If an error occurs during serialization.
+fn render_block_mapping(output: &mut String, mapping: &YamlMapping, indent: usize,
+                        flow: bool) -> Result<(), ConfigError> {
+    for (key, value) in mapping {
+        let key = to_yaml_string(key)?;
+        output.push_str(&format!("{}{}:", " ".repeat(indent), key.trim_end()));
+        render_block_value(output, value, indent, flow)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the value of a key of a block mapping, starting after the colon.
+fn render_block_value(output: &mut String, value: &YamlValue, indent: usize, flow: bool) -> Result<(), ConfigError> {
+    match value {
+        YamlValue::Mapping(mapping) if !mapping.is_empty() => {
+            output.push('\n');
+            render_block_mapping(output, mapping, indent + 2, flow)
+        },
+        YamlValue::Sequence(items) if !items.is_empty() => {
+            let flow_items = if flow { flow_sequence(items)? } else { None };
+            match flow_items {
+                Some(flow_items) => {
+                    output.push_str(&format!(" {}\n", flow_items));
+                    Ok(())
+                },
+                None => {
+                    output.push('\n');
+                    render_block_sequence(output, items, indent + 2)
+                },
+            }
+        },
+        _ => {
+            output.push_str(&format!(" {}\n", scalar_string(value, indent)?));
+            Ok(())
+        },
+    }
+}
+
+/// Writes the items of a sequence in block style, nested arrays included.
+fn render_block_sequence(output: &mut String, items: &[YamlValue], indent: usize) -> Result<(), ConfigError> {
+    let padding = " ".repeat(indent);
+
+    for item in items {
+        let nested = match item {
+            YamlValue::Mapping(mapping) if !mapping.is_empty() => {
+                let mut nested = String::new();
+                render_block_mapping(&mut nested, mapping, indent + 2, false)?;
+                nested
+            },
+            YamlValue::Sequence(items) if !items.is_empty() => {
+                let mut nested = String::new();
+                render_block_sequence(&mut nested, items, indent + 2)?;
+                nested
+            },
+            _ => {
+                output.push_str(&format!("{}- {}\n", padding, scalar_string(item, indent)?));
+                continue;
+            },
+        };
+
+        // The first line of a nested block starts on the line of the dash
+        output.push_str(&format!("{}- {}", padding, &nested[indent + 2..]));
+    }
+
+    Ok(())
+}
+
+/// Formats a sequence of single-line scalars in flow style, e.g. `[a, b, c]`.
+///
+/// Returns `None` if an item is a collection or a multi-line string.
+fn flow_sequence(items: &[YamlValue]) -> Result<Option<String>, ConfigError> {
+    let mut flow_items = Vec::new();
+
+    for item in items {
+        match item {
+            YamlValue::Sequence(_) | YamlValue::Mapping(_) => return Ok(None),
+            YamlValue::String(s) if s.contains('\n') => return Ok(None),
+            _ => {},
+        }
+
+        let scalar = to_yaml_string(item)?.trim_end().to_string();
+        // Plain scalars cannot contain the flow indicators, so such strings are quoted
+        let quoted = scalar.starts_with(['\'', '"']);
+        if !quoted && scalar.contains([',', '[', ']', '{', '}']) {
+            flow_items.push(serde_json::to_string(&scalar)
+                .map_err(|e| ConfigError::Generic(format!("Errore nella serializzazione YAML: {}", e)))?);
         } else {
-            let mut section_mapping = YamlMapping::new();
-            for (key, value) in values {
-                section_mapping.insert(
-                    YamlValue::String(key.clone()),
-                    config_value_to_yaml_value(value)
-                );
+            flow_items.push(scalar);
+        }
+    }
+
+    Ok(Some(format!("[{}]", flow_items.join(", "))))
+}
+
+/// Formats a scalar, or an empty collection, as the value of a key or item.
+///
+/// The continuation lines of a multi-line string are indented by `indent`, the
+/// indentation of the key or item, on top of the indentation of serde_yaml.
+fn scalar_string(value: &YamlValue, indent: usize) -> Result<String, ConfigError> {
+    let rendered = to_yaml_string(value)?;
+    let mut lines = rendered.trim_end().lines();
+    let mut scalar = lines.next().unwrap_or_default().to_string();
+
+    for line in lines {
+        scalar.push('\n');
+        if !line.is_empty() {
+            scalar.push_str(&" ".repeat(indent));
+            scalar.push_str(line);
+        }
+    }
+
+    Ok(scalar)
+}
+
+/// Serializes a YAML value into a string.
+fn to_yaml_string(value: &YamlValue) -> Result<String, ConfigError> {
+    serde_yaml::to_string(value)
+        .map_err(|e| ConfigError::Generic(format!("Errore nella serializzazione YAML: {}", e)))
+}
+
+/// A key of a block mapping of a loaded YAML document, with the lines of its value.
+struct DocumentEntry {
+    /// The key, as loaded.
+    key: String,
+    /// The index of the line of the key.
+    start: usize,
+    /// The index after the last line of the value.
+    end: usize,
+    /// Whether the value starts on the line of the key.
+    inline: bool,
+}
+
+/// The update of the source of a loaded YAML document.
+struct DocumentUpdate<'a> {
+    config: &'a Config,
+    schema: Option<&'a ValidationSchema>,
+    lines: Vec<&'a str>,
+    flatten_root: bool,
+    /// Whether the document is saved to the file it was loaded from.
+    in_place: bool,
+}
+
+/// Writes the values of the configuration into the source of a loaded YAML document.
+///
+/// The document is read line by line as nested block mappings: unchanged
+/// entries are left as they are, with their comments, changed values are
+/// rewritten in place, keeping the trailing comment of single-line values,
+/// removed keys are dropped with the comments above them and new keys are
+/// appended at the end of their mapping. When saving in place, include
+/// directives and the keys read from included files are left as they are (see
+/// `formats::is_kept_entry`); otherwise the directives are dropped and every
+/// value is written.
+///
+/// The updated document is read back and compared with the configuration, so
+/// that layouts which are not handled (e.g. a flow mapping at the root,
+/// anchors or several documents) fall back to writing the document from
+/// scratch.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance to serialize.
+/// * `source` - The source of the document, possibly with the format header.
+/// * `schema` - An optional `ValidationSchema` providing the descriptions of new keys.
+/// * `in_place` - Whether the document is saved to the file it was loaded from.
+///
+/// # Returns
+///
+/// `Some(String)` with the updated document, or `None` if it cannot be updated in place.
+fn update_document(config: &Config, source: &str, schema: Option<&ValidationSchema>,
+                   in_place: bool) -> Option<String> {
+    let source = utils::strip_format_header(source);
+    let YamlValue::Mapping(original) = serde_yaml::from_str(source).ok()? else {
+        return None;
+    };
+
+    let update = DocumentUpdate {
+        config,
+        schema,
+        lines: source.lines().collect(),
+        flatten_root: formats::can_flatten_root(config),
+        in_place,
+    };
+    let mut expected = update.document_values();
+
+    let mut lines = Vec::new();
+    update.sync_mapping(0..update.lines.len(), 0, &original, &expected, &[], &mut lines)?;
+    let document: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+
+    // The document must be read back with the values of the configuration
+    let YamlValue::Mapping(updated) = serde_yaml::from_str(&document).ok()? else {
+        return None;
+    };
+    let mut written = mapping_values(&updated);
+    update.remove_kept(&mut written);
+    update.remove_kept(&mut expected);
+
+    (written == expected).then(|| format!("#!config/yaml\n{}", document))
+}
+
+impl DocumentUpdate<'_> {
+    /// Returns the values of the configuration as laid out at the document root.
+    fn document_values(&self) -> HashMap<String, ConfigValue> {
+        let mut values = HashMap::new();
+
+        for (section, section_values) in &self.config.values {
+            if *section == self.config.root_section && self.flatten_root {
+                values.extend(section_values.iter().map(|(key, value)| (key.clone(), value.clone())));
+            } else if !section_values.is_empty() {
+                values.insert(section.clone(), ConfigValue::Table(section_values.clone()));
             }
+        }
 
-            if !section_mapping.is_empty() {
-                root_mapping.insert(
-                    YamlValue::String(section.clone()),
-                    YamlValue::Mapping(section_mapping)
-                );
+        values
+    }
+
+    /// Checks whether an entry of the document is left as it is.
+    fn is_kept(&self, path: &[&str]) -> bool {
+        self.in_place && formats::is_kept_entry(self.config, self.flatten_root, path)
+    }
+
+    /// Removes the entries left as they are from values laid out at the document root.
+    fn remove_kept(&self, values: &mut HashMap<String, ConfigValue>) {
+        values.retain(|key, _| !self.is_kept(&[key]));
+
+        for (section, value) in values.iter_mut() {
+            if let ConfigValue::Table(section_values) = value {
+                section_values.retain(|key, _| !self.is_kept(&[section, key]));
             }
         }
     }
 
-    let yaml_string = serde_yaml::to_string(&YamlValue::Mapping(root_mapping))
-        .map_err(|e| ConfigError::Generic(format!("Errore nella serializzazione YAML: {}", e)))?;
+    /// Writes the lines of a block mapping, updated with the given values.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The indices of the lines of the mapping.
+    /// * `indent` - The indentation of the keys of the mapping.
+    /// * `original` - The mapping as loaded.
+    /// * `values` - The values the mapping must hold.
+    /// * `path` - The path of the mapping from the document root.
+    /// * `output` - The lines the mapping is written to.
+    ///
+    /// # Returns
+    ///
+    /// `Some(())` if the mapping is written, or `None` if its layout is not handled.
+    fn sync_mapping(&self, lines: Range<usize>, indent: usize, original: &YamlMapping,
+                    values: &HashMap<String, ConfigValue>, path: &[&str],
+                    output: &mut Vec<String>) -> Option<()> {
+        let entries = self.block_entries(lines.clone(), indent)?;
+        let mut cursor = lines.start;
+        let mut append_at = output.len();
 
-    write!(file, "{}", yaml_string).map_err(ConfigError::Io)?;
+        for entry in &entries {
+            let leading = &self.lines[cursor..entry.start];
+            cursor = entry.end;
 
-    Ok(())
-}
\ No newline at end of file
+            let mut entry_path = path.to_vec();
+            entry_path.push(&entry.key);
+            let original_value = original.iter()
+                .find(|(key, _)| yaml_key_to_string(key).as_ref() == Some(&entry.key))
+                .map(|(_, value)| value);
+
+            if self.is_kept(&entry_path) {
+                output.extend(leading.iter().chain(&self.lines[entry.start..entry.end]).map(|line| line.to_string()));
+            } else if let Some(value) = values.get(&entry.key) {
+                output.extend(leading.iter().map(|line| line.to_string()));
+
+                match (value, original_value) {
+                    (_, Some(loaded)) if yaml_value_to_config_value(loaded) == *value => {
+                        output.extend(self.lines[entry.start..entry.end].iter().map(|line| line.to_string()));
+                    },
+                    (ConfigValue::Table(table), Some(YamlValue::Mapping(loaded)))
+                        if !table.is_empty() && !entry.inline && entry.end > entry.start + 1 => {
+                        let child_indent = self.lines[entry.start + 1..entry.end].iter()
+                            .find(|line| is_content(line))
+                            .map(|line| line_indent(line))
+                            .filter(|child_indent| *child_indent > indent)?;
+
+                        output.push(self.lines[entry.start].to_string());
+                        self.sync_mapping(entry.start + 1..entry.end, child_indent, loaded, table, &entry_path, output)?;
+                    },
+                    _ => output.extend(self.rewrite_entry(entry, value, original_value, indent)?),
+                }
+            } else {
+                // Removed keys are dropped with the comments above them
+                continue;
+            }
+
+            append_at = output.len();
+        }
+
+        let mut added: Vec<&String> = values.keys()
+            .filter(|key| entries.iter().all(|entry| entry.key != **key))
+            .filter(|key| {
+                let mut entry_path = path.to_vec();
+                entry_path.push(key);
+                !self.is_kept(&entry_path)
+            })
+            .collect();
+        added.sort();
+
+        let mut new_lines = Vec::new();
+        for key in added {
+            new_lines.extend(self.render_new_entry(path, key, &values[key], indent)?);
+        }
+        output.splice(append_at..append_at, new_lines);
+
+        output.extend(self.lines[cursor..lines.end].iter().map(|line| line.to_string()));
+        Some(())
+    }
+
+    /// Splits the lines of a block mapping into its entries.
+    ///
+    /// The lines of an entry are the line of its key and the following lines
+    /// indented further, or starting a sequence item at the same indentation;
+    /// the blank and comment lines after its last line belong to the next entry.
+    ///
+    /// Returns `None` if a line is not the key of a block mapping at `indent`.
+    fn block_entries(&self, lines: Range<usize>, indent: usize) -> Option<Vec<DocumentEntry>> {
+        let mut entries = Vec::new();
+        let mut index = lines.start;
+
+        while index < lines.end {
+            let line = self.lines[index];
+            if !is_content(line) {
+                index += 1;
+                continue;
+            }
+            if line_indent(line) != indent {
+                return None;
+            }
+
+            let (key, rest) = split_key_line(&line[indent..])?;
+            let inline = !rest.is_empty() && !rest.starts_with('#');
+
+            let mut entry_end = index + 1;
+            for next in index + 1..lines.end {
+                let next_line = self.lines[next];
+                if !is_content(next_line) {
+                    continue;
+                }
+
+                let next_indent = line_indent(next_line);
+                let sequence_item = !inline && next_indent == indent && is_sequence_item(&next_line[indent..]);
+                if next_indent <= indent && !sequence_item {
+                    break;
+                }
+                entry_end = next + 1;
+            }
+
+            entries.push(DocumentEntry { key, start: index, end: entry_end, inline });
+            index = entry_end;
+        }
+
+        Some(entries)
+    }
+
+    /// Writes an entry whose value changed, keeping the trailing comment of a single-line value.
+    fn rewrite_entry(&self, entry: &DocumentEntry, value: &ConfigValue, original: Option<&YamlValue>,
+                     indent: usize) -> Option<Vec<String>> {
+        let rendered = render_key(self.config, &entry.key, value, None, &" ".repeat(indent)).ok()?;
+        let mut lines: Vec<String> = rendered.lines().map(|line| line.to_string()).collect();
+
+        if lines.len() == 1 && entry.end == entry.start + 1
+            && let Some(comment) = original.and_then(|loaded| trailing_comment(self.lines[entry.start], loaded)) {
+            lines[0].push_str(comment);
+        }
+
+        Some(lines)
+    }
+
+    /// Writes a key added to a mapping, preceded by its description if any.
+    fn render_new_entry(&self, path: &[&str], key: &str, value: &ConfigValue, indent: usize) -> Option<Vec<String>> {
+        let description = |section: &str| self.schema.and_then(|schema| schema.field_description(section, key));
+
+        let rendered = match (path, value) {
+            ([], ConfigValue::Table(values)) => render_section(self.config, key, values, self.schema),
+            ([], _) => render_key(self.config, key, value, description(&self.config.root_section), ""),
+            ([section], _) => render_key(self.config, key, value, description(section), &" ".repeat(indent)),
+            _ => render_key(self.config, key, value, None, &" ".repeat(indent)),
+        };
+
+        Some(rendered.ok()?.lines().map(|line| line.to_string()).collect())
+    }
+}
+
+/// Converts a mapping at the document root into values laid out as in the document.
+fn mapping_values(mapping: &YamlMapping) -> HashMap<String, ConfigValue> {
+    mapping.iter()
+        .filter_map(|(key, value)| Some((yaml_key_to_string(key)?, yaml_value_to_config_value(value))))
+        .collect()
+}
+
+/// Checks whether a line holds something other than blanks and a comment.
+fn is_content(line: &str) -> bool {
+    let text = line.trim();
+    !text.is_empty() && !text.starts_with('#')
+}
+
+/// Returns the number of spaces indenting a line.
+fn line_indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Checks whether a line, without its indentation, starts a sequence item.
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Splits the line of a block mapping key, without its indentation.
+///
+/// Plain and quoted keys are handled; complex keys, sequence items, flow
+/// collections and other indicators are not.
+///
+/// # Returns
+///
+/// `Some((key, rest))` with the key as loaded and the text after the colon,
+/// or `None` if the line is not the key of a block mapping.
+fn split_key_line(text: &str) -> Option<(String, &str)> {
+    let key_end = match text.chars().next()? {
+        quote @ ('"' | '\'') => closing_quote(text, quote)? + 1,
+        first if "-?:,[]{}#&*!|>%@`".contains(first) => return None,
+        _ => text.find(": ").or_else(|| text.ends_with(':').then(|| text.len() - 1))?,
+    };
+
+    let rest = text[key_end..].strip_prefix(':')?;
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    let key_text = &text[..key_end];
+    if key_text.contains(" #") {
+        return None;
+    }
+    let key = yaml_key_to_string(&serde_yaml::from_str(key_text).ok()?)?;
+
+    Some((key, rest.trim_start()))
+}
+
+/// Returns the position of the quote closing a quoted scalar at the start of a text.
+fn closing_quote(text: &str, quote: char) -> Option<usize> {
+    let mut chars = text.char_indices().skip(1).peekable();
+
+    while let Some((index, c)) = chars.next() {
+        if quote == '"' && c == '\\' {
+            chars.next();
+        } else if c == quote {
+            // In single-quoted scalars, a quote is escaped by doubling it
+            if quote == '\'' && chars.peek().is_some_and(|(_, next)| *next == '\'') {
+                chars.next();
+            } else {
+                return Some(index);
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns the trailing comment of the line of a key, with the blanks before it.
+///
+/// A `#` preceded by a blank starts the comment only if the text before it
+/// still holds the loaded value, so that `#` inside quoted values is skipped.
+fn trailing_comment<'l>(line: &'l str, value: &YamlValue) -> Option<&'l str> {
+    line.char_indices()
+        .filter(|(index, c)| *c == '#' && line[..*index].ends_with([' ', '\t']))
+        .map(|(index, _)| line[..index].trim_end().len())
+        .find(|code_end| {
+            serde_yaml::from_str::<YamlMapping>(line[..*code_end].trim_start()).ok()
+                .is_some_and(|entry| entry.len() == 1 && entry.values().next() == Some(value))
+        })
+        .map(|code_end| &line[code_end..])
+}
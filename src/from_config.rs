@@ -0,0 +1,69 @@
+// src/from_config.rs
+//! Binding a configuration to a struct
+//!
+//! `FromConfig` builds a value from a whole configuration. It is usually
+//! derived with `#[derive(FromConfig)]` (feature `derive`), mapping each field
+//! to a `section.key` path:
+//!
+//! ```rust,ignore
+//! use confucius::FromConfig;
+//!
+//! #[derive(FromConfig)]
+//! #[config(section = "server")]
+//! struct Server {
+//!     host: String,
+//!     #[config(key = "listen_port", default = "default_port")]
+//!     port: u16,
+//!     #[config(section = "tls", key = "cert_file")]
+//!     cert: Option<String>,
+//! }
+//! ```
+//!
+//! The section of a field defaults to the one of the struct, and its key to the
+//! field name. `Option` fields may be missing; `default` fills a missing field
+//! with `Default::default()`, or with the given function when it names one.
+//! Values are converted with serde, so any `Deserialize` type can be a field.
+
+use serde::de::DeserializeOwned;
+
+use crate::{Config, ConfigError};
+
+/// Types that can be built from a configuration.
+pub trait FromConfig: Sized {
+    /// Builds a value from the configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A reference to the `Config` instance to read.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Self)` - The value built from the configuration.
+    /// * `Err(ConfigError)` - If a required value is missing or has the wrong type.
+    fn from_config(config: &Config) -> Result<Self, ConfigError>;
+}
+
+/// Helpers used by the code generated by `#[derive(FromConfig)]`.
+#[doc(hidden)]
+pub mod __private {
+    use super::*;
+
+    /// Reads a value that must be present.
+    pub fn required_field<T: DeserializeOwned>(config: &Config, section: &str, key: &str) -> Result<T, ConfigError> {
+        optional_field(config, section, key)?.ok_or_else(|| {
+            ConfigError::Generic(format!("Missing configuration value: {}.{}", section, key))
+        })
+    }
+
+    /// Reads a value that may be missing.
+    pub fn optional_field<T: DeserializeOwned>(config: &Config, section: &str, key: &str) -> Result<Option<T>, ConfigError> {
+        let Some(value) = config.get(section, key) else {
+            return Ok(None);
+        };
+
+        serde_json::to_value(value)
+            .and_then(serde_json::from_value)
+            .map(Some)
+            .map_err(|e| ConfigError::TypeError(format!("Invalid value for {}.{}: {}", section, key, e)))
+    }
+}
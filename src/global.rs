@@ -0,0 +1,86 @@
+// src/global.rs
+//! Process-wide shared configuration
+//!
+//! Applications that need the configuration from anywhere can store it here
+//! once at startup with `init` and read it with `get`. Readers receive an
+//! `Arc<Config>` snapshot, while `reload` atomically swaps in a new
+//! configuration that is visible to every subsequent `get`.
+//!
+//! Available only with the `global` feature enabled.
+
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::{Config, ConfigError};
+
+/// The shared configuration, set once by `init`.
+static GLOBAL: OnceLock<RwLock<Arc<Config>>> = OnceLock::new();
+
+/// Initializes the global configuration.
+///
+/// # Arguments
+///
+/// * `config` - The `Config` instance to share.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the global configuration is successfully initialized.
+/// * `Err(ConfigError)` - If the global configuration was already initialized.
+pub fn init(config: Config) -> Result<(), ConfigError> {
+    GLOBAL.set(RwLock::new(Arc::new(config)))
+        .map_err(|_| ConfigError::Generic("Global configuration already initialized".to_string()))
+}
+
+/// Checks whether the global configuration has been initialized.
+pub fn is_initialized() -> bool {
+    GLOBAL.get().is_some()
+}
+
+/// Returns the current global configuration.
+///
+/// The returned `Arc` is a snapshot: it is not affected by later reloads.
+///
+/// # Returns
+///
+/// * `Some(Arc<Config>)` - The current configuration.
+/// * `None` - If the global configuration has not been initialized.
+pub fn try_get() -> Option<Arc<Config>> {
+    GLOBAL.get().map(|lock| {
+        // A poisoned lock still holds a valid Arc, since writers only swap it
+        let current = lock.read().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(&current)
+    })
+}
+
+/// Returns the current global configuration.
+///
+/// The returned `Arc` is a snapshot: it is not affected by later reloads.
+///
+/// # Panics
+///
+/// Panics if the global configuration has not been initialized with `init`.
+pub fn get() -> Arc<Config> {
+    try_get().expect("Global configuration not initialized: call confucius::global::init first")
+}
+
+/// Replaces the global configuration.
+///
+/// The new configuration is swapped in atomically: readers see either the old
+/// or the new configuration, and snapshots already obtained with `get` are
+/// left untouched.
+///
+/// # Arguments
+///
+/// * `config` - The new `Config` instance to share.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the global configuration is successfully replaced.
+/// * `Err(ConfigError)` - If the global configuration has not been initialized.
+pub fn reload(config: Config) -> Result<(), ConfigError> {
+    let lock = GLOBAL.get()
+        .ok_or_else(|| ConfigError::Generic("Global configuration not initialized".to_string()))?;
+
+    let mut current = lock.write().unwrap_or_else(|e| e.into_inner());
+    *current = Arc::new(config);
+    Ok(())
+}
@@ -1,14 +1,94 @@
 // src/include.rs
 //! Management of inclusion directives in configuration files
 
+use std::env;
 use std::fs;
 use std::path::Path;
 use glob::glob;
+use regex::Regex;
 
 use crate::{Config, ConfigError, ConfigFormat};
 use crate::utils;
 use crate::formats;
 
+/// Position at which an include directive is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IncludeOrder {
+    /// `include`: applied where the directive appears. In INI files, keys before
+    /// the directive are overridden by the included file and keys after it
+    /// override it; in the other formats includes are applied before any key.
+    Inline,
+    /// `include_before`: applied before any key of the including file, so that
+    /// the included file only provides defaults.
+    Before,
+    /// `include_after`: applied after every key of the including file, so that
+    /// the included file overrides it.
+    After,
+}
+
+/// An include directive recognized from its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IncludeDirective {
+    /// Position at which the include is applied.
+    pub order: IncludeOrder,
+    /// Whether missing files are skipped instead of reported as errors (`?` suffix).
+    pub optional: bool,
+}
+
+/// Recognizes an include directive from its key.
+///
+/// The accepted keys are `include`, `include_before` and `include_after`, each
+/// optionally followed by `?` to skip missing files (e.g. `include?`).
+///
+/// # Arguments
+///
+/// * `key` - A string slice containing the key to check.
+///
+/// # Returns
+///
+/// An `Option<IncludeDirective>` describing the directive, or `None` if the key
+/// is not an include directive.
+pub(crate) fn parse_include_key(key: &str) -> Option<IncludeDirective> {
+    let (name, optional) = match key.strip_suffix('?') {
+        Some(name) => (name, true),
+        None => (key, false),
+    };
+
+    let order = match name {
+        "include" => IncludeOrder::Inline,
+        "include_before" => IncludeOrder::Before,
+        "include_after" => IncludeOrder::After,
+        _ => return None,
+    };
+
+    Some(IncludeDirective { order, optional })
+}
+
+/// Splits the optional format prefix of an include path, e.g. `ini:conf/base`.
+///
+/// The prefix is recognized only if it names a known format, including the
+/// custom formats registered on the configuration, so that Windows drive
+/// letters such as `C:` are kept in the path.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance holding the custom formats.
+/// * `include_path` - A string slice containing the path of the include directive.
+///
+/// # Returns
+///
+/// A tuple with the format named by the prefix, if any, and the path without it.
+pub(crate) fn split_format_prefix<'a>(config: &Config, include_path: &'a str) -> (Option<ConfigFormat>, &'a str) {
+    if let Some((prefix, path)) = include_path.split_once(':') {
+        match config.resolve_format(prefix) {
+            ConfigFormat::Unknown => {},
+            format => return (Some(format), path),
+        }
+    }
+
+    (None, include_path)
+}
+
 /// Processes a glob pattern inclusion.
 ///
 /// This function resolves a glob pattern relative to a base path and includes
@@ -19,12 +99,15 @@ use crate::formats;
 /// * `config` - A mutable reference to the `Config` instance where the content will be included.
 /// * `glob_pattern` - A string slice representing the glob pattern to match files.
 /// * `base_path` - A reference to a `Path` representing the base path for resolving the glob pattern.
+/// * `optional` - Whether a pattern matching no files is accepted instead of reported as an error.
+/// * `format` - The format of the matching files, if given by a prefix, instead of their header.
 ///
 /// # Returns
 ///
 /// * `Ok(())` - If all matching files are successfully included.
 /// * `Err(ConfigError)` - If an error occurs during glob resolution, file reading, or content inclusion.
-pub fn process_glob_include(config: &mut Config, glob_pattern: &str, base_path: &Path) -> Result<(), ConfigError> {
+pub fn process_glob_include(config: &mut Config, glob_pattern: &str, base_path: &Path,
+                            optional: bool, format: Option<ConfigFormat>) -> Result<(), ConfigError> {
     // Resolve the pattern relative to the base path
     let resolved_pattern = utils::resolve_path(base_path, glob_pattern);
     let pattern_str = resolved_pattern.to_string_lossy();
@@ -40,14 +123,16 @@ pub fn process_glob_include(config: &mut Config, glob_pattern: &str, base_path:
         match entry {
             Ok(path) => {
                 found_any = true;
+                if !mark_included(config, &path) {
+                    continue;
+                }
 
                 // Read the content of the file
-                let content = fs::read_to_string(&path)
-                    .map_err(|e| ConfigError::IncludeError(format!("Error reading included file {}: {}",
-                                                                   path.display(), e)))?;
+                let content = read_included_file(config, &path)?;
 
                 // Determine the format and include the content
-                include_content(config, &content, &path)?;
+                let format = format.clone().unwrap_or_else(|| detect_format_from_content(config, &content));
+                parse_content(config, format, &content, &path)?;
             },
             Err(e) => {
                 return Err(ConfigError::IncludeError(format!("Error expanding glob: {}", e)));
@@ -55,38 +140,111 @@ pub fn process_glob_include(config: &mut Config, glob_pattern: &str, base_path:
         }
     }
 
-    if !found_any {
+    if !found_any && !optional {
         return Err(ConfigError::IncludeError(format!("No files found for pattern: {}", glob_pattern)));
     }
 
     Ok(())
 }
 
-/// Includes the content of a file into the configuration based on its format.
+/// Expands the `${VAR}` tokens of an include path from the environment.
+///
+/// This lets a file include e.g. `overrides/${APP_ENV}.conf` to pick the file
+/// of the current deployment.
+///
+/// # Arguments
+///
+/// * `include_path` - The path or glob pattern of the include directive.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The path with every token replaced by the variable's value.
+/// * `Err(ConfigError)` - If a variable is not defined.
+pub(crate) fn expand_env_vars(include_path: &str) -> Result<String, ConfigError> {
+    let token_regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+
+    let mut expanded = String::with_capacity(include_path.len());
+    let mut last = 0;
+    for cap in token_regex.captures_iter(include_path) {
+        let token = cap.get(0).unwrap();
+        let value = env::var(&cap[1]).map_err(|_| ConfigError::IncludeError(format!(
+            "Undefined environment variable {} in include path: {}", &cap[1], include_path
+        )))?;
+        expanded.push_str(&include_path[last..token.start()]);
+        expanded.push_str(&value);
+        last = token.end();
+    }
+    expanded.push_str(&include_path[last..]);
+
+    Ok(expanded)
+}
+
+/// Reads an included file, within the size limit of the configuration.
 ///
-/// This function determines the format of the file content and parses it
-/// accordingly to include it into the configuration.
+/// The UTF-8 BOM written by some Windows editors is stripped, as it is from
+/// the main file, so that the format header on the first line is recognized.
+///
+/// # Arguments
+///
+/// * `config` - A reference to the `Config` instance being loaded.
+/// * `path` - The path of the included file.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The content of the file.
+/// * `Err(ConfigError)` - If the file is too large or cannot be read.
+pub(crate) fn read_included_file(config: &Config, path: &Path) -> Result<String, ConfigError> {
+    config.check_input_size(path)?;
+    let content = fs::read_to_string(path)
+        .map_err(|e| ConfigError::IncludeError(format!("Error reading included file {}: {}", path.display(), e)))?;
+
+    Ok(match content.strip_prefix('\u{FEFF}') {
+        Some(stripped) => stripped.to_string(),
+        None => content,
+    })
+}
+
+/// Records a file as read by the current load.
+///
+/// Files are compared by their real path, so that two relative paths, or a
+/// symlink and its target, naming the same file are recognized as the same.
+/// Skipping the files already read avoids both duplicated includes and
+/// include cycles.
+///
+/// # Arguments
+///
+/// * `config` - A mutable reference to the `Config` instance being loaded.
+/// * `path` - The path of the file about to be included.
+///
+/// # Returns
+///
+/// `true` if the file was not read yet by the current load, `false` otherwise.
+pub(crate) fn mark_included(config: &mut Config, path: &Path) -> bool {
+    let real_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    config.included_files.insert(real_path)
+}
+
+/// Includes the content of a file into the configuration in the given format.
 ///
 /// # Arguments
 ///
 /// * `config` - A mutable reference to the `Config` instance where the content will be included.
+/// * `format` - The format of the content.
 /// * `content` - A string slice containing the content of the file.
 /// * `path` - A reference to a `Path` representing the file path.
 ///
 /// # Returns
 ///
 /// * `Ok(())` - If the content is successfully included.
-/// * `Err(ConfigError)` - If an error occurs during format detection or parsing.
-fn include_content(config: &mut Config, content: &str, path: &Path) -> Result<(), ConfigError> {
-    // Determine the format from the content
-    let format = detect_format_from_content(content);
-
-    // Parse the content based on the format
+/// * `Err(ConfigError)` - If the format is unknown or an error occurs during parsing.
+pub(crate) fn parse_content(config: &mut Config, format: ConfigFormat, content: &str,
+                            path: &Path) -> Result<(), ConfigError> {
     match format {
         ConfigFormat::Ini => formats::ini::parse_ini(config, content, path)?,
         ConfigFormat::Toml => formats::toml::parse_toml(config, content, path)?,
         ConfigFormat::Yaml => formats::yaml::parse_yaml(config, content, path)?,
         ConfigFormat::Json => formats::json::parse_json(config, content, path)?,
+        ConfigFormat::Custom(name) => config.parse_custom(&name, content, path)?,
         ConfigFormat::Unknown => {
             return Err(ConfigError::IncludeError(format!(
                 // Cast the error for unknown config format file
@@ -102,24 +260,26 @@ fn include_content(config: &mut Config, content: &str, path: &Path) -> Result<()
 /// Detects the format from the content of a file.
 ///
 /// This function reads the first line of the content to determine the format.
-/// If the first line starts with `#!config/FORMAT`, the format is extracted.
+/// If the first line starts with `#!config/FORMAT`, the format is extracted,
+/// including the custom formats registered on the configuration.
 /// If no format is specified, the default format is assumed to be INI.
 ///
 /// # Arguments
 ///
+/// * `config` - A reference to the `Config` instance holding the custom formats.
 /// * `content` - A string slice containing the content of the file.
 ///
 /// # Returns
 ///
 /// The detected configuration format as a `ConfigFormat` enum.
-fn detect_format_from_content(content: &str) -> ConfigFormat {
+fn detect_format_from_content(config: &Config, content: &str) -> ConfigFormat {
     // Read the first line to determine the format
     let first_line = content.lines().next().unwrap_or("");
 
     // If the first line is in the format #!config/FORMAT
     if first_line.starts_with("#!config/") {
         let format_str = first_line.trim_start_matches("#!config/").trim();
-        ConfigFormat::from(format_str)
+        config.resolve_format(format_str)
     } else {
         // If not specified or unknown, return error
         return ConfigFormat::Unknown;
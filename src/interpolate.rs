@@ -0,0 +1,145 @@
+// src/interpolate.rs
+//! Resolution of `${section.key}` references between configuration values
+
+use std::collections::{HashMap, HashSet};
+use regex::Regex;
+
+use crate::{Config, ConfigError, ConfigValue};
+
+type Values = HashMap<String, HashMap<String, ConfigValue>>;
+
+/// Resolves all `${section.key}` references in the string values of a configuration.
+///
+/// References are resolved against the values already loaded in the configuration.
+/// A referenced string value is itself expanded before being substituted, while
+/// non-string values are substituted using their `Display` representation.
+/// Tokens without a dot, such as `${HOME}`, are not references and are kept as
+/// they are.
+///
+/// # Arguments
+///
+/// * `config` - A mutable reference to the `Config` instance to interpolate.
+///
+/// # Returns
+///
+/// * `Ok(())` - If all references are successfully resolved.
+/// * `Err(ConfigError)` - If a reference cannot be resolved or a cycle is detected.
+pub fn interpolate_config(config: &mut Config) -> Result<(), ConfigError> {
+    let reference_regex = Regex::new(r"\$\{([^}]+)\}").unwrap();
+    let snapshot = config.values.clone();
+    let mut resolver = Resolver {
+        values: &snapshot,
+        regex: &reference_regex,
+        resolved: HashMap::new(),
+        visiting: HashSet::new(),
+    };
+
+    let mut interpolated = HashMap::new();
+    for (section, keys) in &snapshot {
+        let mut section_values = HashMap::new();
+        for (key, value) in keys {
+            section_values.insert(key.clone(), resolver.expand_value(value)?);
+        }
+        interpolated.insert(section.clone(), section_values);
+    }
+
+    config.values = interpolated;
+    Ok(())
+}
+
+/// State used while resolving references, with memoization and cycle detection.
+struct Resolver<'a> {
+    /// Snapshot of the configuration values before interpolation.
+    values: &'a Values,
+    /// Regex matching a `${section.key}` reference.
+    regex: &'a Regex,
+    /// Already resolved references.
+    resolved: HashMap<String, String>,
+    /// References currently being resolved, used to detect cycles.
+    visiting: HashSet<String>,
+}
+
+impl Resolver<'_> {
+    /// Expands the references contained in a value, recursing into arrays and tables.
+    fn expand_value(&mut self, value: &ConfigValue) -> Result<ConfigValue, ConfigError> {
+        match value {
+            ConfigValue::String(s) => Ok(ConfigValue::String(self.expand_string(s)?)),
+            ConfigValue::Array(arr) => {
+                let values = arr.iter()
+                    .map(|item| self.expand_value(item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(ConfigValue::Array(values))
+            },
+            ConfigValue::Table(table) => {
+                let mut map = HashMap::new();
+                for (k, v) in table {
+                    map.insert(k.clone(), self.expand_value(v)?);
+                }
+                Ok(ConfigValue::Table(map))
+            },
+            other => Ok(other.clone()),
+        }
+    }
+
+    /// Expands all the references contained in a string.
+    fn expand_string(&mut self, s: &str) -> Result<String, ConfigError> {
+        let mut result = String::with_capacity(s.len());
+        let mut last = 0;
+
+        let references: Vec<(usize, usize, String)> = self.regex.captures_iter(s)
+            .map(|cap| {
+                let whole = cap.get(0).unwrap();
+                (whole.start(), whole.end(), cap[1].trim().to_string())
+            })
+            .collect();
+
+        for (start, end, reference) in references {
+            result.push_str(&s[last..start]);
+            // ${NAME} tokens are left to whoever reads the value, e.g. a shell
+            if reference.contains('.') {
+                result.push_str(&self.resolve_reference(&reference)?);
+            } else {
+                result.push_str(&s[start..end]);
+            }
+            last = end;
+        }
+        result.push_str(&s[last..]);
+
+        Ok(result)
+    }
+
+    /// Resolves a single `section.key` reference to its fully expanded string.
+    fn resolve_reference(&mut self, reference: &str) -> Result<String, ConfigError> {
+        if let Some(value) = self.resolved.get(reference) {
+            return Ok(value.clone());
+        }
+
+        if !self.visiting.insert(reference.to_string()) {
+            return Err(ConfigError::InterpolationError(format!(
+                "Reference cycle detected at ${{{}}}", reference
+            )));
+        }
+
+        let (section, key) = reference.split_once('.').ok_or_else(|| {
+            ConfigError::InterpolationError(format!(
+                "Invalid reference ${{{}}}: expected ${{section.key}}", reference
+            ))
+        })?;
+
+        let value = self.values.get(section)
+            .and_then(|keys| keys.get(key))
+            .ok_or_else(|| ConfigError::InterpolationError(format!(
+                "Unresolved reference ${{{}}}", reference
+            )))?;
+
+        let expanded = match value {
+            ConfigValue::String(s) => self.expand_string(s)?,
+            other => other.to_string(),
+        };
+
+        self.visiting.remove(reference);
+        self.resolved.insert(reference.to_string(), expanded.clone());
+
+        Ok(expanded)
+    }
+}
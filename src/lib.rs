@@ -13,7 +13,7 @@
 //! - Format identification through shebang (#!config/FORMAT)
 //! - Support for comments and text values
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt;
 use std::fs;
@@ -21,11 +21,18 @@ use std::io::{self, BufRead};
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 use serde::{Serialize, Deserialize};
+use std::sync::Arc;
 
 pub mod validation;
+#[cfg(feature = "encryption")]
+pub mod crypto;
+#[cfg(feature = "global")]
+pub mod global;
+mod from_config;
 mod parser;
 mod formats;
 mod include;
+mod interpolate;
 mod utils;
 
 
@@ -35,7 +42,7 @@ mod utils;
 /// configuration files. It includes common formats such as INI, TOML,
 /// YAML, and JSON, as well as an `Unknown` variant for unsupported or
 /// unrecognized formats.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ConfigFormat {
     /// INI format.
     Ini,
@@ -45,6 +52,8 @@ pub enum ConfigFormat {
     Yaml,
     /// JSON format.
     Json,
+    /// Custom format registered with `Config::register_format`, by name.
+    Custom(String),
     /// Unknown or unsupported format.
     Unknown,
 }
@@ -56,6 +65,7 @@ impl fmt::Display for ConfigFormat {
             ConfigFormat::Toml => write!(f, "toml"),
             ConfigFormat::Yaml => write!(f, "yaml"),
             ConfigFormat::Json => write!(f, "json"),
+            ConfigFormat::Custom(name) => write!(f, "{}", name),
             ConfigFormat::Unknown => write!(f, "unknown"),
         }
     }
@@ -73,6 +83,32 @@ impl From<&str> for ConfigFormat {
     }
 }
 
+/// Name of the section holding the keys found at the root of a file, unless
+/// configured otherwise with `Config::set_root_section`.
+pub const DEFAULT_ROOT_SECTION: &str = "default";
+
+/// Default separator between keys and values in INI files.
+pub const DEFAULT_INI_SEPARATOR: char = '=';
+
+/// Default decimal separator of the floats in INI files.
+pub const DEFAULT_DECIMAL_SEPARATOR: char = '.';
+
+/// Default words marking a key as sensitive, see `Config::set_sensitive_keys`.
+pub const DEFAULT_SENSITIVE_KEYS: &[&str] = &["password", "secret", "token", "key"];
+
+/// Text replacing the sensitive values in the `Debug` and `Display` outputs of a `Config`.
+const REDACTED_VALUE: &str = "***";
+
+/// Source recorded for the values loaded by `Config::load_defaults_from_str`.
+const EMBEDDED_DEFAULTS_SOURCE: &str = "<defaults>";
+
+/// Key under which a root-level array (JSON array or YAML sequence) is stored
+/// in the root section.
+///
+/// Saving writes the array back as the document root, as long as it is the
+/// only value of the configuration.
+pub const ROOT_ARRAY_KEY: &str = "items";
+
 /// Errors that can occur during configuration management.
 ///
 /// This enum defines the possible errors that might be encountered
@@ -100,6 +136,26 @@ pub enum ConfigError {
     #[error("File or files include error: {0}")]
     IncludeError(String),
 
+    /// The configuration contains a section outside the known sections.
+    #[error("Unknown configuration section: {0}")]
+    UnknownSection(String),
+
+    /// A value does not have the type required by the operation.
+    #[error("Type error: {0}")]
+    TypeError(String),
+
+    /// A value could not be encrypted or decrypted.
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+
+    /// A dotted path is malformed or cannot be resolved.
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+
+    /// A `${section.key}` reference could not be resolved.
+    #[error("Interpolation error: {0}")]
+    InterpolationError(String),
+
     /// A generic or unknown error occurred.
     #[error("Unknown error: {0}")]
     Generic(String),
@@ -120,6 +176,10 @@ pub enum ConfigError {
 /// * `Boolean` - A boolean value.
 /// * `Array` - A list of configuration values.
 /// * `Table` - A map of string keys to configuration values.
+/// * `Null` - An explicit null value (e.g. `null` in JSON, `~` in YAML).
+///
+/// Values are totally ordered, so arrays of values can be sorted: see the
+/// `Ord` implementation for the order across types.
 #[derive(Debug, Clone)]
 pub enum ConfigValue {
     String(String),
@@ -128,6 +188,7 @@ pub enum ConfigValue {
     Boolean(bool),
     Array(Vec<ConfigValue>),
     Table(HashMap<String, ConfigValue>),
+    Null,
 }
 
 impl ConfigValue {
@@ -203,6 +264,75 @@ impl ConfigValue {
             None
         }
     }
+
+    /// Gets the value of a key, if this value is a table.
+    ///
+    /// Together with `ConfigValue::index`, this method allows chained access
+    /// to nested values without matching on each level.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the value to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a reference to the value if the `ConfigValue` is
+    /// of type `Table` and contains the key, or `None` otherwise.
+    pub fn get(&self, key: &str) -> Option<&ConfigValue> {
+        if let ConfigValue::Table(table) = self {
+            table.get(key)
+        } else {
+            None
+        }
+    }
+
+    /// Gets the element at a position, if this value is an array.
+    ///
+    /// # Arguments
+    ///
+    /// * `i` - The zero-based position of the element to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a reference to the element if the `ConfigValue` is
+    /// of type `Array` and the position is in bounds, or `None` otherwise.
+    pub fn index(&self, i: usize) -> Option<&ConfigValue> {
+        if let ConfigValue::Array(array) = self {
+            array.get(i)
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over the entries of this value, if it is a table.
+    ///
+    /// The entries are visited in no particular order.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing an iterator over the `(key, value)` pairs if the
+    /// `ConfigValue` is of type `Table`, or `None` otherwise.
+    pub fn as_table_iter(&self) -> Option<impl Iterator<Item = (&String, &ConfigValue)>> {
+        if let ConfigValue::Table(table) = self {
+            Some(table.iter())
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over the elements of this value, if it is an array.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing an iterator over the elements, in order, if the
+    /// `ConfigValue` is of type `Array`, or `None` otherwise.
+    pub fn as_array_iter(&self) -> Option<impl Iterator<Item = &ConfigValue>> {
+        if let ConfigValue::Array(array) = self {
+            Some(array.iter())
+        } else {
+            None
+        }
+    }
 }
 
 /// Implements the `Serialize` trait for the `ConfigValue` enum.
@@ -248,6 +378,9 @@ impl Serialize for ConfigValue {
                 seq.end()
             },
 
+            // Serializes a null value.
+            ConfigValue::Null => serializer.serialize_unit(),
+
             // Serializes a table (map) of string keys to `ConfigValue` instances.
             ConfigValue::Table(table) => {
                 use serde::ser::SerializeMap;
@@ -300,7 +433,23 @@ impl<'de> Deserialize<'de> for ConfigValue {
             /// # Returns
             /// * `fmt::Result` - The result of the formatting operation.
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("a string, number, boolean, array, or map")
+                formatter.write_str("a string, number, boolean, array, map, or null")
+            }
+
+            /// Visits a unit value and converts it to `ConfigValue::Null`.
+            fn visit_unit<E>(self) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(ConfigValue::Null)
+            }
+
+            /// Visits an absent optional value and converts it to `ConfigValue::Null`.
+            fn visit_none<E>(self) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(ConfigValue::Null)
             }
 
             /// Visits a boolean value and converts it to `ConfigValue::Boolean`.
@@ -450,7 +599,182 @@ impl fmt::Display for ConfigValue {
                     write!(f, "{}: {}", key, val)?;
                 }
                 write!(f, "}}")
-            }
+            },
+
+            // Formats a null value.
+            ConfigValue::Null => write!(f, "null"),
+        }
+    }
+}
+
+/// Implements a total order for the `ConfigValue` enum.
+///
+/// Values of different types are ordered by type: `Null`, `Boolean`,
+/// `Integer`, `Float`, `String`, `Array`, `Table`, so that an integer always
+/// precedes a float, whatever their values. Values of the same type are ordered
+/// by their content:
+/// - Floats are ordered numerically, with `0.0` equal to `-0.0`; NaN values
+///   are placed after every number if positive, and before if negative.
+/// - Arrays are ordered lexicographically.
+/// - Tables are ordered lexicographically by their entries, sorted by key.
+///
+/// Equality is consistent with this order, so two NaN values with the same
+/// bits are equal.
+impl Ord for ConfigValue {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (self, other) {
+            (ConfigValue::Boolean(a), ConfigValue::Boolean(b)) => a.cmp(b),
+            (ConfigValue::Integer(a), ConfigValue::Integer(b)) => a.cmp(b),
+            (ConfigValue::Float(a), ConfigValue::Float(b)) => a.partial_cmp(b).unwrap_or_else(|| a.total_cmp(b)),
+            (ConfigValue::String(a), ConfigValue::String(b)) => a.cmp(b),
+            (ConfigValue::Array(a), ConfigValue::Array(b)) => a.cmp(b),
+            (ConfigValue::Table(a), ConfigValue::Table(b)) => {
+                let mut a_entries: Vec<_> = a.iter().collect();
+                let mut b_entries: Vec<_> = b.iter().collect();
+                a_entries.sort();
+                b_entries.sort();
+                a_entries.cmp(&b_entries)
+            },
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+}
+
+impl PartialOrd for ConfigValue {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for ConfigValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for ConfigValue {}
+
+impl ConfigValue {
+    /// Returns the position of the type of the value in the order across types.
+    fn type_rank(&self) -> u8 {
+        match self {
+            ConfigValue::Null => 0,
+            ConfigValue::Boolean(_) => 1,
+            ConfigValue::Integer(_) => 2,
+            ConfigValue::Float(_) => 3,
+            ConfigValue::String(_) => 4,
+            ConfigValue::Array(_) => 5,
+            ConfigValue::Table(_) => 6,
+        }
+    }
+}
+
+impl From<&str> for ConfigValue {
+    fn from(s: &str) -> Self {
+        ConfigValue::String(s.to_string())
+    }
+}
+
+impl From<String> for ConfigValue {
+    fn from(s: String) -> Self {
+        ConfigValue::String(s)
+    }
+}
+
+impl From<i64> for ConfigValue {
+    fn from(i: i64) -> Self {
+        ConfigValue::Integer(i)
+    }
+}
+
+impl From<i32> for ConfigValue {
+    fn from(i: i32) -> Self {
+        ConfigValue::Integer(i64::from(i))
+    }
+}
+
+impl From<f64> for ConfigValue {
+    fn from(f: f64) -> Self {
+        ConfigValue::Float(f)
+    }
+}
+
+impl From<bool> for ConfigValue {
+    fn from(b: bool) -> Self {
+        ConfigValue::Boolean(b)
+    }
+}
+
+impl From<Vec<ConfigValue>> for ConfigValue {
+    fn from(a: Vec<ConfigValue>) -> Self {
+        ConfigValue::Array(a)
+    }
+}
+
+impl From<HashMap<String, ConfigValue>> for ConfigValue {
+    fn from(t: HashMap<String, ConfigValue>) -> Self {
+        ConfigValue::Table(t)
+    }
+}
+
+/// Builds a `ConfigValue` from a JSON-like literal.
+///
+/// Tables are written as `{ "key": value, ... }`, arrays as `[value, ...]` and
+/// null as `null`; any other value is converted with `ConfigValue::from`, so
+/// strings, integers, floats, booleans and variables of those types can be used.
+/// Values made of more than one token, such as `-1` or `port + 1`, must be
+/// enclosed in parentheses.
+///
+/// # Example
+///
+/// ```rust
+/// use confucius::{config_value, ConfigValue};
+///
+/// let server = config_value!({
+///     "host": "localhost",
+///     "ports": [80, 443],
+///     "tls": { "enabled": true, "min_version": 1.2 },
+///     "offset": (-1)
+/// });
+///
+/// match server {
+///     ConfigValue::Table(table) => assert_eq!(table["host"], ConfigValue::from("localhost")),
+///     _ => unreachable!(),
+/// }
+/// ```
+#[macro_export]
+macro_rules! config_value {
+    (null) => {
+        $crate::ConfigValue::Null
+    };
+    ([ $($item:tt),* $(,)? ]) => {
+        $crate::ConfigValue::Array(vec![ $( $crate::config_value!($item) ),* ])
+    };
+    ({ $($key:literal : $value:tt),* $(,)? }) => {{
+        #[allow(unused_mut)]
+        let mut table = ::std::collections::HashMap::new();
+        $( table.insert(::std::string::String::from($key), $crate::config_value!($value)); )*
+        $crate::ConfigValue::Table(table)
+    }};
+    ($other:expr) => {
+        $crate::ConfigValue::from($other)
+    };
+}
+
+/// Origin of a configuration value, as returned by `Config::value_source`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueSource {
+    /// The value was read from a configuration file (the main file or an include).
+    File(PathBuf),
+    /// The value was set at runtime through the `Config` API.
+    Runtime,
+}
+
+impl fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueSource::File(path) => write!(f, "{}", path.display()),
+            ValueSource::Runtime => write!(f, "runtime set"),
         }
     }
 }
@@ -468,7 +792,35 @@ impl fmt::Display for ConfigValue {
 /// * `values` - A map of configuration values organized by section and key.
 /// * `format` - The format of the configuration file (e.g., INI, TOML, YAML, JSON).
 /// * `config_file_path` - The path to the loaded configuration file, if any.
-#[derive(Debug, Clone)]
+/// * `ini_auto_type` - Whether unquoted INI values are converted to typed values.
+/// * `ini_separator` - The character separating keys from values in INI files.
+/// * `ini_align` - Whether the values of a section are aligned in a column in INI files.
+/// * `ini_dotted_keys` - Whether dotted INI keys are loaded as nested tables.
+/// * `decimal_separator` - The decimal separator also accepted in INI floats.
+/// * `json_pretty` - Whether JSON files are written indented rather than compact.
+/// * `yaml_flow_arrays` - Whether arrays of scalars are written in flow style in YAML files.
+/// * `nested_sections` - Whether nested tables are loaded as dotted sub-sections.
+/// * `crypto` - The encryptor, decryptor and encrypted keys used for values at rest.
+/// * `known_sections` - The sections accepted when unknown sections are rejected.
+/// * `reject_unknown_sections` - Whether loading fails on sections outside `known_sections`.
+/// * `root_section` - The section holding the keys found at the root of the file.
+/// * `sources` - The origin of the last write of each key.
+/// * `max_input_bytes` - The maximum size of each file read while loading, if limited.
+/// * `max_total_keys` - The maximum number of keys after loading, if limited.
+/// * `config_filename` - The file name searched by `load`, if not `{app_name}.conf`.
+/// * `search_dirs` - The directories searched by `load`, if not the standard ones.
+/// * `toml_document` - The source of the loaded TOML file, whose comments and layout are kept on save.
+/// * `yaml_document` - The source of the loaded YAML file, whose comments and layout are kept on save.
+/// * `root_array` - Whether the document root of the loaded file is an array.
+/// * `format_handlers` - The handlers of the custom formats.
+/// * `locked` - Whether the values are locked against changes.
+/// * `unknown_format_fallback` - The format used to parse files with an unknown `#!config/` header, if any.
+/// * `format_header` - The unknown header of the loaded file, written back on save.
+/// * `included_files` - The real paths of the files read by the last load, each included once.
+/// * `schema` - The schema applied and validated after each load, if any.
+/// * `sensitive_keys` - The words marking a key as sensitive, whose value is masked in `Debug` output.
+/// * `include_base_dir` - The directory includes are resolved from in content not read from a file, if set.
+#[derive(Clone)]
 pub struct Config {
     /// The name of the application (e.g., "galatea").
     app_name: String,
@@ -481,6 +833,91 @@ pub struct Config {
 
     /// The path to the loaded configuration file, if any.
     config_file_path: Option<PathBuf>,
+
+    /// Whether unquoted INI values are automatically converted to typed values.
+    ini_auto_type: bool,
+
+    /// The character separating keys from values in INI files.
+    ini_separator: char,
+
+    /// Whether the values of a section are aligned in a column in INI files.
+    ini_align: bool,
+
+    /// Whether dotted INI keys are loaded as nested tables.
+    ini_dotted_keys: bool,
+
+    /// The decimal separator also accepted in INI floats.
+    decimal_separator: char,
+
+    /// Whether JSON files are written indented rather than compact.
+    json_pretty: bool,
+
+    /// Whether arrays of scalars are written in flow style in YAML files.
+    yaml_flow_arrays: bool,
+
+    /// Whether nested tables are loaded as dotted sub-sections.
+    nested_sections: bool,
+
+    /// Encryption of values at rest.
+    #[cfg(feature = "encryption")]
+    crypto: crypto::ValueCrypto,
+
+    /// Sections accepted when unknown sections are rejected at load time.
+    known_sections: HashSet<String>,
+
+    /// Whether loading fails on sections outside `known_sections`.
+    reject_unknown_sections: bool,
+
+    /// The section holding the keys found at the root of the file.
+    root_section: String,
+
+    /// The origin of the last write of each (section, key).
+    sources: HashMap<(String, String), ValueSource>,
+
+    /// The maximum size in bytes of each file read while loading, if limited.
+    max_input_bytes: Option<usize>,
+
+    /// The maximum number of keys after loading, if limited.
+    max_total_keys: Option<usize>,
+
+    /// The file name searched by `load`, if not `{app_name}.conf`.
+    config_filename: Option<String>,
+
+    /// The directories searched by `load`, if not the standard ones.
+    search_dirs: Option<Vec<PathBuf>>,
+
+    /// The source of the loaded TOML file, whose comments and layout are kept on save.
+    pub(crate) toml_document: Option<String>,
+
+    /// The source of the loaded YAML file, whose comments and layout are kept on save.
+    pub(crate) yaml_document: Option<String>,
+
+    /// Whether the document root of the loaded file is an array, see `ROOT_ARRAY_KEY`.
+    pub(crate) root_array: bool,
+
+    /// The handlers of the custom formats.
+    format_handlers: formats::FormatRegistry,
+
+    /// Whether the values are locked against changes, see `lock`.
+    locked: bool,
+
+    /// The format used to parse files with an unknown `#!config/` header, if any.
+    unknown_format_fallback: Option<ConfigFormat>,
+
+    /// The unknown header of the loaded file, written back on save.
+    format_header: Option<String>,
+
+    /// The real paths of the files read by the last load, each included once.
+    pub(crate) included_files: HashSet<PathBuf>,
+
+    /// The schema applied and validated after each load, see `with_schema`.
+    schema: Option<ValidationSchema>,
+
+    /// The words marking a key as sensitive, whose value is masked in `Debug` output.
+    sensitive_keys: Vec<String>,
+
+    /// The directory includes are resolved from in content not read from a file, if set.
+    include_base_dir: Option<PathBuf>,
 }
 
 impl Config {
@@ -503,6 +940,35 @@ impl Config {
             values: HashMap::new(),
             format: ConfigFormat::Unknown,
             config_file_path: None,
+            ini_auto_type: true,
+            ini_separator: DEFAULT_INI_SEPARATOR,
+            ini_align: false,
+            ini_dotted_keys: false,
+            decimal_separator: DEFAULT_DECIMAL_SEPARATOR,
+            json_pretty: true,
+            yaml_flow_arrays: false,
+            nested_sections: false,
+            #[cfg(feature = "encryption")]
+            crypto: crypto::ValueCrypto::default(),
+            known_sections: HashSet::new(),
+            reject_unknown_sections: false,
+            root_section: DEFAULT_ROOT_SECTION.to_string(),
+            sources: HashMap::new(),
+            max_input_bytes: None,
+            max_total_keys: None,
+            config_filename: None,
+            search_dirs: None,
+            toml_document: None,
+            yaml_document: None,
+            root_array: false,
+            format_handlers: formats::FormatRegistry::default(),
+            locked: false,
+            unknown_format_fallback: None,
+            format_header: None,
+            included_files: HashSet::new(),
+            schema: None,
+            sensitive_keys: DEFAULT_SENSITIVE_KEYS.iter().map(|word| word.to_string()).collect(),
+            include_base_dir: None,
         }
     }
 
@@ -520,10 +986,42 @@ impl Config {
     ///
     /// A mutable reference to the `Config` instance, allowing method chaining.
     pub fn set_format(&mut self, format: ConfigFormat) -> &mut Self {
+        // An unknown header read with another format would not match the content
+        if format != self.format {
+            self.format_header = None;
+        }
         self.format = format;
         self
     }
 
+    /// Sets the format used to parse files with an unknown `#!config/` header.
+    ///
+    /// By default loading a file whose header names an unknown format fails
+    /// with `ConfigError::UnsupportedFormat`. With a fallback, the file is parsed
+    /// in the fallback format instead, and its original header is kept: saving
+    /// in the same format writes it back, so that a tool can load, change and
+    /// save a file without losing a marker it does not understand.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format used for files with an unknown header.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_unknown_format_fallback(&mut self, format: ConfigFormat) -> &mut Self {
+        self.unknown_format_fallback = Some(format);
+        self
+    }
+
+    /// Returns the unknown `#!config/` header of the loaded file, if any.
+    ///
+    /// The header is only kept for files parsed with the format set by
+    /// `set_unknown_format_fallback`, and is forgotten if the format is changed.
+    pub fn format_header(&self) -> Option<&str> {
+        self.format_header.as_deref()
+    }
+
     /// Retrieves the current configuration format.
     ///
     /// This method returns the format of the configuration file currently
@@ -534,176 +1032,1913 @@ impl Config {
     ///
     /// The current configuration format as a `ConfigFormat` enum.
     pub fn get_format(&self) -> ConfigFormat {
-        self.format
+        self.format.clone()
     }
 
-
-    /// Loads the configuration from predefined paths.
+    /// Enables or disables automatic typing of INI values.
     ///
-    /// This method attempts to locate and load a configuration file from a set
-    /// of predefined search paths. It retrieves the current executable's path
-    /// and the username of the current user to construct these paths. If a
-    /// configuration file is found, it is loaded into the `Config` instance.
+    /// When enabled (the default), unquoted INI values are converted to booleans,
+    /// integers or floats whenever possible. When disabled, every INI value is
+    /// loaded as a `ConfigValue::String`, leaving type interpretation to the
+    /// application. This must be set before loading the configuration.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// * `Ok(())` - If a configuration file is successfully loaded.
-    /// * `Err(ConfigError)` - If no configuration file is found or an error occurs.
+    /// * `enabled` - A boolean indicating whether INI values are automatically typed.
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// Returns a `ConfigError` in the following cases:
-    /// - I/O error while retrieving the executable path.
-    /// - Failure to retrieve the current username.
-    /// - No configuration file is found in the predefined paths.
-    pub fn load(&mut self) -> Result<(), ConfigError> {
-        // Retrieve the current executable's path and the current username.
-        let exec_path = env::current_exe().map_err(ConfigError::Io)?;
-        let username = utils::get_current_username()?;
-
-        // Build the search paths for the configuration file.
-        let search_paths = self.build_search_paths(&exec_path, &username);
-
-        // Search for the first available configuration file.
-        for path in search_paths {
-            if path.exists() {
-                return self.load_from_file(&path);
-            }
-        }
-
-        // Return an error if no configuration file is found.
-        Err(ConfigError::ConfigNotFound(self.app_name.clone()))
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_ini_auto_type(&mut self, enabled: bool) -> &mut Self {
+        self.ini_auto_type = enabled;
+        self
     }
 
-    /// Loads the configuration from a specific file.
+    /// Sets the character separating keys from values in INI files.
     ///
-    /// This method reads the content of the specified configuration file,
-    /// determines its format, and parses it into the `Config` structure.
-    /// The file path is stored in the `config_file_path` field of the `Config` instance.
+    /// The default separator is `=`. Setting it to `:` allows loading files
+    /// written as `key: value`, including `include:` directives. The same
+    /// separator is used when the configuration is saved in the INI format.
     ///
     /// # Arguments
     ///
-    /// * `path` - A reference to a `Path` representing the file to load the configuration from.
+    /// * `separator` - The key-value separator character.
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// Returns a `ConfigError` if:
-    /// - The file cannot be read (I/O error).
-    /// - The format of the configuration file is unsupported.
-    /// - Parsing the file content fails.
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_ini_separator(&mut self, separator: char) -> &mut Self {
+        self.ini_separator = separator;
+        self
+    }
+
+    /// Sets whether INI files are written with aligned values.
     ///
-    /// # Examples
+    /// When enabled, the keys of each section and subsection are padded to the
+    /// longest one, so that the separators and the values form a column, as in
+    /// hand-maintained files. This only changes the layout: the file is read
+    /// back the same.
     ///
-    /// ```rust
-    /// // use confucius::Config;
-    /// // let mut config = Config::new("my_app");
-    /// // config.load_from_file(Path::new("/path/to/config.toml")).unwrap();
-    /// ```
-    pub fn load_from_file(&mut self, path: &Path) -> Result<(), ConfigError> {
-        let content = fs::read_to_string(path).map_err(ConfigError::Io)?;
-        self.config_file_path = Some(path.to_path_buf());
-
-        // Determiniamo il formato dal contenuto
-        self.detect_format_from_content(&content)?;
-
-        // Parserizziamo il contenuto in base al formato
-        match self.format {
-            ConfigFormat::Ini => ini::parse_ini(self, &content, path)?,
-            ConfigFormat::Toml => toml::parse_toml(self, &content, path)?,
-            ConfigFormat::Yaml => yaml::parse_yaml(self, &content, path)?,
-            ConfigFormat::Json => json::parse_json(self, &content, path)?,
-            ConfigFormat::Unknown => return Err(ConfigError::UnsupportedFormat("Unknown".to_string())),
-        }
-
-        Ok(())
+    /// # Arguments
+    ///
+    /// * `align` - `true` to align the values, `false` to write `key = value`.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_ini_align(&mut self, align: bool) -> &mut Self {
+        self.ini_align = align;
+        self
     }
 
-    /// Builds a list of potential search paths for the configuration file.
+    /// Sets whether dotted INI keys are loaded as nested tables.
     ///
-    /// This function generates a vector of `PathBuf` objects representing
-    /// the possible locations where the configuration file might be found.
-    /// The paths are constructed based on the application's name, the current
-    /// execution path, and the username of the user.
+    /// When enabled, a key such as `app.name = "demo"` is loaded as the key
+    /// `name` of the table `app`, and tables below a subsection are written as
+    /// dotted keys. By default dotted keys are kept as they are, so existing
+    /// files read `config.get("section", "app.name")` as before, and such
+    /// tables are written as subsections of their own.
     ///
     /// # Arguments
     ///
-    /// * `exec_path` - A reference to a `Path` representing the current executable's path.
-    /// * `username` - A string slice representing the current user's username.
+    /// * `dotted` - `true` to load dotted keys as tables, `false` to keep them flat.
     ///
     /// # Returns
     ///
-    /// A `Vec<PathBuf>` containing the potential search paths for the configuration file.
-    fn build_search_paths(&self, exec_path: &Path, username: &str) -> Vec<PathBuf> {
-        let mut paths = Vec::new();
-        let config_filename = format!("{}.conf", self.app_name);
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_ini_dotted_keys(&mut self, dotted: bool) -> &mut Self {
+        self.ini_dotted_keys = dotted;
+        self
+    }
 
-        // /etc/myapp/myapp.conf
+    /// Sets the decimal separator accepted in INI floats.
+    ///
+    /// Numbers and booleans are parsed independently of the system locale: floats
+    /// use `.` as decimal separator and boolean words are matched ignoring ASCII
+    /// case only. For files written in comma-decimal locales, setting `,` also
+    /// accepts unquoted INI values such as `3,14` as floats. A value with more
+    /// than one separator is kept as a string. Floats are always written with `.`,
+    /// and the other formats follow their own number syntax.
+    ///
+    /// # Arguments
+    ///
+    /// * `separator` - The decimal separator, `.` by default.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_decimal_separator(&mut self, separator: char) -> &mut Self {
+        self.decimal_separator = separator;
+        self
+    }
+
+    /// Sets whether JSON files are written indented or compact.
+    ///
+    /// JSON is written indented by default. Compact output puts the whole
+    /// document on the line after the `#!config/json` header. In both cases
+    /// sections and keys are sorted, so saving the same values always produces
+    /// the same bytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `pretty` - `true` to indent the output, `false` for compact output.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_json_pretty(&mut self, pretty: bool) -> &mut Self {
+        self.json_pretty = pretty;
+        self
+    }
+
+    /// Sets whether arrays of scalars are written in flow style in YAML files.
+    ///
+    /// By default every array is written in block style, one item per line.
+    /// In flow style, arrays whose items are all single-line scalars stay on
+    /// one line, e.g. `allowed_methods: [GET, POST]`. Tables and arrays holding
+    /// collections are still written in block style, and so are the arrays
+    /// inside such an array, directly or in its tables.
+    ///
+    /// # Arguments
+    ///
+    /// * `flow` - `true` to write arrays of scalars in flow style.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_yaml_flow_arrays(&mut self, flow: bool) -> &mut Self {
+        self.yaml_flow_arrays = flow;
+        self
+    }
+
+    /// Sets whether nested tables are loaded as dotted sub-sections.
+    ///
+    /// Sections hold keys and values, so by default a nested object such as
+    /// `{"database": {"pool": {"size": 10}}}` is loaded as the `Table` value
+    /// of the key `pool` in the section `database`. With nested sections, every
+    /// table held by a section becomes a section of its own, named with the
+    /// dotted path of the table: `config.get("database.pool", "size")`. This
+    /// applies to every format, after the file and its includes are parsed.
+    /// Tables inside arrays are kept as values. When saving in TOML, YAML or
+    /// JSON, the sub-sections are written back as nested tables.
+    ///
+    /// # Arguments
+    ///
+    /// * `nested` - `true` to load nested tables as sub-sections.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_nested_sections(&mut self, nested: bool) -> &mut Self {
+        self.nested_sections = nested;
+        self
+    }
+
+    /// Moves the tables held by the sections into dotted sub-sections.
+    ///
+    /// The source of each moved key is the source of the table it came from.
+    fn split_nested_sections(&mut self) {
+        let mut pending: Vec<String> = self.values.keys().cloned().collect();
+
+        while let Some(section) = pending.pop() {
+            let Some(section_values) = self.values.get_mut(&section) else { continue };
+            let table_keys: Vec<String> = section_values.iter()
+                .filter(|(_, value)| matches!(value, ConfigValue::Table(_)))
+                .map(|(key, _)| key.clone())
+                .collect();
+
+            let mut tables = Vec::new();
+            for key in table_keys {
+                if let Some(ConfigValue::Table(table)) = section_values.remove(&key) {
+                    tables.push((key, table));
+                }
+            }
+
+            for (key, table) in tables {
+                let sub_section = format!("{}.{}", section, key);
+                let source = self.sources.remove(&(section.clone(), key));
+                for sub_key in table.keys() {
+                    if let Some(source) = &source {
+                        self.record_source(&sub_section, sub_key, source.clone());
+                    }
+                }
+                self.values.entry(sub_section.clone()).or_default().extend(table);
+                pending.push(sub_section);
+            }
+        }
+    }
+
+    /// Returns a copy with the dotted sub-sections moved back into tables.
+    ///
+    /// This reverses `split_nested_sections` when saving in the structured
+    /// formats, so that the file keeps its nesting. A sub-section whose parent
+    /// holds a value other than a table under its name is kept as it is.
+    fn join_nested_sections(&self) -> Config {
+        let mut joined = self.clone();
+        let mut sections: Vec<String> = joined.values.keys()
+            .filter(|section| section.contains('.'))
+            .cloned()
+            .collect();
+        // The deepest first, so that a.b.c is moved into a.b before a.b into a
+        sections.sort_by_key(|section| std::cmp::Reverse(section.matches('.').count()));
+
+        for section in sections {
+            let Some((parent, key)) = section.rsplit_once('.') else { continue };
+            if parent.is_empty() || key.is_empty() {
+                continue;
+            }
+            let blocked = joined.values.get(parent)
+                .and_then(|values| values.get(key))
+                .is_some_and(|value| !matches!(value, ConfigValue::Table(_)));
+            if blocked {
+                continue;
+            }
+            let Some(values) = joined.values.remove(&section) else { continue };
+
+            // The table comes from a file if all its keys come from it
+            let sources: Vec<ValueSource> = values.keys()
+                .filter_map(|sub_key| joined.sources.remove(&(section.clone(), sub_key.clone())))
+                .collect();
+            if let Some(first) = sources.first()
+                && sources.len() == values.len() && sources.iter().all(|source| source == first) {
+                joined.record_source(parent, key, first.clone());
+            }
+
+            let target = joined.values.entry(parent.to_string()).or_default();
+            match target.get_mut(key) {
+                Some(ConfigValue::Table(table)) => table.extend(values),
+                _ => {
+                    target.insert(key.to_string(), ConfigValue::Table(values));
+                },
+            }
+        }
+
+        joined
+    }
+
+    /// Limits the size of the files read while loading.
+    ///
+    /// The limit applies to the main file and to each included file, and is
+    /// checked before the file is read, so that an oversized file is never
+    /// loaded in memory. This protects services loading untrusted configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_bytes` - The maximum size of a file in bytes.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_max_input_bytes(&mut self, max_bytes: usize) -> &mut Self {
+        self.max_input_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Limits the total number of keys of a loaded configuration.
+    ///
+    /// Every key of every section counts, including keys loaded from included
+    /// files, and so do the entries of nested tables and the elements of
+    /// arrays, at any depth: `ports = [80, 443]` counts as three keys. A file
+    /// going over the limit fails to load with `ConfigError::ParseError`, and
+    /// the configuration is left as it was before the load.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_keys` - The maximum number of keys.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_max_total_keys(&mut self, max_keys: usize) -> &mut Self {
+        self.max_total_keys = Some(max_keys);
+        self
+    }
+
+    /// Sets the file name searched by `load` in the standard directories.
+    ///
+    /// By default `load` looks for `{app_name}.conf`. A file without a
+    /// `#!config/FORMAT` header is parsed according to its extension, so a name
+    /// such as `config.yaml` loads a plain YAML file.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The name of the configuration file, e.g. `myapp.toml`.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_config_filename(&mut self, filename: &str) -> &mut Self {
+        self.config_filename = Some(filename.to_string());
+        self
+    }
+
+    /// Sets the directories searched by `load`, in place of the standard ones.
+    ///
+    /// `load` then looks for the configuration file in each directory, in
+    /// order, instead of `/etc`, `~/.config` and the directory of the
+    /// executable. This suits deployments with their own layout, and tests.
+    ///
+    /// # Arguments
+    ///
+    /// * `dirs` - The directories to search, from the highest to the lowest priority.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_search_dirs(&mut self, dirs: &[PathBuf]) -> &mut Self {
+        self.search_dirs = Some(dirs.to_vec());
+        self
+    }
+
+    /// Registers the handler of a custom format.
+    ///
+    /// Files starting with `#!config/NAME`, or without a header and with the
+    /// `NAME` extension, are then loaded with the handler, and the configuration
+    /// is saved with it when its format is `ConfigFormat::Custom(NAME)`. The
+    /// built-in formats take precedence over custom formats with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the format, e.g. `"kv"`.
+    /// * `handler` - The handler parsing and writing the format.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn register_format<H: FormatHandler + 'static>(&mut self, name: &str, handler: H) -> &mut Self {
+        self.format_handlers.register(name, Arc::new(handler));
+        self
+    }
+
+    /// Resolves a format name into a built-in or registered custom format.
+    pub(crate) fn resolve_format(&self, name: &str) -> ConfigFormat {
+        match ConfigFormat::from(name) {
+            ConfigFormat::Unknown => self.format_handlers.find(name)
+                .map_or(ConfigFormat::Unknown, ConfigFormat::Custom),
+            format => format,
+        }
+    }
+
+    /// Parses content with the handler of a custom format.
+    pub(crate) fn parse_custom(&mut self, name: &str, content: &str, path: &Path) -> Result<(), ConfigError> {
+        let handler = self.format_handlers.get(name)?;
+        handler.parse(self, utils::strip_format_header(content), path)
+    }
+
+    /// Checks a file against the `max_input_bytes` limit before it is read.
+    pub(crate) fn check_input_size(&self, path: &Path) -> Result<(), ConfigError> {
+        let Some(max_bytes) = self.max_input_bytes else {
+            return Ok(());
+        };
+
+        let size = fs::metadata(path).map_err(ConfigError::Io)?.len();
+        if size > max_bytes as u64 {
+            return Err(ConfigError::ParseError(format!(
+                "File {} is too large: {} bytes, limit {} bytes", path.display(), size, max_bytes
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Reads the content of a configuration file, decompressing `.gz` files.
+    fn read_source(&self, path: &Path) -> Result<String, ConfigError> {
+        if path.extension().is_some_and(|ext| ext == "gz") {
+            return self.read_gzip(path);
+        }
+
+        fs::read_to_string(path).map_err(ConfigError::Io)
+    }
+
+    /// Reads a gzip-compressed file, applying the size limit to its decompressed content.
+    #[cfg(feature = "gzip")]
+    fn read_gzip(&self, path: &Path) -> Result<String, ConfigError> {
+        use std::io::Read;
+
+        let file = fs::File::open(path).map_err(ConfigError::Io)?;
+        let limit = self.max_input_bytes.map_or(u64::MAX, |max_bytes| max_bytes as u64 + 1);
+        let mut content = Vec::new();
+        flate2::read::GzDecoder::new(file).take(limit).read_to_end(&mut content)
+            .map_err(ConfigError::Io)?;
+
+        // Stop compressed files expanding beyond the limit
+        if let Some(max_bytes) = self.max_input_bytes
+            && content.len() > max_bytes {
+            return Err(ConfigError::ParseError(format!(
+                "File {} is too large: more than {} bytes decompressed", path.display(), max_bytes
+            )));
+        }
+
+        String::from_utf8(content).map_err(|e| ConfigError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))
+    }
+
+    /// Rejects gzip-compressed files, which require the `gzip` feature.
+    #[cfg(not(feature = "gzip"))]
+    fn read_gzip(&self, path: &Path) -> Result<String, ConfigError> {
+        Err(ConfigError::UnsupportedFormat(format!(
+            "{} is gzip-compressed, which requires the gzip feature", path.display()
+        )))
+    }
+
+    /// Locks the values of the configuration against changes.
+    ///
+    /// This is a guard against code mutating a loaded configuration by mistake.
+    /// Once locked, the fallible methods changing values (`try_set`, `push`,
+    /// `set_path`, `interpolate` and the `load*` methods) return
+    /// `ConfigError::Generic("config is locked")`, while the infallible ones
+    /// (`set`, `set_many`, `set_section`, `get_or_insert_with`, `merge`,
+    /// `map_strings`, `retain` and `get_table_mut`, and the schema methods
+    /// going through them) panic with the same message, in debug and release
+    /// builds alike. Settings such as the format are not locked.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn lock(&mut self) -> &mut Self {
+        self.locked = true;
+        self
+    }
+
+    /// Checks whether the values of the configuration are locked, see `lock`.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Returns an error if the values of the configuration are locked.
+    fn check_unlocked(&self) -> Result<(), ConfigError> {
+        if self.locked {
+            return Err(ConfigError::Generic("config is locked".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Panics if the values of the configuration are locked.
+    fn assert_unlocked(&self) {
+        assert!(!self.locked, "config is locked");
+    }
+
+    /// Sets the name of the root section.
+    ///
+    /// The root section holds the keys that are not part of any section: keys
+    /// before the first `[section]` header in INI files, and scalar keys at the
+    /// root of TOML, YAML and JSON documents. When saving, the keys of the root
+    /// section are written back at the root of the document. The default name
+    /// is `default`. To be used when loading, this must be set before loading
+    /// the configuration.
+    ///
+    /// Setting it before saving designates the section whose keys are written
+    /// at the document root, e.g. a `meta` section built with `set`, while the
+    /// former root section becomes a regular section. If the keys could not be
+    /// read back unchanged from the root (a table value, a key named like an
+    /// include directive or like another section), the section is written as a
+    /// regular section instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the root section.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_root_section(&mut self, name: &str) -> &mut Self {
+        self.root_section = name.to_string();
+        self
+    }
+
+    /// Retrieves the name of the root section.
+    ///
+    /// # Returns
+    ///
+    /// The name of the section holding the keys found at the root of the file.
+    pub fn get_root_section(&self) -> &str {
+        &self.root_section
+    }
+
+    /// Sets the sections accepted when unknown sections are rejected at load time.
+    ///
+    /// This is a lightweight alternative to a full `ValidationSchema` for catching
+    /// typos in section names. It only takes effect when
+    /// `set_reject_unknown_sections(true)` is also called.
+    ///
+    /// # Arguments
+    ///
+    /// * `sections` - The names of the known sections.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_known_sections(&mut self, sections: &[&str]) -> &mut Self {
+        self.known_sections = sections.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Enables or disables rejecting unknown sections at load time.
+    ///
+    /// When enabled, `load_from_file` returns `ConfigError::UnknownSection` if the
+    /// loaded file (including its includes) contains a section that is not
+    /// among the known sections. Only the sections set by the file are
+    /// checked: those set with `set` or by earlier loads are not, nor are the
+    /// sub-sections of a known section made with `set_nested_sections`. The
+    /// root section, holding the keys outside any section, is always accepted.
+    /// A rejected file leaves the configuration as it was before the load.
+    ///
+    /// # Arguments
+    ///
+    /// * `reject` - A boolean indicating whether unknown sections are rejected.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_reject_unknown_sections(&mut self, reject: bool) -> &mut Self {
+        self.reject_unknown_sections = reject;
+        self
+    }
+
+
+    /// Loads the configuration from predefined paths.
+    ///
+    /// This method attempts to locate and load a configuration file from a set
+    /// of predefined search paths. It retrieves the current executable's path
+    /// and the username of the current user to construct these paths, unless
+    /// they are replaced with `set_search_dirs`. If a configuration file is
+    /// found, it is loaded into the `Config` instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If a configuration file is successfully loaded.
+    /// * `Err(ConfigError)` - If no configuration file is found or an error occurs.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` in the following cases:
+    /// - I/O error while retrieving the executable path.
+    /// - Failure to retrieve the current username.
+    /// - No configuration file is found in the predefined paths.
+    pub fn load(&mut self) -> Result<(), ConfigError> {
+        // Retrieve the current executable's path and the current username.
+        let exec_path = env::current_exe().map_err(ConfigError::Io)?;
+        let username = utils::get_current_username()?;
+
+        // Build the search paths for the configuration file.
+        let search_paths = self.build_search_paths(&exec_path, &username);
+
+        // Search for the first available configuration file.
+        for path in search_paths {
+            if path.exists() {
+                return self.load_from_file(&path);
+            }
+        }
+
+        // Return an error if no configuration file is found.
+        Err(ConfigError::ConfigNotFound(self.app_name.clone()))
+    }
+
+    /// Loads the configuration from a specific file.
+    ///
+    /// This method reads the content of the specified configuration file,
+    /// determines its format, and parses it into the `Config` structure.
+    /// The file path is stored in the `config_file_path` field of the `Config` instance.
+    ///
+    /// With the `gzip` feature, a file with the `.gz` extension is decompressed
+    /// before its format is detected. Saving always writes uncompressed content,
+    /// so a configuration loaded from a `.gz` file should be saved elsewhere.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to a `Path` representing the file to load the configuration from.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if:
+    /// - The file cannot be read (I/O error).
+    /// - The format of the configuration file is unsupported.
+    /// - Parsing the file content fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// // use confucius::Config;
+    /// // let mut config = Config::new("my_app");
+    /// // config.load_from_file(Path::new("/path/to/config.toml")).unwrap();
+    /// ```
+    pub fn load_from_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        self.load_atomically(|loaded| {
+            loaded.read_file(path)?;
+            loaded.apply_schema()
+        })
+    }
+
+    /// Loads a file as `load_from_file` does, without applying the schema.
+    fn load_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        self.load_atomically(|loaded| loaded.read_file(path))
+    }
+
+    /// Runs a load on a copy of the configuration, which replaces it on success.
+    ///
+    /// A load failing at any step, including the validation of the schema,
+    /// leaves the configuration unchanged.
+    fn load_atomically<F>(&mut self, load: F) -> Result<(), ConfigError>
+    where
+        F: FnOnce(&mut Config) -> Result<(), ConfigError>,
+    {
+        self.check_unlocked()?;
+
+        let mut loaded = self.clone();
+        load(&mut loaded)?;
+        *self = loaded;
+        Ok(())
+    }
+
+    /// Reads, parses and checks a file, storing its values.
+    fn read_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        self.check_input_size(path)?;
+        let content = self.read_source(path)?;
+        self.config_file_path = Some(path.to_path_buf());
+
+        // Start tracking the files read by this load, so that includes leading
+        // back to the main file are skipped
+        self.included_files.clear();
+        include::mark_included(self, path);
+
+        // Strip the UTF-8 BOM written by some Windows editors
+        let content = content.strip_prefix('\u{FEFF}').unwrap_or(&content);
+
+        // Determiniamo il formato dal contenuto
+        self.detect_format_from_content(content)?;
+
+        // Keep the sections as they were, to check only the ones this file sets
+        let previous = self.reject_unknown_sections.then(|| self.values.clone());
+
+        // Parserizziamo il contenuto in base al formato
+        self.root_array = false;
+        self.parse_in_format(content, path)?;
+
+        // Reject the sections outside the known ones, if requested, before
+        // nested tables are split into sub-sections
+        if let Some(previous) = previous
+            && let Some(section) = self.values.iter()
+                .filter(|(section, values)| previous.get(*section) != Some(*values))
+                .map(|(section, _)| section)
+                .find(|section| **section != self.root_section && !self.known_sections.contains(*section))
+        {
+            return Err(ConfigError::UnknownSection(section.clone()));
+        }
+
+        // Reject configurations with too many keys, if limited, counting the
+        // values nested in tables and arrays
+        if let Some(max_keys) = self.max_total_keys {
+            let total_keys: usize = self.values.values()
+                .flat_map(|keys| keys.values())
+                .map(count_values)
+                .sum();
+            if total_keys > max_keys {
+                return Err(ConfigError::ParseError(format!(
+                    "Too many keys: {}, limit {}", total_keys, max_keys
+                )));
+            }
+        }
+
+        if self.nested_sections {
+            self.split_nested_sections();
+        }
+
+        // Keep the TOML and YAML sources, so that saving preserves their comments and layout
+        self.toml_document = (self.format == ConfigFormat::Toml).then(|| content.to_string());
+        self.yaml_document = (self.format == ConfigFormat::Yaml).then(|| content.to_string());
+
+        // Decrypt the values stored as enc:...
+        #[cfg(feature = "encryption")]
+        crypto::decrypt_config(self)?;
+
+        Ok(())
+    }
+
+    /// Attaches a schema applied on every subsequent load.
+    ///
+    /// After `load`, `load_from_file`, `load_layered` and `load_conf_d`, the
+    /// defaults of the schema are applied and the configuration is validated,
+    /// so that loading a configuration that does not satisfy the schema fails
+    /// with a `ConfigError` listing the validation errors, and leaves the
+    /// configuration as it was before the load. Layered and `.d` loads validate
+    /// the merged result once, after all the files are loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - The schema providing the defaults and the validation rules.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn with_schema(&mut self, schema: ValidationSchema) -> &mut Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Applies the defaults of the attached schema, if any, and validates against it.
+    fn apply_schema(&mut self) -> Result<(), ConfigError> {
+        let Some(schema) = self.schema.take() else {
+            return Ok(());
+        };
+
+        schema.apply_defaults(self);
+        let result = schema.validate(self);
+        self.schema = Some(schema);
+        result.map_err(ConfigError::from)
+    }
+
+    /// Loads built-in default values from a string, such as a file embedded in
+    /// the binary with `include_str!`.
+    ///
+    /// The content is the base layer of the configuration: values loaded
+    /// afterwards, with `load`, `load_from_file` or from the environment,
+    /// override it, while the keys they do not set keep their default. The
+    /// format is given by the `#!config/FORMAT` header, INI if there is none.
+    /// Relative include paths are resolved from the directory set with
+    /// `set_include_base_dir`, or from the current directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - A string slice containing the default configuration.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the defaults are loaded.
+    /// * `Err(ConfigError)` - If the content cannot be parsed or the format is unsupported.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use confucius::Config;
+    ///
+    /// let mut config = Config::new("my_app");
+    /// config.load_defaults_from_str("#!config/ini\n[server]\nport = 8080\n").unwrap();
+    /// assert_eq!(config.get_integer("server", "port", None), Some(8080));
+    /// ```
+    pub fn load_defaults_from_str(&mut self, content: &str) -> Result<(), ConfigError> {
+        self.check_unlocked()?;
+        let content = content.strip_prefix('\u{FEFF}').unwrap_or(content);
+
+        // Track the files read by this load only, so that the files included
+        // by an earlier load are included again
+        self.included_files.clear();
+
+        // The format comes from the header only, not from a previously loaded file
+        let file_path = self.config_file_path.take();
+        let detected = self.detect_format_from_content(content);
+        self.config_file_path = file_path;
+        detected?;
+
+        // Includes are resolved from the directory of the source path
+        let source = self.include_base_dir.as_deref()
+            .unwrap_or(Path::new(""))
+            .join(EMBEDDED_DEFAULTS_SOURCE);
+        self.parse_in_format(content, &source)?;
+        if self.nested_sections {
+            self.split_nested_sections();
+        }
+
+        // Decrypt the values stored as enc:...
+        #[cfg(feature = "encryption")]
+        crypto::decrypt_config(self)?;
+
+        Ok(())
+    }
+
+    /// Sets the directory relative includes are resolved from in content not read from a file.
+    ///
+    /// Includes of a file are resolved from the directory of the file, but
+    /// content loaded from a string with `load_defaults_from_str` has none, so
+    /// its relative includes are resolved from the current directory unless a
+    /// base directory is set. The values loaded from the string are then
+    /// recorded as coming from `<defaults>` in that directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory relative includes are resolved from.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_include_base_dir(&mut self, dir: PathBuf) -> &mut Self {
+        self.include_base_dir = Some(dir);
+        self
+    }
+
+    /// Parses a content in the current format.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - A string slice containing the configuration.
+    /// * `path` - The path the content was read from, used to resolve includes.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the content is parsed.
+    /// * `Err(ConfigError)` - If the content cannot be parsed or the format is unknown.
+    fn parse_in_format(&mut self, content: &str, path: &Path) -> Result<(), ConfigError> {
+        match self.format.clone() {
+            ConfigFormat::Ini => ini::parse_ini(self, content, path),
+            ConfigFormat::Toml => toml::parse_toml(self, content, path),
+            ConfigFormat::Yaml => yaml::parse_yaml(self, content, path),
+            ConfigFormat::Json => json::parse_json(self, content, path),
+            ConfigFormat::Custom(name) => self.parse_custom(&name, content, path),
+            ConfigFormat::Unknown => Err(ConfigError::UnsupportedFormat("Unknown".to_string())),
+        }
+    }
+
+    /// Loads a configuration file, applies the schema defaults and validates it.
+    ///
+    /// This is a shorthand for the usual sequence of `Config::new`,
+    /// `load_from_file`, `apply_defaults` and `validate`.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_name` - A string slice representing the name of the application.
+    /// * `path` - A reference to a `Path` representing the file to load.
+    /// * `schema` - The schema providing the defaults and the validation rules.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Config)` - The loaded configuration, with the defaults applied.
+    /// * `Err(ConfigError)` - If the file cannot be loaded, or if it does not
+    ///   satisfy the schema, in which case the validation errors are reported.
+    pub fn load_and_validate(app_name: &str, path: &Path, schema: &ValidationSchema) -> Result<Config, ConfigError> {
+        let mut config = Config::new(app_name);
+        config.load_from_file(path)?;
+        schema.apply_defaults(&mut config);
+        schema.validate(&config)?;
+        Ok(config)
+    }
+
+    /// Loads several configuration files in order, merging them into this configuration.
+    ///
+    /// Each file is loaded on its own and merged with `Config::merge`, so values
+    /// from later files override those from earlier ones. Files can use different
+    /// formats. After loading, the path and format of the last file are stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The files to load, from the lowest to the highest priority.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if any of the files cannot be loaded, including
+    /// when a file does not exist, in which case none of the files is merged
+    /// and the configuration is left as it was. Use
+    /// `Config::load_layered_optional` to skip missing files instead.
+    pub fn load_layered(&mut self, paths: &[&Path]) -> Result<(), ConfigError> {
+        self.load_layers(paths, false)
+    }
+
+    /// Loads several configuration files in order, skipping those that do not exist.
+    ///
+    /// This method behaves like `Config::load_layered`, except that missing files
+    /// are silently ignored. Files that exist but cannot be parsed still fail.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The files to load, from the lowest to the highest priority.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if any of the existing files cannot be loaded.
+    pub fn load_layered_optional(&mut self, paths: &[&Path]) -> Result<(), ConfigError> {
+        self.load_layers(paths, true)
+    }
+
+    /// Loads and merges each layer, optionally skipping missing files.
+    fn load_layers(&mut self, paths: &[&Path], skip_missing: bool) -> Result<(), ConfigError> {
+        self.load_atomically(|loaded| {
+            for path in paths {
+                if skip_missing && !path.exists() {
+                    continue;
+                }
+
+                let layer = loaded.load_layer(path)?;
+                loaded.format = layer.format;
+                loaded.config_file_path = layer.config_file_path;
+                loaded.root_array = layer.root_array;
+            }
+
+            loaded.apply_schema()
+        })
+    }
+
+    /// Loads a single file with the same settings, starting from no values, and merges it.
+    fn load_layer(&mut self, path: &Path) -> Result<Config, ConfigError> {
+        let mut layer = self.clone();
+        layer.values.clear();
+        layer.sources.clear();
+        layer.load_file(path)?;
+
+        self.merge(&layer);
+        // The keys decrypted from the layer are encrypted again on save
+        #[cfg(feature = "encryption")]
+        self.crypto.clone_from(&layer.crypto);
+        Ok(layer)
+    }
+
+    /// Loads a configuration file followed by the fragments in its `.d` directory.
+    ///
+    /// After loading `base`, every file in the directory named after it with a
+    /// `.d` suffix (e.g. `myapp.conf.d/` for `myapp.conf`) is merged in order of
+    /// file name, as systemd and nginx do, so later fragments override earlier
+    /// ones and all of them override the main file. Only the files with the
+    /// extension of the main file, `.conf` or the extension of a supported
+    /// format (`.ini`, `.toml`, `.yaml`, `.yml`, `.json`) are fragments: hidden
+    /// files, backups such as `10-log.conf~`, `.bak` or `.rpmsave`, files such
+    /// as `README` and subdirectories are skipped. A missing directory is not
+    /// an error. If a fragment cannot be loaded, the configuration is left as
+    /// it was. The path and format of the main file are kept, so that saving
+    /// writes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - A reference to a `Path` representing the main configuration file.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError` if the main file or any fragment cannot be loaded,
+    /// or if the directory cannot be read.
+    pub fn load_conf_d(&mut self, base: &Path) -> Result<(), ConfigError> {
+        self.load_atomically(|loaded| {
+            loaded.read_file(base)?;
+
+            let mut dir_name = base.as_os_str().to_os_string();
+            dir_name.push(".d");
+            let dir = PathBuf::from(dir_name);
+            if !dir.is_dir() {
+                return loaded.apply_schema();
+            }
+
+            // Fragments are the configuration files, not the backups left by editors
+            // and package managers
+            let base_extension = base.extension();
+            let is_fragment = |path: &Path| match path.extension() {
+                Some(extension) => Some(extension) == base_extension
+                    || extension == "conf"
+                    || ConfigFormat::from(extension.to_string_lossy().as_ref()) != ConfigFormat::Unknown,
+                None => false,
+            };
+
+            let mut fragments = Vec::new();
+            for entry in fs::read_dir(&dir).map_err(ConfigError::Io)? {
+                let path = entry.map_err(ConfigError::Io)?.path();
+                let hidden = path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+                if path.is_file() && !hidden && is_fragment(&path) {
+                    fragments.push(path);
+                }
+            }
+            fragments.sort();
+
+            for fragment in &fragments {
+                loaded.load_layer(fragment)?;
+            }
+
+            loaded.apply_schema()
+        })
+    }
+
+    /// Merges the values of another configuration into this one.
+    ///
+    /// Values from `other` override the existing ones. Tables present in both
+    /// configurations are merged key by key, so that nested values not set in
+    /// `other` are preserved; any other value is replaced as a whole.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - A reference to the `Config` whose values take precedence.
+    pub fn merge(&mut self, other: &Config) {
+        self.assert_unlocked();
+        for (section, keys) in &other.values {
+            let target = self.values.entry(section.clone()).or_default();
+            for (key, value) in keys {
+                match target.get_mut(key) {
+                    Some(existing) => merge_value(existing, value),
+                    None => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+
+            for key in keys.keys() {
+                let source = other.value_source(section, key).unwrap_or(ValueSource::Runtime);
+                self.record_source(section, key, source);
+            }
+        }
+    }
+
+    /// Builds a list of potential search paths for the configuration file.
+    ///
+    /// This function generates a vector of `PathBuf` objects representing
+    /// the possible locations where the configuration file might be found.
+    /// The paths are constructed based on the application's name, the current
+    /// execution path, and the username of the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `exec_path` - A reference to a `Path` representing the current executable's path.
+    /// * `username` - A string slice representing the current user's username.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<PathBuf>` containing the potential search paths for the configuration file.
+    fn build_search_paths(&self, exec_path: &Path, username: &str) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        let config_filename = self.config_filename.clone()
+            .unwrap_or_else(|| format!("{}.conf", self.app_name));
+
+        // Directories set with set_search_dirs replace the standard ones
+        if let Some(dirs) = &self.search_dirs {
+            return dirs.iter().map(|dir| dir.join(&config_filename)).collect();
+        }
+
+        // /etc/myapp/myapp.conf
         paths.push(PathBuf::from(format!("/etc/{}/{}", self.app_name, config_filename)));
 
-        // /etc/myapp.conf
-        paths.push(PathBuf::from(format!("/etc/{}", config_filename)));
+        // /etc/myapp.conf
+        paths.push(PathBuf::from(format!("/etc/{}", config_filename)));
+
+        // /opt/etc/myapp.conf
+        paths.push(PathBuf::from(format!("/opt/etc/{}", config_filename)));
+
+        // ~/.config/myapp/myapp.conf
+        paths.push(PathBuf::from(format!("/home/{}/.config/{}/{}", username, self.app_name, config_filename)));
+
+        // ~/.config/myapp.conf
+        paths.push(PathBuf::from(format!("/home/{}/.config/{}", username, config_filename)));
+
+        // Path of executable file
+        if let Some(exec_dir) = exec_path.parent() {
+            paths.push(exec_dir.join(&config_filename));
+        }
+
+        paths
+    }
+
+    /// Detects the configuration format from the file content.
+    ///
+    /// This function reads the first line of the provided content to determine
+    /// the configuration format. If the first line starts with `#!config/FORMAT`,
+    /// the format is extracted and set in the `format` field of the `Config` struct.
+    /// If the format is unknown or unsupported, the fallback format is used if
+    /// set, keeping the header in `format_header`, or an error is returned. If no format
+    /// is specified, it is taken from the extension of the file being loaded,
+    /// falling back to INI.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - A string slice containing the content of the configuration file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the format is successfully detected and set.
+    /// * `Err(ConfigError)` - If the format is unknown or unsupported.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ConfigError::UnsupportedFormat` if the format specified in the
+    /// content is not recognized.
+    fn detect_format_from_content(&mut self, content: &str) -> Result<(), ConfigError> {
+        // Read the first line to determine the format.
+        let first_line = content.lines().next().unwrap_or("");
+        self.format_header = None;
+
+        // If the first line is in the format #!config/FORMAT
+        if first_line.starts_with("#!config/") {
+            let format_str = first_line.trim_start_matches("#!config/").trim();
+            self.format = self.resolve_format(format_str);
+
+            if self.format == ConfigFormat::Unknown {
+                let Some(fallback) = self.unknown_format_fallback.clone() else {
+                    return Err(ConfigError::UnsupportedFormat(format_str.to_string()));
+                };
+                self.format = fallback;
+                self.format_header = Some(first_line.trim_end().to_string());
+            }
+        } else {
+            // Otherwise use the extension of the file, assuming INI if unknown.
+            self.format = self.config_file_path.as_ref()
+                .and_then(|path| path.extension())
+                .and_then(|ext| ext.to_str())
+                .map(|ext| self.resolve_format(ext))
+                .filter(|format| *format != ConfigFormat::Unknown)
+                .unwrap_or(ConfigFormat::Ini);
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves a value from the configuration.
+    ///
+    /// This method looks up a value in the configuration by its section and key.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a reference to the `ConfigValue` if the value exists,
+    /// or `None` if the section or key is not found.
+    pub fn get(&self, section: &str, key: &str) -> Option<&ConfigValue> {
+        self.values.get(section).and_then(|section_map| section_map.get(key))
+    }
+
+    /// Retrieves a copy of a value from the configuration.
+    ///
+    /// Unlike `get`, the returned value does not borrow the configuration, so it
+    /// can be stored or used after the configuration is changed or dropped. The
+    /// typed getters such as `get_string` and `get_integer` already return owned
+    /// values.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a clone of the `ConfigValue` if the value exists,
+    /// or `None` if the section or key is not found.
+    pub fn get_owned(&self, section: &str, key: &str) -> Option<ConfigValue> {
+        self.get(section, key).cloned()
+    }
+
+    /// Retrieves a copy of a value from the configuration, or a default value.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    /// * `default` - The value returned if the key is missing.
+    ///
+    /// # Returns
+    ///
+    /// A clone of the stored `ConfigValue`, or `default` if the section or key
+    /// is not found.
+    pub fn get_or(&self, section: &str, key: &str, default: ConfigValue) -> ConfigValue {
+        self.get_owned(section, key).unwrap_or(default)
+    }
+
+    /// Retrieves a copy of an array value from the configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a clone of the items, or `None` if the key is
+    /// missing or does not hold an array.
+    pub fn get_array_owned(&self, section: &str, key: &str) -> Option<Vec<ConfigValue>> {
+        match self.get(section, key) {
+            Some(ConfigValue::Array(items)) => Some(items.clone()),
+            _ => None,
+        }
+    }
+
+    /// Retrieves a copy of a table value from the configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a clone of the entries, or `None` if the key is
+    /// missing or does not hold a table.
+    pub fn get_table_owned(&self, section: &str, key: &str) -> Option<HashMap<String, ConfigValue>> {
+        match self.get(section, key) {
+            Some(ConfigValue::Table(entries)) => Some(entries.clone()),
+            _ => None,
+        }
+    }
+
+    /// Finds a key in every section of the configuration.
+    ///
+    /// This is useful for auditing, e.g. to list every `password` regardless of
+    /// its section. Only top-level keys are matched, not keys of nested tables.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec` of `(section, value)` pairs for each section containing the key,
+    /// sorted by section name.
+    pub fn find_key(&self, key: &str) -> Vec<(&str, &ConfigValue)> {
+        let mut found: Vec<(&str, &ConfigValue)> = self.values.iter()
+            .filter_map(|(section, keys)| keys.get(key).map(|value| (section.as_str(), value)))
+            .collect();
+
+        found.sort_by(|a, b| a.0.cmp(b.0));
+        found
+    }
+
+    /// Sets a value in the configuration.
+    ///
+    /// This method inserts or updates a value in the configuration under the specified
+    /// section and key.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    /// * `value` - The `ConfigValue` to be set.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration is locked, see `lock` and `try_set`.
+    pub fn set(&mut self, section: &str, key: &str, value: ConfigValue) -> &mut Self {
+        self.assert_unlocked();
+        self.values
+            .entry(section.to_string())
+            .or_insert_with(HashMap::new)
+            .insert(key.to_string(), value);
+        self.record_source(section, key, ValueSource::Runtime);
+        self
+    }
+
+    /// Sets a value in the configuration, unless it is locked.
+    ///
+    /// This method works like `set`, but returns an error instead of panicking
+    /// when the configuration is locked.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    /// * `value` - The `ConfigValue` to be set.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(&mut Self)` - If the value is set, allowing method chaining.
+    /// * `Err(ConfigError)` - If the configuration is locked.
+    pub fn try_set(&mut self, section: &str, key: &str, value: ConfigValue) -> Result<&mut Self, ConfigError> {
+        self.check_unlocked()?;
+        Ok(self.set(section, key, value))
+    }
+
+    /// Sets many values in the configuration.
+    ///
+    /// This method is equivalent to calling `set` for each entry, in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The `(section, key, value)` tuples to set.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_many<I>(&mut self, entries: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (String, String, ConfigValue)>,
+    {
+        for (section, key, value) in entries {
+            self.set(&section, &key, value);
+        }
+        self
+    }
+
+    /// Sets many values in a section of the configuration.
+    ///
+    /// Existing keys of the section not present in `entries` are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `entries` - The keys and values to set.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_section(&mut self, section: &str, entries: HashMap<String, ConfigValue>) -> &mut Self {
+        for (key, value) in entries {
+            self.set(section, &key, value);
+        }
+        self
+    }
+
+    /// Sets a value read from a configuration file, recording the file as its source.
+    pub(crate) fn set_from_file(&mut self, section: &str, key: &str, value: ConfigValue, path: &Path) {
+        self.values
+            .entry(section.to_string())
+            .or_default()
+            .insert(key.to_string(), value);
+        self.record_source(section, key, ValueSource::File(path.to_path_buf()));
+    }
+
+    /// Records the origin of the last write of a key.
+    pub(crate) fn record_source(&mut self, section: &str, key: &str, source: ValueSource) {
+        self.sources.insert((section.to_string(), key.to_string()), source);
+    }
+
+    /// Retrieves the origin of a value.
+    ///
+    /// The source is the file (main file, include or layer) that last wrote the
+    /// key, or `ValueSource::Runtime` if it was last set through the API. This
+    /// helps finding out which of several files sets a value.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<ValueSource>` containing the origin of the value, or `None` if
+    /// the key is not found.
+    pub fn value_source(&self, section: &str, key: &str) -> Option<ValueSource> {
+        self.get(section, key)?;
+        self.sources.get(&(section.to_string(), key.to_string())).cloned()
+    }
+
+    /// Retrieves a value, inserting a computed default if it is missing.
+    ///
+    /// If the key is present, its value is returned unchanged. Otherwise the
+    /// closure is called, its result is stored under the section and key, and
+    /// a reference to the stored value is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    /// * `f` - A closure computing the default value, called only if the key is missing.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the existing or newly inserted `ConfigValue`.
+    pub fn get_or_insert_with<F>(&mut self, section: &str, key: &str, f: F) -> &ConfigValue
+    where
+        F: FnOnce() -> ConfigValue,
+    {
+        self.assert_unlocked();
+        if self.get(section, key).is_none() {
+            self.record_source(section, key, ValueSource::Runtime);
+        }
+
+        self.values
+            .entry(section.to_string())
+            .or_default()
+            .entry(key.to_string())
+            .or_insert_with(f)
+    }
+
+    /// Appends a value to an array in the configuration.
+    ///
+    /// If the key is not present, a new array containing only the given value is
+    /// created. If the key already holds an array, the value is appended to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    /// * `value` - The `ConfigValue` to append.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the value is successfully appended.
+    /// * `Err(ConfigError)` - If the key holds a value that is not an array.
+    pub fn push(&mut self, section: &str, key: &str, value: ConfigValue) -> Result<(), ConfigError> {
+        self.check_unlocked()?;
+        let entry = self.values
+            .entry(section.to_string())
+            .or_default()
+            .entry(key.to_string())
+            .or_insert_with(|| ConfigValue::Array(Vec::new()));
+
+        match entry {
+            ConfigValue::Array(arr) => {
+                arr.push(value);
+                self.record_source(section, key, ValueSource::Runtime);
+                Ok(())
+            },
+            _ => Err(ConfigError::TypeError(format!("{}.{} is not an array", section, key))),
+        }
+    }
 
-        // /opt/etc/myapp.conf
-        paths.push(PathBuf::from(format!("/opt/etc/{}", config_filename)));
+    /// Sets the encryptor used to encrypt values when saving.
+    ///
+    /// Values marked as encrypted (see `mark_encrypted`) are passed through the
+    /// encryptor and written as `enc:<ciphertext>`.
+    ///
+    /// Available only with the `encryption` feature enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `encryptor` - The `Encryptor` implementation to use.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    #[cfg(feature = "encryption")]
+    pub fn set_encryptor<E: crypto::Encryptor + 'static>(&mut self, encryptor: E) -> &mut Self {
+        self.crypto.set_encryptor(Arc::new(encryptor));
+        self
+    }
 
-        // ~/.config/myapp/myapp.conf
-        paths.push(PathBuf::from(format!("/home/{}/.config/{}/{}", username, self.app_name, config_filename)));
+    /// Sets the decryptor used to decrypt values when loading.
+    ///
+    /// String values stored as `enc:<ciphertext>` are passed through the decryptor
+    /// when the configuration is loaded, and automatically marked as encrypted.
+    /// This must be set before loading the configuration.
+    ///
+    /// Available only with the `encryption` feature enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `decryptor` - The `Decryptor` implementation to use.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    #[cfg(feature = "encryption")]
+    pub fn set_decryptor<D: crypto::Decryptor + 'static>(&mut self, decryptor: D) -> &mut Self {
+        self.crypto.set_decryptor(Arc::new(decryptor));
+        self
+    }
 
-        // ~/.config/myapp.conf
-        paths.push(PathBuf::from(format!("/home/{}/.config/{}", username, config_filename)));
+    /// Marks a value as encrypted, so that it is encrypted when saving.
+    ///
+    /// Available only with the `encryption` feature enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    #[cfg(feature = "encryption")]
+    pub fn mark_encrypted(&mut self, section: &str, key: &str) -> &mut Self {
+        self.crypto.mark(section, key);
+        self
+    }
 
-        // Path of executable file
-        if let Some(exec_dir) = exec_path.parent() {
-            paths.push(exec_dir.join(&config_filename));
+    /// Checks whether a value is stored encrypted.
+    ///
+    /// Available only with the `encryption` feature enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the value was loaded encrypted or marked with `mark_encrypted`.
+    #[cfg(feature = "encryption")]
+    pub fn is_encrypted(&self, section: &str, key: &str) -> bool {
+        self.crypto.is_marked(section, key)
+    }
+
+    /// Returns a copy of the configuration with the marked values encrypted,
+    /// or `None` if no value is marked.
+    #[cfg(feature = "encryption")]
+    fn encrypted(&self) -> Result<Option<Config>, ConfigError> {
+        if self.crypto.has_marked() {
+            crypto::encrypt_config(self).map(Some)
+        } else {
+            Ok(None)
         }
+    }
 
-        paths
+    /// Without the `encryption` feature no value is encrypted.
+    #[cfg(not(feature = "encryption"))]
+    fn encrypted(&self) -> Result<Option<Config>, ConfigError> {
+        Ok(None)
     }
 
-    /// Detects the configuration format from the file content.
+    /// Checks whether a value is stored encrypted, see `is_encrypted`.
+    #[cfg(feature = "encryption")]
+    fn is_stored_encrypted(&self, section: &str, key: &str) -> bool {
+        self.is_encrypted(section, key)
+    }
+
+    /// Without the `encryption` feature no value is stored encrypted.
+    #[cfg(not(feature = "encryption"))]
+    fn is_stored_encrypted(&self, _section: &str, _key: &str) -> bool {
+        false
+    }
+
+    /// Sets the words marking a key as sensitive.
     ///
-    /// This function reads the first line of the provided content to determine
-    /// the configuration format. If the first line starts with `#!config/FORMAT`,
-    /// the format is extracted and set in the `format` field of the `Config` struct.
-    /// If the format is unknown or unsupported, an error is returned. If no format
-    /// is specified, the default format is assumed to be INI.
+    /// The `Debug` and `Display` outputs of a `Config` mask as `***` the values
+    /// of the keys whose name contains one of these words, compared case-insensitively, and
+    /// of the keys stored encrypted, so that secrets do not leak into logs. Keys
+    /// of nested tables are checked too. By default the words are those of
+    /// `DEFAULT_SENSITIVE_KEYS`; an empty list masks only the encrypted keys.
     ///
     /// # Arguments
     ///
-    /// * `content` - A string slice containing the content of the configuration file.
+    /// * `words` - The words marking a key as sensitive.
     ///
     /// # Returns
     ///
-    /// * `Ok(())` - If the format is successfully detected and set.
-    /// * `Err(ConfigError)` - If the format is unknown or unsupported.
+    /// A mutable reference to the `Config` instance, allowing method chaining.
+    pub fn set_sensitive_keys(&mut self, words: &[&str]) -> &mut Self {
+        self.sensitive_keys = words.iter().map(|word| word.to_lowercase()).collect();
+        self
+    }
+
+    /// Checks whether the name of a key contains one of the sensitive words.
+    fn is_sensitive_key(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.sensitive_keys.iter().any(|word| key.contains(word.as_str()))
+    }
+
+    /// Returns a copy of the value of a key, masked if the key is sensitive or encrypted.
+    fn redact_entry(&self, section: &str, key: &str, value: &ConfigValue) -> ConfigValue {
+        if self.is_sensitive_key(key) || self.is_stored_encrypted(section, key) {
+            ConfigValue::String(REDACTED_VALUE.to_string())
+        } else {
+            self.redact_value(value)
+        }
+    }
+
+    /// Returns a copy of a value with the values of its sensitive keys masked.
+    fn redact_value(&self, value: &ConfigValue) -> ConfigValue {
+        match value {
+            ConfigValue::Table(table) => ConfigValue::Table(table.iter()
+                .map(|(key, value)| {
+                    let value = if self.is_sensitive_key(key) {
+                        ConfigValue::String(REDACTED_VALUE.to_string())
+                    } else {
+                        self.redact_value(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect()),
+            ConfigValue::Array(items) => ConfigValue::Array(items.iter().map(|item| self.redact_value(item)).collect()),
+            _ => value.clone(),
+        }
+    }
+
+    /// Resolves `${section.key}` references within string values.
     ///
-    /// # Errors
+    /// This method replaces every `${section.key}` reference found in string
+    /// values (including strings nested in arrays and tables) with the value
+    /// stored under that section and key. References are resolved recursively,
+    /// so a referenced value may itself contain references. Tokens without a
+    /// dot, such as `${HOME}`, are not references and are kept as they are.
     ///
-    /// Returns a `ConfigError::UnsupportedFormat` if the format specified in the
-    /// content is not recognized.
-    fn detect_format_from_content(&mut self, content: &str) -> Result<(), ConfigError> {
-        // Read the first line to determine the format.
-        let first_line = content.lines().next().unwrap_or("");
+    /// # Returns
+    ///
+    /// * `Ok(())` - If all references are successfully resolved.
+    /// * `Err(ConfigError)` - If a reference points to a missing value or a
+    ///   reference cycle is detected.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use confucius::{Config, ConfigValue};
+    ///
+    /// let mut config = Config::new("my_app");
+    /// config.set("server", "hostname", ConfigValue::String("localhost".to_string()));
+    /// config.set("server", "port", ConfigValue::Integer(8080));
+    /// config.set("server", "base_url", ConfigValue::String("http://${server.hostname}:${server.port}".to_string()));
+    ///
+    /// config.interpolate().unwrap();
+    /// assert_eq!(config.get_string("server", "base_url", None), Some("http://localhost:8080".to_string()));
+    /// ```
+    pub fn interpolate(&mut self) -> Result<(), ConfigError> {
+        self.check_unlocked()?;
+        interpolate::interpolate_config(self)
+    }
 
-        // If the first line is in the format #!config/FORMAT
-        if first_line.starts_with("#!config/") {
-            let format_str = first_line.trim_start_matches("#!config/").trim();
-            self.format = ConfigFormat::from(format_str);
+    /// Replaces every string value with the result of a function.
+    ///
+    /// Strings nested in arrays and tables are replaced too, while keys and
+    /// values of other types are left unchanged. This is meant for normalization,
+    /// such as trimming whitespace or expanding templates.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The function computing the new value of each string.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use confucius::{Config, ConfigValue};
+    ///
+    /// let mut config = Config::new("my_app");
+    /// config.set("server", "hostname", ConfigValue::String("  localhost ".to_string()));
+    ///
+    /// config.map_strings(|s| s.trim().to_string());
+    /// assert_eq!(config.get_string("server", "hostname", None), Some("localhost".to_string()));
+    /// ```
+    pub fn map_strings<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&str) -> String,
+    {
+        self.assert_unlocked();
+        for value in self.values.values_mut().flat_map(|keys| keys.values_mut()) {
+            map_value_strings(value, &mut f);
+        }
+    }
 
-            if self.format == ConfigFormat::Unknown {
-                return Err(ConfigError::UnsupportedFormat(format_str.to_string()));
+    /// Keeps only the entries for which a predicate returns `true`.
+    ///
+    /// The predicate receives the section, the key and the value of every entry.
+    /// Sections left empty by the removal are removed as well, while sections
+    /// that were already empty are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The predicate deciding which entries to keep.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use confucius::{Config, ConfigValue};
+    ///
+    /// let mut config = Config::new("my_app");
+    /// config.set("server", "port", ConfigValue::Integer(8080));
+    /// config.set("server", "legacy_port", ConfigValue::Integer(80));
+    ///
+    /// config.retain(|_, key, _| !key.starts_with("legacy_"));
+    /// assert!(config.get("server", "legacy_port").is_none());
+    /// assert!(config.get("server", "port").is_some());
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&str, &str, &ConfigValue) -> bool,
+    {
+        self.assert_unlocked();
+        let sources = &mut self.sources;
+        self.values.retain(|section, keys| {
+            if keys.is_empty() {
+                return true;
             }
+            keys.retain(|key, value| {
+                let keep = f(section, key, value);
+                if !keep {
+                    sources.remove(&(section.clone(), key.clone()));
+                }
+                keep
+            });
+            !keys.is_empty()
+        });
+    }
+
+    /// Saves the configuration to the current file.
+    ///
+    /// This method writes the configuration to the file specified in the `config_file_path`
+    /// field. If no file path is set, an error is returned.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the configuration is successfully saved.
+    /// * `Err(ConfigError)` - If no file path is set or an error occurs during saving.
+    pub fn save(&self) -> Result<(), ConfigError> {
+        if let Some(path) = &self.config_file_path {
+            self.save_to_file(path)
+        } else {
+            Err(ConfigError::Generic("Nessun file di configurazione caricato".to_string()))
+        }
+    }
+
+    /// Saves the configuration to a specific file.
+    ///
+    /// This method writes the configuration to the specified file path in the format
+    /// defined by the `format` field. The file is replaced atomically, keeping its
+    /// permissions, so a crash while saving never leaves a truncated file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to a `Path` representing the file to save the configuration to.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the configuration is successfully saved.
+    /// * `Err(ConfigError)` - If an error occurs during saving or the format is unsupported.
+    pub fn save_to_file(&self, path: &Path) -> Result<(), ConfigError> {
+        self.write_file(path, None)
+    }
+
+    /// Saves the configuration to a file only if its content would change.
+    ///
+    /// The configuration is serialized and compared with the current content of
+    /// the file, which is left untouched, modification time included, when they
+    /// are equal. This avoids spurious changes for tools watching the file.
+    /// Custom formats write the file themselves, so they are always saved.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to a `Path` representing the file to save the configuration to.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` - If the file was written.
+    /// * `Ok(false)` - If the file already had the same content.
+    /// * `Err(ConfigError)` - If an error occurs during saving or the format is unsupported.
+    pub fn save_if_changed(&self, path: &Path) -> Result<bool, ConfigError> {
+        if let ConfigFormat::Custom(_) = self.format {
+            self.write_file(path, None)?;
+            return Ok(true);
+        }
+
+        let content = self.render(None, path)?;
+        if fs::read(path).is_ok_and(|existing| existing == content.as_bytes()) {
+            return Ok(false);
+        }
+
+        utils::write_atomic(path, content.as_bytes()).map_err(ConfigError::Io)?;
+        Ok(true)
+    }
+
+    /// Saves the configuration to a specific file, documented by a schema.
+    ///
+    /// This method works like `save_to_file`, but writes the description of
+    /// each field defined in the schema as a `#` comment above its key, turning
+    /// the schema into a self-documenting configuration file. Descriptions are
+    /// written for the INI, TOML and YAML formats; JSON has no comment syntax,
+    /// so JSON output is unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to a `Path` representing the file to save the configuration to.
+    /// * `schema` - A reference to the `ValidationSchema` providing the field descriptions.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the configuration is successfully saved.
+    /// * `Err(ConfigError)` - If an error occurs during saving or the format is unsupported.
+    pub fn save_with_schema(&self, path: &Path, schema: &ValidationSchema) -> Result<(), ConfigError> {
+        self.write_file(path, Some(schema))
+    }
+
+    /// Saves only the values that differ from their schema defaults.
+    ///
+    /// Keys whose value equals the static default of their schema field (see
+    /// `FieldDefinition::default`) are omitted, as are sections left empty, so
+    /// that the file lists only intentional overrides. Keys without a static
+    /// default, including those with a computed default, are always written.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to a `Path` representing the file to save the configuration to.
+    /// * `schema` - A reference to the `ValidationSchema` providing the defaults.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the configuration is successfully saved.
+    /// * `Err(ConfigError)` - If an error occurs during saving or the format is unsupported.
+    pub fn save_non_defaults(&self, path: &Path, schema: &ValidationSchema) -> Result<(), ConfigError> {
+        let mut lean = self.clone();
+        for (section, keys) in lean.values.iter_mut() {
+            keys.retain(|key, value| {
+                schema.field_definition(section, key)
+                    .and_then(|field| field.default_value.as_ref())
+                    .is_none_or(|default| default != value)
+            });
+        }
+        lean.values.retain(|_, keys| !keys.is_empty());
+
+        lean.write_file(path, None)
+    }
+
+    /// Checks whether a path is the file the configuration was loaded from.
+    pub(crate) fn is_loaded_file(&self, path: &Path) -> bool {
+        self.config_file_path.as_deref().is_some_and(|loaded| {
+            loaded == path || fs::canonicalize(loaded).is_ok_and(|loaded| fs::canonicalize(path).is_ok_and(|path| path == loaded))
+        })
+    }
+
+    /// Writes the configuration to a file in its format.
+    ///
+    /// Built-in formats are serialized with `render` and written atomically with
+    /// `utils::write_atomic`; custom formats are written by their handler, which
+    /// does not receive the schema descriptions, to the temporary file of
+    /// `utils::write_atomic_with`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to a `Path` representing the file to write.
+    /// * `schema` - An optional `ValidationSchema` whose descriptions are written as comments.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the configuration is successfully written.
+    /// * `Err(ConfigError)` - If an error occurs during writing or the format is unsupported.
+    fn write_file(&self, path: &Path, schema: Option<&ValidationSchema>) -> Result<(), ConfigError> {
+        if let ConfigFormat::Custom(name) = &self.format {
+            let handler = self.format_handlers.get(name)?;
+            let encrypted = self.encrypted()?;
+            let config = encrypted.as_ref().unwrap_or(self);
+            return utils::write_atomic_with(path, |_, temp_path| handler.write(config, temp_path));
+        }
+
+        let content = self.render(schema, path)?;
+        utils::write_atomic(path, content.as_bytes()).map_err(ConfigError::Io)
+    }
+
+    /// Serializes the configuration in its format.
+    ///
+    /// Values marked as encrypted are encrypted before serialization. Include
+    /// directives of a loaded TOML or YAML document are only kept when saving
+    /// to the file it was loaded from: any other file gets all the values,
+    /// those read from includes too, and no include directive, whose relative
+    /// paths would not resolve from there.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - An optional `ValidationSchema` whose descriptions are written as comments.
+    /// * `path` - The file the configuration is written to.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The serialized configuration.
+    /// * `Err(ConfigError)` - If an error occurs during serialization or the format is unsupported.
+    fn render(&self, schema: Option<&ValidationSchema>, path: &Path) -> Result<String, ConfigError> {
+        // Encrypt the marked values before writing them
+        let encrypted = self.encrypted()?;
+        let config = encrypted.as_ref().unwrap_or(self);
+        let in_place = self.is_loaded_file(path);
+
+        // Sub-sections are written back as the tables they were loaded from
+        let joined;
+        let config = if config.nested_sections
+            && matches!(config.format, ConfigFormat::Toml | ConfigFormat::Yaml | ConfigFormat::Json) {
+            joined = config.join_nested_sections();
+            &joined
         } else {
-            // For now, assume INI if no format is specified.
-            self.format = ConfigFormat::Ini;
+            config
+        };
+
+        let output = match &config.format {
+            ConfigFormat::Ini => formats::ini::render_ini(config, schema)?,
+            ConfigFormat::Toml => formats::toml::render_toml(config, schema, in_place)?,
+            ConfigFormat::Yaml => formats::yaml::render_yaml(config, schema, in_place)?,
+            ConfigFormat::Json => formats::json::render_json(config)?,
+            // Custom formats write files directly, see `write_file`
+            ConfigFormat::Custom(name) => return Err(ConfigError::UnsupportedFormat(name.to_string())),
+            ConfigFormat::Unknown => return Err(ConfigError::UnsupportedFormat("Sconosciuto".to_string())),
+        };
+
+        // Write back the unknown header of the loaded file in place of ours
+        match (&self.format_header, output.split_once('\n')) {
+            (Some(header), Some((_, body))) => Ok(format!("{}\n{}", header, body)),
+            _ => Ok(output),
+        }
+    }
+
+    /// Retrieves a string value from the configuration.
+    ///
+    /// This method is a convenience wrapper that looks up a configuration value
+    /// by section and key, and returns it as a string if found. A key set to
+    /// `ConfigValue::Null` is treated as not set.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    /// * `default` - An optional default value to return if the key is not found or is null.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<String>` containing the string value if found, or the default value
+    /// if provided, or `None` if the key is not found and no default was provided.
+    pub fn get_string(&self, section: &str, key: &str, default: Option<&str>) -> Option<String> {
+        match self.get(section, key) {
+            Some(ConfigValue::Null) | None => default.map(|s| s.to_string()),
+            Some(value) => value.as_string().map(|s| s.clone()),
         }
+    }
 
-        Ok(())
+    /// Retrieves a string value from the configuration, without any fallback.
+    ///
+    /// Unlike `get_string`, this method distinguishes an explicitly empty string,
+    /// returned as `Some("")`, from a key that is missing or null.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<String>` containing the string value if the key holds a string,
+    /// or `None` if the key is missing, null, or not a string.
+    pub fn get_string_strict(&self, section: &str, key: &str) -> Option<String> {
+        self.get(section, key)?.as_string().cloned()
     }
 
-    /// Retrieves a value from the configuration.
+    /// Retrieves a value from the configuration, failing if it is missing.
     ///
-    /// This method looks up a value in the configuration by its section and key.
+    /// Unlike `get`, this method returns an error naming the missing
+    /// `section.key`, so it can be propagated with `?`.
     ///
     /// # Arguments
     ///
@@ -712,80 +2947,99 @@ impl Config {
     ///
     /// # Returns
     ///
-    /// An `Option` containing a reference to the `ConfigValue` if the value exists,
-    /// or `None` if the section or key is not found.
-    pub fn get(&self, section: &str, key: &str) -> Option<&ConfigValue> {
-        self.values.get(section).and_then(|section_map| section_map.get(key))
+    /// * `Ok(&ConfigValue)` - The value of the key.
+    /// * `Err(ConfigError)` - A `ConfigError::Generic` if the key is missing.
+    pub fn try_get(&self, section: &str, key: &str) -> Result<&ConfigValue, ConfigError> {
+        self.get(section, key).ok_or_else(|| {
+            ConfigError::Generic(format!("Missing configuration value: {}.{}", section, key))
+        })
     }
 
-    /// Sets a value in the configuration.
+    /// Retrieves a string value from the configuration, failing if it is missing.
     ///
-    /// This method inserts or updates a value in the configuration under the specified
-    /// section and key.
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The string value of the key.
+    /// * `Err(ConfigError)` - A `ConfigError::Generic` if the key is missing, or a
+    ///   `ConfigError::TypeError` if it does not hold a string.
+    pub fn try_get_string(&self, section: &str, key: &str) -> Result<String, ConfigError> {
+        self.try_get(section, key)?.as_string().cloned().ok_or_else(|| {
+            ConfigError::TypeError(format!("{}.{} is not a string", section, key))
+        })
+    }
+
+    /// Retrieves an integer value from the configuration, failing if it is missing.
     ///
     /// # Arguments
     ///
     /// * `section` - A string slice representing the section name.
     /// * `key` - A string slice representing the key name.
-    /// * `value` - The `ConfigValue` to be set.
     ///
     /// # Returns
     ///
-    /// A mutable reference to the `Config` instance, allowing method chaining.
-    pub fn set(&mut self, section: &str, key: &str, value: ConfigValue) -> &mut Self {
-        self.values
-            .entry(section.to_string())
-            .or_insert_with(HashMap::new)
-            .insert(key.to_string(), value);
-        self
+    /// * `Ok(i64)` - The integer value of the key.
+    /// * `Err(ConfigError)` - A `ConfigError::Generic` if the key is missing, or a
+    ///   `ConfigError::TypeError` if it does not hold an integer.
+    pub fn try_get_integer(&self, section: &str, key: &str) -> Result<i64, ConfigError> {
+        self.try_get(section, key)?.as_integer().ok_or_else(|| {
+            ConfigError::TypeError(format!("{}.{} is not an integer", section, key))
+        })
     }
 
-    /// Saves the configuration to the current file.
+    /// Retrieves a path from the configuration, expanding a leading `~`.
     ///
-    /// This method writes the configuration to the file specified in the `config_file_path`
-    /// field. If no file path is set, an error is returned.
+    /// A string value such as `~/.myapp/cache` is returned with `~` replaced by
+    /// the home directory of the current user. Expansion only happens through
+    /// this accessor, so strings that are not paths are never modified.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
     ///
     /// # Returns
     ///
-    /// * `Ok(())` - If the configuration is successfully saved.
-    /// * `Err(ConfigError)` - If no file path is set or an error occurs during saving.
-    pub fn save(&self) -> Result<(), ConfigError> {
-        if let Some(path) = &self.config_file_path {
-            self.save_to_file(path)
-        } else {
-            Err(ConfigError::Generic("Nessun file di configurazione caricato".to_string()))
-        }
+    /// An `Option<PathBuf>` containing the expanded path if the key holds a
+    /// string, or `None` if the key is missing or not a string.
+    pub fn get_path(&self, section: &str, key: &str) -> Option<PathBuf> {
+        self.get(section, key)?.as_string().map(|s| utils::expand_home(s))
     }
 
-    /// Saves the configuration to a specific file.
+    /// Retrieves a list stored as a comma-separated string, e.g. `"a, b, c"`.
     ///
-    /// This method writes the configuration to the specified file path in the format
-    /// defined by the `format` field.
+    /// INI has no arrays, so arrays saved as INI are written as a comma-separated
+    /// string and read back as a string. This accessor splits such a string on
+    /// commas and trims the items, so that the data stays usable while the
+    /// configuration is migrated to a format with real arrays. An empty string is
+    /// an empty list, and a real array is returned with its items as strings.
     ///
     /// # Arguments
     ///
-    /// * `path` - A reference to a `Path` representing the file to save the configuration to.
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
     ///
     /// # Returns
     ///
-    /// * `Ok(())` - If the configuration is successfully saved.
-    /// * `Err(ConfigError)` - If an error occurs during saving or the format is unsupported.
-    pub fn save_to_file(&self, path: &Path) -> Result<(), ConfigError> {
-        match self.format {
-            ConfigFormat::Ini => formats::ini::write_ini(self, path)?,
-            ConfigFormat::Toml => formats::toml::write_toml(self, path)?,
-            ConfigFormat::Yaml => formats::yaml::write_yaml(self, path)?,
-            ConfigFormat::Json => formats::json::write_json(self, path)?,
-            ConfigFormat::Unknown => return Err(ConfigError::UnsupportedFormat("Sconosciuto".to_string())),
+    /// An `Option<Vec<String>>` containing the items, or `None` if the key is
+    /// missing or holds neither a string nor an array.
+    pub fn get_csv_array(&self, section: &str, key: &str) -> Option<Vec<String>> {
+        match self.get(section, key)? {
+            ConfigValue::String(s) if s.trim().is_empty() => Some(Vec::new()),
+            ConfigValue::String(s) => Some(s.split(',').map(|item| item.trim().to_string()).collect()),
+            ConfigValue::Array(items) => Some(items.iter().map(|item| item.to_string()).collect()),
+            _ => None,
         }
-
-        Ok(())
     }
 
-    /// Retrieves a string value from the configuration.
+    /// Retrieves an integer value from the configuration.
     ///
     /// This method is a convenience wrapper that looks up a configuration value
-    /// by section and key, and returns it as a string if found.
+    /// by section and key, and returns it as an integer if found.
     ///
     /// # Arguments
     ///
@@ -795,19 +3049,21 @@ impl Config {
     ///
     /// # Returns
     ///
-    /// An `Option<String>` containing the string value if found, or the default value
+    /// An `Option<i64>` containing the integer value if found, or the default value
     /// if provided, or `None` if the key is not found and no default was provided.
-    pub fn get_string(&self, section: &str, key: &str, default: Option<&str>) -> Option<String> {
+    pub fn get_integer(&self, section: &str, key: &str, default: Option<i64>) -> Option<i64> {
         match self.get(section, key) {
-            Some(value) => value.as_string().map(|s| s.clone()),
-            None => default.map(|s| s.to_string()),
+            Some(value) => value.as_integer().or(default),
+            None => default,
         }
     }
 
-    /// Retrieves an integer value from the configuration.
+    /// Retrieves an integer value from the configuration, accepting integral floats.
     ///
-    /// This method is a convenience wrapper that looks up a configuration value
-    /// by section and key, and returns it as an integer if found.
+    /// Unlike `get_integer`, a float with no fractional part, such as `3.0`, is
+    /// returned as an integer. Floats are never rounded: a float with a
+    /// fractional part, such as `3.5`, or outside the `i64` range is treated
+    /// like any other non-integer value and yields the default.
     ///
     /// # Arguments
     ///
@@ -819,8 +3075,10 @@ impl Config {
     ///
     /// An `Option<i64>` containing the integer value if found, or the default value
     /// if provided, or `None` if the key is not found and no default was provided.
-    pub fn get_integer(&self, section: &str, key: &str, default: Option<i64>) -> Option<i64> {
+    pub fn get_integer_lossy(&self, section: &str, key: &str, default: Option<i64>) -> Option<i64> {
         match self.get(section, key) {
+            Some(ConfigValue::Float(f))
+                if f.fract() == 0.0 && *f >= i64::MIN as f64 && *f < i64::MAX as f64 => Some(*f as i64),
             Some(value) => value.as_integer().or(default),
             None => default,
         }
@@ -848,10 +3106,39 @@ impl Config {
         }
     }
 
+    /// Retrieves a percentage from the configuration, as a fraction.
+    ///
+    /// A string such as `"80%"` is converted to `0.8`, while a number, or a
+    /// string holding a number, is returned as it is: `0.5` stays `0.5`.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<f64>` containing the fraction, or `None` if the key is missing
+    /// or does not hold a percentage or a number.
+    pub fn get_percent(&self, section: &str, key: &str) -> Option<f64> {
+        match self.get(section, key)? {
+            ConfigValue::String(s) => {
+                let s = s.trim();
+                match s.strip_suffix('%') {
+                    Some(percent) => percent.trim_end().parse::<f64>().ok().map(|p| p / 100.0),
+                    None => s.parse::<f64>().ok(),
+                }
+            },
+            value => value.as_float(),
+        }
+    }
+
     /// Retrieves a boolean value from the configuration.
     ///
     /// This method is a convenience wrapper that looks up a configuration value
-    /// by section and key, and returns it as a boolean if found.
+    /// by section and key, and returns it as a boolean if found. String values
+    /// such as `"yes"`, `"off"` or `"1"` are converted with the same rules used
+    /// by the INI parser (`true/false`, `yes/no`, `on/off`, `1/0`, case-insensitive).
     ///
     /// # Arguments
     ///
@@ -865,11 +3152,37 @@ impl Config {
     /// if provided, or `None` if the key is not found and no default was provided.
     pub fn get_boolean(&self, section: &str, key: &str, default: Option<bool>) -> Option<bool> {
         match self.get(section, key) {
+            Some(ConfigValue::String(s)) => utils::parse_bool(s.trim()).or(default),
             Some(value) => value.as_boolean().or(default),
             None => default,
         }
     }
 
+    /// Retrieves a timestamp from the configuration.
+    ///
+    /// The value must be a string in the RFC 3339 format (e.g.
+    /// `2024-05-01T22:00:00Z` or `2024-05-01T23:00:00+01:00`), and is converted
+    /// to UTC.
+    ///
+    /// Available only with the `chrono` feature enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<DateTime<Utc>>` containing the timestamp if found, or `None` if
+    /// the key is not found or the value is not a valid RFC 3339 string.
+    #[cfg(feature = "chrono")]
+    pub fn get_datetime(&self, section: &str, key: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+        let value = self.get(section, key)?.as_string()?;
+        chrono::DateTime::parse_from_rfc3339(value.trim())
+            .ok()
+            .map(|datetime| datetime.with_timezone(&chrono::Utc))
+    }
+
     /// Retrieves an array value from the configuration.
     ///
     /// This method is a convenience wrapper that looks up a configuration value
@@ -894,6 +3207,78 @@ impl Config {
         })
     }
 
+    /// Retrieves the first element of an array value as a string.
+    ///
+    /// This is meant for lists whose first item is the primary one, such as a
+    /// list of servers tried in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<String>` containing the first element, or `None` if the key is
+    /// not found, is not an array, the array is empty or its first element is
+    /// not a string.
+    pub fn get_array_first_string(&self, section: &str, key: &str) -> Option<String> {
+        self.get_array(section, key)?.first()?.as_string().cloned()
+    }
+
+    /// Retrieves the first element of an array value as an integer.
+    ///
+    /// See `get_array_first_string`.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<i64>` containing the first element, or `None` if the key is
+    /// not found, is not an array, the array is empty or its first element is
+    /// not an integer.
+    pub fn get_array_first_integer(&self, section: &str, key: &str) -> Option<i64> {
+        self.get_array(section, key)?.first()?.as_integer()
+    }
+
+    /// Retrieves the first element of an array value as a boolean.
+    ///
+    /// See `get_array_first_string`.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<bool>` containing the first element, or `None` if the key is
+    /// not found, is not an array, the array is empty or its first element is
+    /// not a boolean.
+    pub fn get_array_first_boolean(&self, section: &str, key: &str) -> Option<bool> {
+        self.get_array(section, key)?.first()?.as_boolean()
+    }
+
+    /// Retrieves a whole section as a table value.
+    ///
+    /// The keys of the section become the keys of the table, so that the
+    /// section can be passed along as a single `ConfigValue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<ConfigValue>` containing a copy of the section as a
+    /// `ConfigValue::Table`, or `None` if the section does not exist.
+    pub fn section_as_table(&self, section: &str) -> Option<ConfigValue> {
+        self.values.get(section).cloned().map(ConfigValue::Table)
+    }
+
     /// Retrieves a table value from the configuration.
     ///
     /// This method is a convenience wrapper that looks up a configuration value
@@ -918,6 +3303,146 @@ impl Config {
         })
     }
 
+    /// Retrieves a mutable table value from the configuration.
+    ///
+    /// This method looks up a configuration value by section and key, and returns
+    /// a mutable reference to it if it is a table, allowing single fields of the
+    /// table to be modified in place.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - A string slice representing the section name.
+    /// * `key` - A string slice representing the key name.
+    ///
+    /// # Returns
+    ///
+    /// An `Option<&mut HashMap<String, ConfigValue>>` containing the table value if found,
+    /// or `None` if the key is not found or the value is not a table.
+    pub fn get_table_mut<'a>(&'a mut self, section: &str, key: &str) -> Option<&'a mut HashMap<String, ConfigValue>> {
+        self.assert_unlocked();
+        self.values.get_mut(section)
+            .and_then(|section_map| section_map.get_mut(key))
+            .and_then(|value| {
+                if let ConfigValue::Table(table) = value {
+                    Some(table)
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Sets a value at a dotted path in the configuration.
+    ///
+    /// The first segment of the path is the section and the second is the key.
+    /// Any further segment descends into nested tables, which are created as
+    /// needed (e.g. `database.main.host`).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A string slice representing the dotted path of the value.
+    /// * `value` - The `ConfigValue` to be set.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the value is successfully set.
+    /// * `Err(ConfigError)` - If the path is malformed or one of its segments
+    ///   holds a value that is not a table.
+    pub fn set_path(&mut self, path: &str, value: ConfigValue) -> Result<(), ConfigError> {
+        self.check_unlocked()?;
+        let segments: Vec<&str> = path.split('.').collect();
+        if segments.len() < 2 || segments.iter().any(|s| s.is_empty()) {
+            return Err(ConfigError::InvalidPath(format!("{} (expected section.key[.subkey...])", path)));
+        }
+
+        let (section, key, nested) = (segments[0], segments[1], &segments[2..]);
+        let Some((last, intermediate)) = nested.split_last() else {
+            self.set(section, key, value);
+            return Ok(());
+        };
+
+        let mut current = self.values
+            .entry(section.to_string())
+            .or_default()
+            .entry(key.to_string())
+            .or_insert_with(|| ConfigValue::Table(HashMap::new()));
+        let mut current_path = format!("{}.{}", section, key);
+
+        for segment in intermediate {
+            current = match current {
+                ConfigValue::Table(table) => table
+                    .entry(segment.to_string())
+                    .or_insert_with(|| ConfigValue::Table(HashMap::new())),
+                _ => return Err(ConfigError::TypeError(format!("{} is not a table", current_path))),
+            };
+            current_path = format!("{}.{}", current_path, segment);
+        }
+
+        match current {
+            ConfigValue::Table(table) => {
+                table.insert(last.to_string(), value);
+                self.record_source(section, key, ValueSource::Runtime);
+                Ok(())
+            },
+            _ => Err(ConfigError::TypeError(format!("{} is not a table", current_path))),
+        }
+    }
+
+    /// Flattens the configuration into dotted key-value pairs.
+    ///
+    /// Every scalar leaf is returned with its full path: the section, the key,
+    /// the keys of any nested table (e.g. `database.main.host`) and the index of
+    /// array items (e.g. `server.hosts[0]`). This is the inverse of `set_path`
+    /// for values held in tables. Empty arrays and tables have no leaves and are
+    /// omitted. Pairs are sorted by path.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<(String, ConfigValue)>` containing the path and value of each leaf.
+    pub fn flatten(&self) -> Vec<(String, ConfigValue)> {
+        let mut entries = Vec::new();
+        for (section, keys) in &self.values {
+            for (key, value) in keys {
+                flatten_value(&format!("{}.{}", section, key), value, &mut entries);
+            }
+        }
+
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Exports the configuration as environment variable assignments.
+    ///
+    /// Every scalar leaf is written as a `NAME=value` line, such as
+    /// `MYAPP_SERVER__PORT=8080` for the prefix `MYAPP` and the separator `__`.
+    /// The name is made of the prefix, the section, the key and the keys or
+    /// indices of any nested table or array, joined by the separator (the
+    /// prefix by `_`), uppercased and with other characters replaced by `_`.
+    /// Values containing characters unsafe for a shell are single-quoted.
+    /// Lines are sorted by name, so the output can be used as an env file.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix of the variable names, or an empty string for none.
+    /// * `separator` - The separator between the section, the key and nested keys.
+    ///
+    /// # Returns
+    ///
+    /// A `String` containing one assignment per line.
+    pub fn to_env(&self, prefix: &str, separator: &str) -> String {
+        let mut entries = Vec::new();
+        for (section, keys) in &self.values {
+            for (key, value) in keys {
+                env_entries(&[section.as_str(), key.as_str()], value, separator, &mut entries);
+            }
+        }
+        entries.sort();
+
+        let prefix = if prefix.is_empty() { String::new() } else { format!("{}_", env_name(prefix)) };
+        entries.into_iter()
+            .map(|(name, value)| format!("{}{}={}\n", prefix, name, shell_quote(&value)))
+            .collect()
+    }
+
     /// Retrieves all configuration values.
     ///
     /// This method provides access to the internal `values` field of the `Config` struct,
@@ -947,6 +3472,147 @@ impl Config {
     }
 }
 
+/// Implements the `Display` trait for the `Config` struct.
+///
+/// This implementation renders the configuration in a human-readable, INI-like
+/// form: one `[section]` header per section followed by its `key = value` pairs.
+/// Nested tables are flattened into dotted keys, strings are quoted, and sections
+/// and keys are sorted so the output is stable across runs. As in the `Debug`
+/// output, the values of sensitive and encrypted keys are masked (see
+/// `Config::set_sensitive_keys`).
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sections: Vec<_> = self.values.iter().collect();
+        sections.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (i, (section, values)) in sections.into_iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{}]", section)?;
+
+            let mut keys: Vec<_> = values.iter().collect();
+            keys.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, value) in keys {
+                fmt_entry(f, key, &self.redact_entry(section, key, value))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the scalar leaves of a value, prefixing their paths with `path`.
+fn flatten_value(path: &str, value: &ConfigValue, entries: &mut Vec<(String, ConfigValue)>) {
+    match value {
+        ConfigValue::Table(table) => {
+            for (key, item) in table {
+                flatten_value(&format!("{}.{}", path, key), item, entries);
+            }
+        },
+        ConfigValue::Array(arr) => {
+            for (i, item) in arr.iter().enumerate() {
+                flatten_value(&format!("{}[{}]", path, i), item, entries);
+            }
+        },
+        scalar => entries.push((path.to_string(), scalar.clone())),
+    }
+}
+
+/// Replaces every string in a value, recursing into arrays and tables.
+fn map_value_strings<F: FnMut(&str) -> String>(value: &mut ConfigValue, f: &mut F) {
+    match value {
+        ConfigValue::String(s) => *s = f(s),
+        ConfigValue::Array(items) => items.iter_mut().for_each(|item| map_value_strings(item, f)),
+        ConfigValue::Table(table) => table.values_mut().for_each(|item| map_value_strings(item, f)),
+        _ => {},
+    }
+}
+
+/// Collects the scalar leaves of a value as environment variable names and values.
+fn env_entries(path: &[&str], value: &ConfigValue, separator: &str, entries: &mut Vec<(String, String)>) {
+    match value {
+        ConfigValue::Table(table) => {
+            for (key, item) in table {
+                env_entries(&[path, &[key.as_str()]].concat(), item, separator, entries);
+            }
+        },
+        ConfigValue::Array(arr) => {
+            for (i, item) in arr.iter().enumerate() {
+                env_entries(&[path, &[i.to_string().as_str()]].concat(), item, separator, entries);
+            }
+        },
+        scalar => {
+            let name = path.iter().map(|segment| env_name(segment)).collect::<Vec<_>>().join(separator);
+            let value = match scalar {
+                ConfigValue::Null => String::new(),
+                other => other.to_string(),
+            };
+            entries.push((name, value));
+        },
+    }
+}
+
+/// Converts a name into an environment variable name: uppercase, with `_` for
+/// any character other than ASCII letters and digits.
+fn env_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+/// Single-quotes a value for a shell, unless it is made of safe characters only.
+fn shell_quote(value: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-.,:/@%+=".contains(c);
+    if !value.is_empty() && value.chars().all(is_safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Counts a value and the values nested in it, recursing into arrays and tables.
+fn count_values(value: &ConfigValue) -> usize {
+    match value {
+        ConfigValue::Array(items) => 1 + items.iter().map(count_values).sum::<usize>(),
+        ConfigValue::Table(table) => 1 + table.values().map(count_values).sum::<usize>(),
+        _ => 1,
+    }
+}
+
+/// Merges a value into an existing one, recursing into tables present in both.
+fn merge_value(target: &mut ConfigValue, source: &ConfigValue) {
+    match (target, source) {
+        (ConfigValue::Table(target_table), ConfigValue::Table(source_table)) => {
+            for (key, value) in source_table {
+                match target_table.get_mut(key) {
+                    Some(existing) => merge_value(existing, value),
+                    None => {
+                        target_table.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        },
+        (target, source) => *target = source.clone(),
+    }
+}
+
+/// Writes a single `key = value` line of the `Config` display, flattening tables.
+fn fmt_entry(f: &mut fmt::Formatter<'_>, key: &str, value: &ConfigValue) -> fmt::Result {
+    match value {
+        ConfigValue::String(s) => writeln!(f, "{} = {:?}", key, s),
+        ConfigValue::Table(table) if !table.is_empty() => {
+            let mut entries: Vec<_> = table.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (sub_key, sub_value) in entries {
+                fmt_entry(f, &format!("{}.{}", key, sub_key), sub_value)?;
+            }
+            Ok(())
+        },
+        other => writeln!(f, "{} = {}", key, other),
+    }
+}
+
 // Add Default implementation for Config
 impl Default for Config {
     /// Creates a new `Config` instance with default values.
@@ -959,14 +3625,102 @@ impl Default for Config {
             values: HashMap::new(),
             format: ConfigFormat::Unknown,
             config_file_path: None,
+            ini_auto_type: true,
+            ini_separator: DEFAULT_INI_SEPARATOR,
+            ini_align: false,
+            ini_dotted_keys: false,
+            decimal_separator: DEFAULT_DECIMAL_SEPARATOR,
+            json_pretty: true,
+            yaml_flow_arrays: false,
+            nested_sections: false,
+            #[cfg(feature = "encryption")]
+            crypto: crypto::ValueCrypto::default(),
+            known_sections: HashSet::new(),
+            reject_unknown_sections: false,
+            root_section: DEFAULT_ROOT_SECTION.to_string(),
+            sources: HashMap::new(),
+            max_input_bytes: None,
+            max_total_keys: None,
+            config_filename: None,
+            search_dirs: None,
+            toml_document: None,
+            yaml_document: None,
+            root_array: false,
+            format_handlers: formats::FormatRegistry::default(),
+            locked: false,
+            unknown_format_fallback: None,
+            format_header: None,
+            included_files: HashSet::new(),
+            schema: None,
+            sensitive_keys: DEFAULT_SENSITIVE_KEYS.iter().map(|word| word.to_string()).collect(),
+            include_base_dir: None,
         }
     }
 }
 
+impl fmt::Debug for Config {
+    /// Formats the configuration, masking the values of sensitive keys.
+    ///
+    /// See `Config::set_sensitive_keys`. The source of the loaded TOML
+    /// document, which holds the values as written, is not printed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values: HashMap<&String, HashMap<&String, ConfigValue>> = self.values.iter()
+            .map(|(section, keys)| {
+                let keys = keys.iter()
+                    .map(|(key, value)| (key, self.redact_entry(section, key, value)))
+                    .collect();
+                (section, keys)
+            })
+            .collect();
+
+        let mut debug = f.debug_struct("Config");
+        debug.field("app_name", &self.app_name)
+            .field("values", &values)
+            .field("format", &self.format)
+            .field("config_file_path", &self.config_file_path)
+            .field("ini_auto_type", &self.ini_auto_type)
+            .field("ini_separator", &self.ini_separator)
+            .field("ini_align", &self.ini_align)
+            .field("ini_dotted_keys", &self.ini_dotted_keys)
+            .field("decimal_separator", &self.decimal_separator)
+            .field("json_pretty", &self.json_pretty)
+            .field("yaml_flow_arrays", &self.yaml_flow_arrays)
+            .field("nested_sections", &self.nested_sections);
+        #[cfg(feature = "encryption")]
+        debug.field("crypto", &self.crypto);
+        debug.field("known_sections", &self.known_sections)
+            .field("reject_unknown_sections", &self.reject_unknown_sections)
+            .field("root_section", &self.root_section)
+            .field("sources", &self.sources)
+            .field("max_input_bytes", &self.max_input_bytes)
+            .field("max_total_keys", &self.max_total_keys)
+            .field("config_filename", &self.config_filename)
+            .field("search_dirs", &self.search_dirs)
+            .field("toml_document", &self.toml_document.is_some())
+            .field("yaml_document", &self.yaml_document.is_some())
+            .field("root_array", &self.root_array)
+            .field("format_handlers", &self.format_handlers)
+            .field("locked", &self.locked)
+            .field("unknown_format_fallback", &self.unknown_format_fallback)
+            .field("format_header", &self.format_header)
+            .field("included_files", &self.included_files)
+            .field("schema", &self.schema)
+            .field("sensitive_keys", &self.sensitive_keys)
+            .field("include_base_dir", &self.include_base_dir)
+            .finish()
+    }
+}
+
 
 // Esportiamo i moduli pubblici
 pub use formats::ini;
 pub use formats::toml;
 pub use formats::yaml;
 pub use formats::json;
-pub use validation::*;
\ No newline at end of file
+pub use validation::*;
+#[cfg(feature = "encryption")]
+pub use crypto::{Encryptor, Decryptor};
+pub use formats::FormatHandler;
+pub use from_config::{FromConfig, __private};
+#[cfg(feature = "derive")]
+pub use confucius_derive::FromConfig;
\ No newline at end of file
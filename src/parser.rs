@@ -33,6 +33,7 @@ pub fn parse_file(config: &mut Config, path: &Path) -> Result<(), ConfigError> {
         ConfigFormat::Toml => formats::toml::parse_toml(config, &content, path),
         ConfigFormat::Yaml => formats::yaml::parse_yaml(config, &content, path),
         ConfigFormat::Json => formats::json::parse_json(config, &content, path),
+        ConfigFormat::Custom(name) => Err(ConfigError::UnsupportedFormat(name.to_string())),
         ConfigFormat::Unknown => Err(ConfigError::UnsupportedFormat("Sconosciuto".to_string())),
     }
 }
@@ -1,6 +1,8 @@
 //! Utility functions for the library
 
 use std::env;
+use std::fs;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use crate::ConfigError;
 use path_clean::PathClean;
@@ -76,13 +78,14 @@ pub fn resolve_path(base_file: &Path, relative_path: &str) -> PathBuf {
 ///
 /// `true` if the string starts and ends with double quotes, otherwise `false`.
 pub fn is_quoted(s: &str) -> bool {
-    s.starts_with('"') && s.ends_with('"')
+    s.len() >= 2 && s.starts_with('"') && s.ends_with('"')
 }
 
 /// Removes double quotes from a string.
 ///
 /// This function removes the leading and trailing double quotes from a string
-/// if they exist. It also handles escaped quotes within the string.
+/// if they exist. Within quotes it interprets the escape sequences `\"`, `\\`,
+/// `\n`, `\t`, `\r` and `\uXXXX`; other backslashes are kept as they are.
 ///
 /// # Arguments
 ///
@@ -96,22 +99,41 @@ pub fn unquote(s: &str) -> String {
         // Extract the string without the leading and trailing quotes
         let content = &s[1..s.len()-1];
 
-        // Handle escape sequences by replacing \" with "
+        // Handle the escape sequences
         let mut result = String::with_capacity(content.len());
         let mut chars = content.chars().peekable();
-        let mut in_escape = false;
 
         while let Some(c) = chars.next() {
-            if in_escape {
-                // If in escape mode, include the character as is
-                result.push(c);
-                in_escape = false;
-            } else if c == '\\' && chars.peek() == Some(&'"') {
-                // If a backslash is followed by a quote, treat it as an escape
-                in_escape = true;
-            } else {
+            if c != '\\' {
                 result.push(c);
+                continue;
+            }
+
+            match chars.peek() {
+                Some('"') => result.push('"'),
+                Some('\\') => result.push('\\'),
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some('u') => {
+                    // \uXXXX, with exactly four hex digits naming a valid character
+                    let hex: String = chars.clone().skip(1).take(4).collect();
+                    match u32::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 4).and_then(char::from_u32) {
+                        Some(unicode) => {
+                            result.push(unicode);
+                            chars.nth(4);
+                        },
+                        None => result.push('\\'),
+                    }
+                    continue;
+                },
+                // Unknown escapes are kept literally
+                _ => {
+                    result.push('\\');
+                    continue;
+                },
             }
+            chars.next();
         }
 
         result
@@ -120,10 +142,38 @@ pub fn unquote(s: &str) -> String {
     }
 }
 
+/// Escapes a string to be written between double quotes.
+///
+/// This is the inverse of `unquote`: backslashes, quotes, newlines, tabs and
+/// carriage returns are written as escape sequences.
+///
+/// # Arguments
+///
+/// * `s` - A string slice to escape.
+///
+/// # Returns
+///
+/// A `String` with the escape sequences, without the surrounding quotes.
+pub fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
 /// Removes comments from a line.
 ///
 /// Comments are defined as anything following a `#` character that is not
-/// inside double quotes.
+/// inside double quotes. Within quotes, an escaped quote (`\"`) does not end
+/// the quoted text.
 ///
 /// # Arguments
 ///
@@ -143,6 +193,12 @@ pub fn strip_comments(line: &str) -> String {
                 in_quotes = !in_quotes;
                 result.push(c);
             },
+            '\\' if in_quotes => {
+                result.push(c);
+                if let Some(escaped) = chars.next() {
+                    result.push(escaped);
+                }
+            },
             '#' if !in_quotes => {
                 break; // Comment found, stop processing
             },
@@ -153,4 +209,189 @@ pub fn strip_comments(line: &str) -> String {
     }
 
     result.trim_end().to_string()
-}
\ No newline at end of file
+}
+
+/// Formats a text as a block of `#` comment lines.
+///
+/// Each line of the text is prefixed with `# ` and terminated by a newline,
+/// which is the comment syntax shared by the INI, TOML and YAML formats.
+///
+/// # Arguments
+///
+/// * `text` - A string slice containing the comment text.
+///
+/// # Returns
+///
+/// A `String` containing the formatted comment lines.
+pub fn format_comment(text: &str) -> String {
+    text.lines()
+        .map(|line| if line.is_empty() { "#\n".to_string() } else { format!("# {}\n", line) })
+        .collect()
+}
+
+/// Parses a boolean-like string.
+///
+/// The accepted values are `true`/`false`, `yes`/`no`, `on`/`off` and `1`/`0`,
+/// compared case-insensitively.
+///
+/// # Arguments
+///
+/// * `s` - A string slice containing the value to parse.
+///
+/// # Returns
+///
+/// An `Option<bool>` containing the parsed value, or `None` if the string is
+/// not a recognized boolean.
+pub fn parse_bool(s: &str) -> Option<bool> {
+    // ASCII case folding, independent of the locale
+    match s.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Removes the `#!config/FORMAT` header line from a file content, if present.
+///
+/// The rest of the content is returned unchanged, so `\r\n` line endings are
+/// preserved.
+///
+/// # Arguments
+///
+/// * `content` - A string slice containing the content of the file.
+///
+/// # Returns
+///
+/// A string slice containing the content after the header line.
+pub fn strip_format_header(content: &str) -> &str {
+    if !content.starts_with("#!config/") {
+        return content;
+    }
+
+    content.split_once('\n').map_or("", |(_, rest)| rest)
+}
+
+/// Expands a leading `~` to the home directory of the current user.
+///
+/// Only `~` alone or followed by a path separator is expanded: `~user/...` and
+/// `~` elsewhere in the path are kept as they are, as is the whole path if the
+/// home directory cannot be determined.
+///
+/// # Arguments
+///
+/// * `path` - A string slice containing the path to expand.
+///
+/// # Returns
+///
+/// A `PathBuf` containing the expanded path.
+pub fn expand_home(path: &str) -> PathBuf {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', std::path::MAIN_SEPARATOR]) => rest,
+        _ => return PathBuf::from(path),
+    };
+
+    match home::home_dir() {
+        Some(home_dir) => home_dir.join(rest.trim_start_matches(['/', std::path::MAIN_SEPARATOR])),
+        None => PathBuf::from(path),
+    }
+}
+
+/// Writes a file atomically, so that readers never see a partial file.
+///
+/// See `write_atomic_with`.
+///
+/// # Arguments
+///
+/// * `path` - A reference to a `Path` representing the file to write.
+/// * `content` - The content of the file.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the file is written.
+/// * `Err(io::Error)` - If an error occurs.
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    write_atomic_with(path, |file, _| file.write_all(content))
+}
+
+/// Writes a file atomically with a custom writer.
+///
+/// The content is written to a temporary file in the same directory, which is
+/// then renamed over the target, and the directory is synced so that the rename
+/// survives a crash. The temporary file is created with the permissions of an
+/// existing target before any byte is written, so that the content of a private
+/// file is never readable by others, and a symbolic link is followed so that its
+/// target is replaced.
+///
+/// # Arguments
+///
+/// * `path` - A reference to a `Path` representing the file to write.
+/// * `write` - Writes the content, given the open temporary file and its path.
+///
+/// # Returns
+///
+/// * `Ok(())` - If the file is written.
+/// * `Err(E)` - If an error occurs. The target is unchanged, unless only the
+///   sync of the directory failed.
+pub fn write_atomic_with<E, F>(path: &Path, write: F) -> Result<(), E>
+where
+    E: From<io::Error>,
+    F: FnOnce(&mut fs::File, &Path) -> Result<(), E>,
+{
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = target.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path without a file name"))?
+        .to_string_lossy();
+    let permissions = fs::metadata(&target).ok().map(|metadata| metadata.permissions());
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if let Some(permissions) = &permissions {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(permissions.mode() & 0o7777);
+    }
+
+    // Find a temporary name not taken, e.g. by a concurrent save
+    let (temp_path, mut file) = (0..)
+        .map(|i| dir.join(format!(".{}.{}.{}.tmp", file_name, std::process::id(), i)))
+        .find_map(|temp_path| match options.open(&temp_path) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => None,
+            result => Some(result.map(|file| (temp_path, file))),
+        })
+        .unwrap()?;
+
+    let result = (|| {
+        // The umask may have narrowed the mode at creation: restore it before writing
+        if let Some(permissions) = permissions {
+            fs::set_permissions(&temp_path, permissions)?;
+        }
+        write(&mut file, &temp_path)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &target)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    sync_dir(dir)?;
+    Ok(())
+}
+
+/// Syncs a directory, so that the renames into it are durable.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+/// Syncs a directory; directories cannot be opened for syncing on this platform.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -5,6 +5,7 @@
 //! data types, constraints, and custom validation logic.
 
 use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::sync::Arc;
 use regex::Regex;
 
@@ -25,10 +26,28 @@ pub enum ValueType {
     Array,
     /// Table type
     Table,
+    /// Null value
+    Null,
     /// Accepts any type
     Any,
 }
 
+impl std::fmt::Display for ValueType {
+    /// Formats the type using the lowercase names used in configuration files.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueType::String => write!(f, "string"),
+            ValueType::Integer => write!(f, "integer"),
+            ValueType::Float => write!(f, "float"),
+            ValueType::Boolean => write!(f, "boolean"),
+            ValueType::Array => write!(f, "array"),
+            ValueType::Table => write!(f, "table"),
+            ValueType::Null => write!(f, "null"),
+            ValueType::Any => write!(f, "any"),
+        }
+    }
+}
+
 impl From<&ConfigValue> for ValueType {
     /// Converts a `ConfigValue` to its corresponding `ValueType`.
     fn from(value: &ConfigValue) -> Self {
@@ -39,6 +58,7 @@ impl From<&ConfigValue> for ValueType {
             ConfigValue::Boolean(_) => ValueType::Boolean,
             ConfigValue::Array(_) => ValueType::Array,
             ConfigValue::Table(_) => ValueType::Table,
+            ConfigValue::Null => ValueType::Null,
         }
     }
 }
@@ -50,8 +70,14 @@ pub struct FieldDefinition {
     pub value_type: ValueType,
     /// Indicates if the field is required
     pub required: bool,
+    /// Profiles in which the field is required, even if not required otherwise
+    pub required_in_profiles: Vec<String>,
     /// Default value (optional)
     pub default_value: Option<ConfigValue>,
+    /// Function computing the default value when it is applied (optional)
+    pub default_fn: Option<DefaultFn>,
+    /// Function deriving the default value from the configuration (optional)
+    pub default_from: Option<DefaultFromFn>,
     /// Constraints for the field
     pub constraints: Vec<FieldConstraint>,
     /// Field description (useful for documentation)
@@ -64,7 +90,10 @@ impl FieldDefinition {
         FieldDefinition {
             value_type,
             required: false,
+            required_in_profiles: Vec::new(),
             default_value: None,
+            default_fn: None,
+            default_from: None,
             constraints: Vec::new(),
             description: None,
         }
@@ -76,12 +105,80 @@ impl FieldDefinition {
         self
     }
 
+    /// Marks the field as required only in the given profiles
+    ///
+    /// The active profile is set with `ValidationSchema::active_profile`; in
+    /// other profiles, or without an active profile, the field is optional.
+    pub fn required_in_profiles(mut self, profiles: &[&str]) -> Self {
+        self.required_in_profiles = profiles.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// Checks whether the field is required in the given profile, if any
+    pub fn is_required_in(&self, profile: Option<&str>) -> bool {
+        self.required || profile.is_some_and(|p| self.required_in_profiles.iter().any(|r| r == p))
+    }
+
     /// Sets a default value for the field
     pub fn default(mut self, value: ConfigValue) -> Self {
         self.default_value = Some(value);
+        self.default_fn = None;
+        self.default_from = None;
+        self
+    }
+
+    /// Sets a function computing the default value each time defaults are applied
+    pub fn default_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn() -> ConfigValue + Send + Sync + 'static,
+    {
+        self.default_fn = Some(DefaultFn::new(f));
+        self.default_value = None;
+        self.default_from = None;
+        self
+    }
+
+    /// Sets a function deriving the default value from other fields
+    ///
+    /// The function is called when defaults are applied, with read access to
+    /// the whole configuration, e.g. to default `display_name` to `username`.
+    /// It runs after the static and computed defaults of the schema are set,
+    /// and returning `None` leaves the field missing.
+    pub fn default_from<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Config) -> Option<ConfigValue> + Send + Sync + 'static,
+    {
+        self.default_from = Some(DefaultFromFn::new(f));
+        self.default_value = None;
+        self.default_fn = None;
         self
     }
 
+    /// Checks whether the field has a default value, static, computed or derived
+    pub fn has_default(&self) -> bool {
+        self.default_value.is_some() || self.default_fn.is_some() || self.default_from.is_some()
+    }
+
+    /// Returns the default value of the field, computing it if needed
+    ///
+    /// Defaults derived from the configuration are not resolved, see
+    /// `resolve_default_in`.
+    pub fn resolve_default(&self) -> Option<ConfigValue> {
+        match (&self.default_value, &self.default_fn) {
+            (Some(value), _) => Some(value.clone()),
+            (None, Some(default_fn)) => Some(default_fn.compute()),
+            (None, None) => None,
+        }
+    }
+
+    /// Returns the default value of the field in a configuration, deriving it if needed
+    pub fn resolve_default_in(&self, config: &Config) -> Option<ConfigValue> {
+        match &self.default_from {
+            Some(default_from) => default_from.compute(config),
+            None => self.resolve_default(),
+        }
+    }
+
     /// Adds a constraint to the field
     pub fn constraint(mut self, constraint: FieldConstraint) -> Self {
         self.constraints.push(constraint);
@@ -96,6 +193,11 @@ impl FieldDefinition {
 
     /// Validates a value against the field definition
     ///
+    /// This method can be used on its own to check a single candidate value, for
+    /// instance while the user edits it, without validating the whole
+    /// configuration: fetch the definition with `ValidationSchema::field_definition`
+    /// and pass the value with its `section.key` path, used in the error.
+    ///
     /// # Arguments
     ///
     /// * `value` - The value to validate.
@@ -105,9 +207,20 @@ impl FieldDefinition {
     ///
     /// * `Ok(())` - If the value is valid.
     /// * `Err(ValidationError)` - If the value is invalid.
+    ///
+    /// Reference constraints need the whole configuration, so they are not
+    /// checked by this method, only by `ValidationSchema::validate`. Likewise
+    /// no profile is active, so `required_in_profiles` is not considered.
     pub fn validate(&self, value: Option<&ConfigValue>, path: &str) -> Result<(), ValidationError> {
+        self.validate_in(value, path, None, None)
+    }
+
+    /// Validates a value, checking references against the configuration if given,
+    /// and the required profiles against the active profile if given.
+    fn validate_in(&self, value: Option<&ConfigValue>, path: &str,
+                   config: Option<&Config>, profile: Option<&str>) -> Result<(), ValidationError> {
         if value.is_none() {
-            if self.required {
+            if self.is_required_in(profile) {
                 return Err(ValidationError::MissingField {
                     path: path.to_string(),
                 });
@@ -129,15 +242,27 @@ impl FieldDefinition {
         }
 
         for constraint in &self.constraints {
-            constraint.validate(value, path)?;
+            constraint.validate_in(value, path, config)?;
         }
 
         Ok(())
     }
 }
 
+/// Signature of a custom validation function
+type ValueValidator = dyn Fn(&ConfigValue) -> Result<(), String> + Send + Sync;
+
+/// Signature of a configuration-wide validation rule
+type ConfigValidator = dyn Fn(&Config) -> Result<(), String> + Send + Sync;
+
+/// Signature of a function computing a default value
+type DefaultProvider = dyn Fn() -> ConfigValue + Send + Sync;
+
+/// Signature of a function deriving a default value from the configuration
+type DerivedDefaultProvider = dyn Fn(&Config) -> Option<ConfigValue> + Send + Sync;
+
 /// Wrapper for custom validation functions
-pub struct ValidateFn(Arc<dyn Fn(&ConfigValue) -> Result<(), String> + Send + Sync>);
+pub struct ValidateFn(Arc<ValueValidator>);
 
 impl ValidateFn {
     /// Creates a new custom validation function
@@ -166,6 +291,331 @@ impl std::fmt::Debug for ValidateFn {
     }
 }
 
+/// Wrapper for validation rules spanning the whole configuration
+pub struct RuleFn(Arc<ConfigValidator>);
+
+impl RuleFn {
+    /// Creates a new configuration-wide validation rule
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&Config) -> Result<(), String> + Send + Sync + 'static,
+    {
+        RuleFn(Arc::new(f))
+    }
+
+    /// Executes the rule on a configuration
+    pub fn validate(&self, config: &Config) -> Result<(), String> {
+        (self.0)(config)
+    }
+}
+
+impl Clone for RuleFn {
+    fn clone(&self) -> Self {
+        RuleFn(Arc::clone(&self.0))
+    }
+}
+
+impl std::fmt::Debug for RuleFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RuleFn")
+    }
+}
+
+/// Wrapper for functions computing default values
+pub struct DefaultFn(Arc<DefaultProvider>);
+
+impl DefaultFn {
+    /// Creates a new default value function
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn() -> ConfigValue + Send + Sync + 'static,
+    {
+        DefaultFn(Arc::new(f))
+    }
+
+    /// Computes the default value
+    pub fn compute(&self) -> ConfigValue {
+        (self.0)()
+    }
+}
+
+impl Clone for DefaultFn {
+    fn clone(&self) -> Self {
+        DefaultFn(Arc::clone(&self.0))
+    }
+}
+
+impl std::fmt::Debug for DefaultFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DefaultFn")
+    }
+}
+
+/// Wrapper for functions deriving default values from the configuration
+pub struct DefaultFromFn(Arc<DerivedDefaultProvider>);
+
+impl DefaultFromFn {
+    /// Creates a new derived default value function
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&Config) -> Option<ConfigValue> + Send + Sync + 'static,
+    {
+        DefaultFromFn(Arc::new(f))
+    }
+
+    /// Computes the default value from a configuration
+    pub fn compute(&self, config: &Config) -> Option<ConfigValue> {
+        (self.0)(config)
+    }
+}
+
+impl Clone for DefaultFromFn {
+    fn clone(&self) -> Self {
+        DefaultFromFn(Arc::clone(&self.0))
+    }
+}
+
+impl std::fmt::Debug for DefaultFromFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DefaultFromFn")
+    }
+}
+
+/// Range of a fixed-width integer type, checked by `FieldConstraint::fits`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntegerWidth {
+    /// Name of the type (e.g. `u16`)
+    pub name: &'static str,
+    /// Smallest value of the type
+    pub min: i64,
+    /// Largest value of the type, capped to `i64::MAX`
+    pub max: i64,
+}
+
+impl IntegerWidth {
+    /// Checks whether a value is in the range of the type
+    pub fn contains(&self, value: i64) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
+
+impl std::fmt::Display for IntegerWidth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}..={})", self.name, self.min, self.max)
+    }
+}
+
+/// Integer types usable with `FieldConstraint::fits`
+pub trait FixedWidth {
+    /// The range of the type
+    const WIDTH: IntegerWidth;
+}
+
+macro_rules! impl_fixed_width {
+    ($($t:ty),*) => {
+        $(
+            impl FixedWidth for $t {
+                const WIDTH: IntegerWidth = IntegerWidth {
+                    name: stringify!($t),
+                    min: <$t>::MIN as i64,
+                    max: if <$t>::MAX as u64 > i64::MAX as u64 { i64::MAX } else { <$t>::MAX as i64 },
+                };
+            }
+        )*
+    };
+}
+
+impl_fixed_width!(u8, u16, u32, u64, usize, i8, i16, i32, i64);
+
+/// Well-known string formats checked by `FieldConstraint::Format`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StringFormat {
+    /// Absolute URL with a scheme and a host (e.g. `https://example.com/path`)
+    Url,
+    /// Email address (e.g. `admin@example.com`)
+    Email,
+    /// IPv4 or IPv6 address
+    IpAddr,
+    /// IP address with a port (e.g. `127.0.0.1:8080` or `[::1]:8080`)
+    SocketAddr,
+}
+
+impl std::fmt::Display for StringFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StringFormat::Url => write!(f, "URL"),
+            StringFormat::Email => write!(f, "email address"),
+            StringFormat::IpAddr => write!(f, "IP address"),
+            StringFormat::SocketAddr => write!(f, "socket address"),
+        }
+    }
+}
+
+impl StringFormat {
+    /// Checks whether a string is well-formed, returning the reason if it is not.
+    fn check(&self, s: &str) -> Result<(), String> {
+        match self {
+            StringFormat::Url => check_url(s),
+            StringFormat::Email => check_email(s),
+            StringFormat::IpAddr => s.parse::<std::net::IpAddr>()
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            StringFormat::SocketAddr => s.parse::<std::net::SocketAddr>()
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Checks on the filesystem path held by a string value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathCheck {
+    /// The path exists
+    Exists,
+    /// The path is a readable file
+    IsFile,
+    /// The path is a readable directory
+    IsDir,
+}
+
+impl std::fmt::Display for PathCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathCheck::Exists => write!(f, "existing path"),
+            PathCheck::IsFile => write!(f, "readable file"),
+            PathCheck::IsDir => write!(f, "readable directory"),
+        }
+    }
+}
+
+impl PathCheck {
+    /// Checks a path on the filesystem, returning the reason if it fails.
+    fn check(&self, s: &str) -> Result<(), String> {
+        let path = std::path::Path::new(s);
+        let metadata = std::fs::metadata(path).map_err(|e| e.to_string())?;
+
+        match self {
+            PathCheck::Exists => Ok(()),
+            PathCheck::IsFile if !metadata.is_file() => Err("not a file".to_string()),
+            PathCheck::IsFile => std::fs::File::open(path)
+                .map(|_| ())
+                .map_err(|e| format!("not readable: {}", e)),
+            PathCheck::IsDir if !metadata.is_dir() => Err("not a directory".to_string()),
+            PathCheck::IsDir => std::fs::read_dir(path)
+                .map(|_| ())
+                .map_err(|e| format!("not readable: {}", e)),
+        }
+    }
+}
+
+/// Target of a string value naming another part of the configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReferenceTarget {
+    /// The value names a section
+    Section,
+    /// The value names a key of the given section
+    Key(String),
+}
+
+impl std::fmt::Display for ReferenceTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReferenceTarget::Section => write!(f, "section"),
+            ReferenceTarget::Key(section) => write!(f, "key of section {}", section),
+        }
+    }
+}
+
+impl ReferenceTarget {
+    /// Checks whether the configuration contains the referenced name.
+    fn exists(&self, name: &str, config: &Config) -> bool {
+        match self {
+            ReferenceTarget::Section => config.values.contains_key(name),
+            ReferenceTarget::Key(section) => config.get(section, name).is_some(),
+        }
+    }
+}
+
+/// Schemes whose URLs have no authority, e.g. `mailto:ops@example.com`.
+const OPAQUE_URL_SCHEMES: &[&str] = &["mailto", "urn", "tel", "sms", "data", "news", "geo", "magnet"];
+
+/// Checks that a string is an absolute URL.
+///
+/// URLs with an authority (`scheme://host[:port][/path]`) need a valid host
+/// and port, except `file` URLs, whose host may be empty (`file:///etc/app.pem`).
+/// URLs without an authority are only accepted for the schemes that have none
+/// (`mailto:ops@example.com`, `urn:isbn:...`, see `OPAQUE_URL_SCHEMES`), and
+/// need a path after the scheme, so that `host:port/path`, `c:/dir` or
+/// `user:pass` are not taken for URLs.
+fn check_url(s: &str) -> Result<(), String> {
+    if s.chars().any(char::is_whitespace) {
+        return Err("contains whitespace".to_string());
+    }
+
+    let (scheme, rest) = s.split_once(':').ok_or("missing scheme")?;
+    let mut scheme_chars = scheme.chars();
+    if !scheme_chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        || !scheme_chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')) {
+        return Err(format!("invalid scheme '{}'", scheme));
+    }
+
+    let Some(rest) = rest.strip_prefix("//") else {
+        if !OPAQUE_URL_SCHEMES.iter().any(|opaque| scheme.eq_ignore_ascii_case(opaque)) {
+            return Err(format!("missing '//' after scheme '{}'", scheme));
+        }
+        if rest.is_empty() {
+            return Err("missing path".to_string());
+        }
+        return Ok(());
+    };
+
+    // The authority ends at the first path, query or fragment delimiter
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    let (host, port) = if let Some(ipv6) = host_port.strip_prefix('[') {
+        let (host, after) = ipv6.split_once(']').ok_or("unterminated IPv6 address")?;
+        host.parse::<std::net::Ipv6Addr>().map_err(|_| format!("invalid IPv6 address '{}'", host))?;
+        (host, after.strip_prefix(':'))
+    } else {
+        match host_port.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (host_port, None),
+        }
+    };
+
+    // file:///path has an empty authority, meaning the local host
+    let local_file = scheme.eq_ignore_ascii_case("file") && authority.is_empty();
+    if host.is_empty() && !local_file {
+        return Err("missing host".to_string());
+    }
+    if let Some(port) = port
+        && port.parse::<u16>().is_err() {
+        return Err(format!("invalid port '{}'", port));
+    }
+
+    Ok(())
+}
+
+/// Checks that a string is an email address of the form `local@domain.tld`.
+fn check_email(s: &str) -> Result<(), String> {
+    if s.chars().any(char::is_whitespace) {
+        return Err("contains whitespace".to_string());
+    }
+
+    let (local, domain) = s.split_once('@').ok_or("missing '@'")?;
+    if local.is_empty() {
+        return Err("missing local part".to_string());
+    }
+    if domain.contains('@') {
+        return Err("more than one '@'".to_string());
+    }
+    if !domain.contains('.') || domain.split('.').any(str::is_empty) {
+        return Err(format!("invalid domain '{}'", domain));
+    }
+
+    Ok(())
+}
+
 /// Custom constraints for fields
 #[derive(Debug, Clone)]
 pub enum FieldConstraint {
@@ -188,6 +638,12 @@ pub enum FieldConstraint {
         max: Option<i64>,
         /// Allowed values (if specified)
         allowed_values: Option<Vec<i64>>,
+        /// Step between valid values (if specified)
+        step: Option<i64>,
+        /// Offset of the valid values from the multiples of the step
+        offset: i64,
+        /// Integer type the value must fit in (if specified)
+        width: Option<IntegerWidth>,
     },
     /// Constraint for float values
     Float {
@@ -204,7 +660,29 @@ pub enum FieldConstraint {
         max_length: Option<usize>,
         /// Type of elements (if specified)
         item_type: Option<Box<FieldDefinition>>,
+        /// Whether the elements must be distinct
+        unique: bool,
     },
+    /// Constraint for tables
+    Table {
+        /// Regex pattern of the keys (if specified)
+        key_pattern: Option<Regex>,
+        /// Type of values (if specified)
+        value_type: Option<Box<FieldDefinition>>,
+        /// Minimum number of entries (if specified)
+        min_entries: Option<usize>,
+        /// Maximum number of entries (if specified)
+        max_entries: Option<usize>,
+    },
+    /// Constraint for strings in a well-known format
+    Format(StringFormat),
+    /// Constraint for strings holding a filesystem path, checked on disk
+    Path(PathCheck),
+    /// Constraint for strings naming a section or key of the configuration
+    Reference(ReferenceTarget),
+    /// Constraint for strings matching a pattern with lookaround or backreferences
+    #[cfg(feature = "fancy-regex")]
+    FancyPattern(fancy_regex::Regex),
     /// Custom constraint with a validation function
     Custom {
         /// Validation function
@@ -237,11 +715,12 @@ impl FieldConstraint {
                     allowed_values,
                 }
             },
-            FieldConstraint::Array { max_length, item_type, .. } => {
+            FieldConstraint::Array { max_length, item_type, unique, .. } => {
                 FieldConstraint::Array {
                     min_length: Some(min),
                     max_length,
                     item_type,
+                    unique,
                 }
             },
             _ => self,
@@ -259,11 +738,12 @@ impl FieldConstraint {
                     allowed_values,
                 }
             },
-            FieldConstraint::Array { min_length, item_type, .. } => {
+            FieldConstraint::Array { min_length, item_type, unique, .. } => {
                 FieldConstraint::Array {
                     min_length,
                     max_length: Some(max),
                     item_type,
+                    unique,
                 }
             },
             _ => self,
@@ -285,6 +765,20 @@ impl FieldConstraint {
         }
     }
 
+    /// Creates a constraint for strings matching a `fancy_regex` pattern
+    ///
+    /// Unlike `pattern`, the pattern may use lookaround and backreferences, e.g.
+    /// `^(?!admin$)\w+$`. An invalid pattern is reported instead of panicking.
+    #[cfg(feature = "fancy-regex")]
+    pub fn pattern_fancy(pattern: &str) -> Result<Self, ValidationError> {
+        fancy_regex::Regex::new(pattern)
+            .map(FieldConstraint::FancyPattern)
+            .map_err(|e| ValidationError::InvalidPattern {
+                pattern: pattern.to_string(),
+                message: e.to_string(),
+            })
+    }
+
     /// Sets the allowed string values for a string constraint
     pub fn allowed_string_values(self, values: Vec<&str>) -> Self {
         match self {
@@ -306,17 +800,23 @@ impl FieldConstraint {
             min: None,
             max: None,
             allowed_values: None,
+            step: None,
+            offset: 0,
+            width: None,
         }
     }
 
     /// Sets the minimum value for an integer constraint
     pub fn min_int(self, min: i64) -> Self {
         match self {
-            FieldConstraint::Integer { max, allowed_values, .. } => {
+            FieldConstraint::Integer { max, allowed_values, step, offset, width, .. } => {
                 FieldConstraint::Integer {
                     min: Some(min),
                     max,
                     allowed_values,
+                    step,
+                    offset,
+                    width,
                 }
             },
             _ => self,
@@ -326,11 +826,14 @@ impl FieldConstraint {
     /// Sets the maximum value for an integer constraint
     pub fn max_int(self, max: i64) -> Self {
         match self {
-            FieldConstraint::Integer { min, allowed_values, .. } => {
+            FieldConstraint::Integer { min, allowed_values, step, offset, width, .. } => {
                 FieldConstraint::Integer {
                     min,
                     max: Some(max),
                     allowed_values,
+                    step,
+                    offset,
+                    width,
                 }
             },
             _ => self,
@@ -340,11 +843,70 @@ impl FieldConstraint {
     /// Sets the allowed integer values for an integer constraint
     pub fn allowed_int_values(self, values: Vec<i64>) -> Self {
         match self {
-            FieldConstraint::Integer { min, max, .. } => {
+            FieldConstraint::Integer { min, max, step, offset, width, .. } => {
                 FieldConstraint::Integer {
                     min,
                     max,
                     allowed_values: Some(values),
+                    step,
+                    offset,
+                    width,
+                }
+            },
+            _ => self,
+        }
+    }
+
+    /// Sets the step for an integer constraint: valid values are `offset + k * step`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `step` is not positive.
+    pub fn step(self, step: i64) -> Self {
+        assert!(step > 0, "the step of an integer constraint must be positive");
+        match self {
+            FieldConstraint::Integer { min, max, allowed_values, offset, width, .. } => {
+                FieldConstraint::Integer {
+                    min,
+                    max,
+                    allowed_values,
+                    step: Some(step),
+                    offset,
+                    width,
+                }
+            },
+            _ => self,
+        }
+    }
+
+    /// Sets the offset of the step for an integer constraint (0 by default)
+    pub fn offset(self, offset: i64) -> Self {
+        match self {
+            FieldConstraint::Integer { min, max, allowed_values, step, width, .. } => {
+                FieldConstraint::Integer {
+                    min,
+                    max,
+                    allowed_values,
+                    step,
+                    offset,
+                    width,
+                }
+            },
+            _ => self,
+        }
+    }
+
+    /// Requires an integer to fit in a fixed-width integer type, e.g. `fits::<u16>()`
+    pub fn fits<T: FixedWidth>(self) -> Self {
+        match self {
+            FieldConstraint::Integer { min, max, allowed_values, step, offset, .. } => {
+                FieldConstraint::Integer {
+                    min,
+                    max,
+                    allowed_values,
+                    step,
+                    offset,
+                    width: Some(T::WIDTH),
                 }
             },
             _ => self,
@@ -391,17 +953,104 @@ impl FieldConstraint {
             min_length: None,
             max_length: None,
             item_type: None,
+            unique: false,
         }
     }
 
     /// Sets the type of elements for an array constraint
     pub fn item_type(self, item_def: FieldDefinition) -> Self {
         match self {
-            FieldConstraint::Array { min_length, max_length, .. } => {
+            FieldConstraint::Array { min_length, max_length, unique, .. } => {
                 FieldConstraint::Array {
                     min_length,
                     max_length,
                     item_type: Some(Box::new(item_def)),
+                    unique,
+                }
+            },
+            _ => self,
+        }
+    }
+
+    /// Requires the items of an array constraint to be distinct
+    pub fn unique(self) -> Self {
+        match self {
+            FieldConstraint::Array { min_length, max_length, item_type, .. } => {
+                FieldConstraint::Array {
+                    min_length,
+                    max_length,
+                    item_type,
+                    unique: true,
+                }
+            },
+            _ => self,
+        }
+    }
+
+    /// Creates a new table constraint
+    pub fn table() -> Self {
+        FieldConstraint::Table {
+            key_pattern: None,
+            value_type: None,
+            min_entries: None,
+            max_entries: None,
+        }
+    }
+
+    /// Sets the regex pattern of the keys for a table constraint
+    pub fn key_pattern(self, pattern: &str) -> Self {
+        match self {
+            FieldConstraint::Table { value_type, min_entries, max_entries, .. } => {
+                FieldConstraint::Table {
+                    key_pattern: Some(Regex::new(pattern).unwrap()),
+                    value_type,
+                    min_entries,
+                    max_entries,
+                }
+            },
+            _ => self,
+        }
+    }
+
+    /// Sets the type of values for a table constraint
+    pub fn value_type(self, value_def: FieldDefinition) -> Self {
+        match self {
+            FieldConstraint::Table { key_pattern, min_entries, max_entries, .. } => {
+                FieldConstraint::Table {
+                    key_pattern,
+                    value_type: Some(Box::new(value_def)),
+                    min_entries,
+                    max_entries,
+                }
+            },
+            _ => self,
+        }
+    }
+
+    /// Sets the minimum number of entries for a table constraint
+    pub fn min_entries(self, min: usize) -> Self {
+        match self {
+            FieldConstraint::Table { key_pattern, value_type, max_entries, .. } => {
+                FieldConstraint::Table {
+                    key_pattern,
+                    value_type,
+                    min_entries: Some(min),
+                    max_entries,
+                }
+            },
+            _ => self,
+        }
+    }
+
+    /// Sets the maximum number of entries for a table constraint
+    pub fn max_entries(self, max: usize) -> Self {
+        match self {
+            FieldConstraint::Table { key_pattern, value_type, min_entries, .. } => {
+                FieldConstraint::Table {
+                    key_pattern,
+                    value_type,
+                    min_entries,
+                    max_entries: Some(max),
                 }
             },
             _ => self,
@@ -419,22 +1068,120 @@ impl FieldConstraint {
         }
     }
 
-    /// Validates a value against the constraint.
-    ///
-    /// This method checks if a given `ConfigValue` satisfies the conditions defined
-    /// by the `FieldConstraint`. It performs type-specific validation based on the
-    /// constraint type (e.g., string, integer, float, array, or custom).
+    /// Creates a new constraint requiring a well-formed absolute URL
     ///
-    /// # Arguments
-    ///
-    /// * `value` - A reference to the `ConfigValue` to validate.
-    /// * `path` - A string slice representing the path of the field in the configuration.
+    /// Besides `scheme://host[:port][/path]` URLs, `file:///path` URLs and the
+    /// URLs of schemes without an authority, such as `mailto:`, `urn:`, `tel:`
+    /// and `data:`, are accepted; `host:port[/path]`, `c:/dir` and `user:pass`
+    /// are not.
+    pub fn url() -> Self {
+        FieldConstraint::Format(StringFormat::Url)
+    }
+
+    /// Creates a new constraint requiring a well-formed email address
+    pub fn email() -> Self {
+        FieldConstraint::Format(StringFormat::Email)
+    }
+
+    /// Creates a new constraint requiring an IPv4 or IPv6 address
+    pub fn ip_addr() -> Self {
+        FieldConstraint::Format(StringFormat::IpAddr)
+    }
+
+    /// Creates a new constraint requiring an IP address with a port
+    pub fn socket_addr() -> Self {
+        FieldConstraint::Format(StringFormat::SocketAddr)
+    }
+
+    /// Creates a new constraint requiring an existing path.
+    ///
+    /// The path is checked on disk during validation, so this constraint should
+    /// not be used for paths created after the configuration is validated.
+    pub fn path_exists() -> Self {
+        FieldConstraint::Path(PathCheck::Exists)
+    }
+
+    /// Creates a new constraint requiring the path of a readable file
+    pub fn path_is_file() -> Self {
+        FieldConstraint::Path(PathCheck::IsFile)
+    }
+
+    /// Creates a new constraint requiring the path of a readable directory
+    pub fn path_is_dir() -> Self {
+        FieldConstraint::Path(PathCheck::IsDir)
+    }
+
+    /// Creates a new constraint requiring the name of an existing section.
+    ///
+    /// For example, `active_profile = "production"` must name a section of the
+    /// configuration. The reference is checked by `ValidationSchema::validate`,
+    /// which has the whole configuration at hand.
+    pub fn references_section() -> Self {
+        FieldConstraint::Reference(ReferenceTarget::Section)
+    }
+
+    /// Creates a new constraint requiring the name of an existing key of a section
+    pub fn references_key(section: &str) -> Self {
+        FieldConstraint::Reference(ReferenceTarget::Key(section.to_string()))
+    }
+
+    /// Validates a value against the constraint.
+    ///
+    /// This method checks if a given `ConfigValue` satisfies the conditions defined
+    /// by the `FieldConstraint`. It performs type-specific validation based on the
+    /// constraint type (e.g., string, integer, float, array, table, path, or custom).
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - A reference to the `ConfigValue` to validate.
+    /// * `path` - A string slice representing the path of the field in the configuration.
     ///
     /// # Returns
     ///
     /// * `Ok(())` - If the value satisfies the constraint.
     /// * `Err(ValidationError)` - If the value violates the constraint.
+    ///
+    /// A value whose type does not match the constraint (e.g. a string checked
+    /// by an integer constraint) is reported as `ConstraintTypeMismatch`; only
+    /// custom constraints accept values of any type.
+    ///
+    /// Reference constraints need the whole configuration, so they are not
+    /// checked by this method, only by `ValidationSchema::validate`.
     pub fn validate(&self, value: &ConfigValue, path: &str) -> Result<(), ValidationError> {
+        self.validate_in(value, path, None)
+    }
+
+    /// Returns the type of the values the constraint applies to, if restricted
+    fn applies_to(&self) -> Option<ValueType> {
+        match self {
+            FieldConstraint::String { .. }
+            | FieldConstraint::Format(_)
+            | FieldConstraint::Path(_)
+            | FieldConstraint::Reference(_) => Some(ValueType::String),
+            #[cfg(feature = "fancy-regex")]
+            FieldConstraint::FancyPattern(_) => Some(ValueType::String),
+            FieldConstraint::Integer { .. } => Some(ValueType::Integer),
+            FieldConstraint::Float { .. } => Some(ValueType::Float),
+            FieldConstraint::Array { .. } => Some(ValueType::Array),
+            FieldConstraint::Table { .. } => Some(ValueType::Table),
+            FieldConstraint::Custom { .. } => None,
+        }
+    }
+
+    /// Validates a value, checking references against the configuration if given.
+    fn validate_in(&self, value: &ConfigValue, path: &str, config: Option<&Config>) -> Result<(), ValidationError> {
+        // A constraint on a value of another type is a schema mistake, not a pass
+        if let Some(expected) = self.applies_to() {
+            let actual = ValueType::from(value);
+            if actual != expected {
+                return Err(ValidationError::ConstraintTypeMismatch {
+                    path: path.to_string(),
+                    constraint: expected,
+                    actual,
+                });
+            }
+        }
+
         match self {
             // Validation for string constraints
             FieldConstraint::String { min_length, max_length, pattern, allowed_values } => {
@@ -486,8 +1233,18 @@ impl FieldConstraint {
             },
 
             // Validation for integer constraints
-            FieldConstraint::Integer { min, max, allowed_values } => {
+            FieldConstraint::Integer { min, max, allowed_values, step, offset, width } => {
                 if let ConfigValue::Integer(i) = value {
+                    // Check the range of the target integer type
+                    if let Some(width) = width
+                        && !width.contains(*i) {
+                        return Err(ValidationError::IntegerOutOfWidth {
+                            path: path.to_string(),
+                            width: *width,
+                            actual: *i,
+                        });
+                    }
+
                     // Check minimum value
                     if let Some(min_val) = min {
                         if *i < *min_val {
@@ -520,6 +1277,17 @@ impl FieldConstraint {
                             });
                         }
                     }
+
+                    // Check the step, in i128 to avoid overflows
+                    if let Some(step_val) = step
+                        && (i128::from(*i) - i128::from(*offset)).rem_euclid(i128::from(*step_val)) != 0 {
+                        return Err(ValidationError::IntegerNotOnStep {
+                            path: path.to_string(),
+                            step: *step_val,
+                            offset: *offset,
+                            actual: *i,
+                        });
+                    }
                 }
             },
 
@@ -551,7 +1319,7 @@ impl FieldConstraint {
             },
 
             // Validation for array constraints
-            FieldConstraint::Array { min_length, max_length, item_type } => {
+            FieldConstraint::Array { min_length, max_length, item_type, unique } => {
                 if let ConfigValue::Array(arr) = value {
                     // Check minimum length
                     if let Some(min) = min_length {
@@ -575,16 +1343,125 @@ impl FieldConstraint {
                         }
                     }
 
+                    // Check that no item repeats an earlier one
+                    if *unique
+                        && let Some((index, first_index)) = arr.iter().enumerate().find_map(|(i, item)| {
+                            arr[..i].iter().position(|earlier| earlier == item).map(|first| (i, first))
+                        }) {
+                        return Err(ValidationError::ArrayDuplicateItem {
+                            path: path.to_string(),
+                            index,
+                            first_index,
+                        });
+                    }
+
                     // Validate each item in the array
                     if let Some(item_def) = item_type {
                         for (i, item) in arr.iter().enumerate() {
                             let item_path = format!("{}[{}]", path, i);
-                            item_def.validate(Some(item), &item_path)?;
+                            item_def.validate_in(Some(item), &item_path, config, None)?;
                         }
                     }
                 }
             },
 
+            // Validation for table constraints
+            FieldConstraint::Table { key_pattern, value_type, min_entries, max_entries } => {
+                if let ConfigValue::Table(table) = value {
+                    // Check minimum number of entries
+                    if let Some(min) = min_entries
+                        && table.len() < *min {
+                        return Err(ValidationError::TableTooSmall {
+                            path: path.to_string(),
+                            min: *min,
+                            actual: table.len(),
+                        });
+                    }
+
+                    // Check maximum number of entries
+                    if let Some(max) = max_entries
+                        && table.len() > *max {
+                        return Err(ValidationError::TableTooLarge {
+                            path: path.to_string(),
+                            max: *max,
+                            actual: table.len(),
+                        });
+                    }
+
+                    // Validate each entry in key order, so errors are deterministic
+                    let mut keys: Vec<&String> = table.keys().collect();
+                    keys.sort();
+                    for key in keys {
+                        let entry_path = format!("{}.{}", path, key);
+
+                        if let Some(regex) = key_pattern
+                            && !regex.is_match(key) {
+                            return Err(ValidationError::PatternMismatch {
+                                path: entry_path,
+                                pattern: regex.to_string(),
+                                value: key.clone(),
+                            });
+                        }
+
+                        if let Some(value_def) = value_type {
+                            value_def.validate_in(Some(&table[key]), &entry_path, config, None)?;
+                        }
+                    }
+                }
+            },
+
+            // Validation for well-known string formats
+            FieldConstraint::Format(format) => {
+                if let ConfigValue::String(s) = value
+                    && let Err(reason) = format.check(s) {
+                    return Err(ValidationError::InvalidFormat {
+                        path: path.to_string(),
+                        format: *format,
+                        value: s.clone(),
+                        reason,
+                    });
+                }
+            },
+
+            // Validation for filesystem paths
+            FieldConstraint::Path(check) => {
+                if let ConfigValue::String(s) = value
+                    && let Err(reason) = check.check(s) {
+                    return Err(ValidationError::InvalidPath {
+                        path: path.to_string(),
+                        check: *check,
+                        value: s.clone(),
+                        reason,
+                    });
+                }
+            },
+
+            // Validation for patterns with lookaround; a match exceeding the
+            // backtracking limit counts as a mismatch
+            #[cfg(feature = "fancy-regex")]
+            FieldConstraint::FancyPattern(regex) => {
+                if let ConfigValue::String(s) = value
+                    && !regex.is_match(s).unwrap_or(false) {
+                    return Err(ValidationError::PatternMismatch {
+                        path: path.to_string(),
+                        pattern: regex.as_str().to_string(),
+                        value: s.clone(),
+                    });
+                }
+            },
+
+            // Validation for references, when the configuration is known
+            FieldConstraint::Reference(target) => {
+                if let (ConfigValue::String(s), Some(config)) = (value, config)
+                    && !target.exists(s, config) {
+                    return Err(ValidationError::DanglingReference {
+                        path: path.to_string(),
+                        target: target.clone(),
+                        value: s.clone(),
+                    });
+                }
+            },
+
             // Validation for custom constraints
             FieldConstraint::Custom { validate_fn, description } => {
                 if let Err(msg) = validate_fn.validate(value) {
@@ -619,6 +1496,18 @@ pub struct ValidationSchema {
 
     /// Indicates whether undefined keys are allowed in sections.
     allow_unknown_keys: bool,
+
+    /// Rules spanning multiple fields or sections.
+    rules: Vec<RuleFn>,
+
+    /// Deprecated key names, as (section, old key, new key).
+    aliases: Vec<(String, String, String)>,
+
+    /// The active profile, for the fields required only in some profiles.
+    profile: Option<String>,
+
+    /// Groups of keys of which exactly one must be set, as (section, keys).
+    exactly_one: Vec<(String, Vec<String>)>,
 }
 
 impl ValidationSchema {
@@ -633,6 +1522,10 @@ impl ValidationSchema {
             required_sections: HashSet::new(),
             allow_unknown_sections: true,
             allow_unknown_keys: true,
+            rules: Vec::new(),
+            aliases: Vec::new(),
+            profile: None,
+            exactly_one: Vec::new(),
         }
     }
 
@@ -690,6 +1583,30 @@ impl ValidationSchema {
         self
     }
 
+    /// Retrieves the definition of a field.
+    ///
+    /// The definition can be used to validate a single value with
+    /// `FieldDefinition::validate`.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The name of the section where the field is defined.
+    /// * `key` - The name of the field.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing a reference to the `FieldDefinition` if the field is
+    /// defined in the schema, or `None` otherwise.
+    pub fn field_definition(&self, section: &str, key: &str) -> Option<&FieldDefinition> {
+        self.sections.get(section).and_then(|fields| fields.get(key))
+    }
+
+    /// Retrieves the description of a field, if the field is defined and documented.
+    pub(crate) fn field_description(&self, section: &str, key: &str) -> Option<&str> {
+        self.field_definition(section, key)
+            .and_then(|definition| definition.description.as_deref())
+    }
+
     /// Configures whether undefined sections are allowed.
     ///
     /// # Arguments
@@ -718,8 +1635,139 @@ impl ValidationSchema {
         self
     }
 
+    /// Sets the active profile, such as `production` or `development`.
+    ///
+    /// Fields marked with `FieldDefinition::required_in_profiles` are required
+    /// only when one of their profiles is active.
+    ///
+    /// # Arguments
+    ///
+    /// * `profile` - The name of the active profile.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `ValidationSchema` instance for method chaining.
+    pub fn active_profile(&mut self, profile: &str) -> &mut Self {
+        self.profile = Some(profile.to_string());
+        self
+    }
+
+    /// Adds a rule validating the configuration as a whole.
+    ///
+    /// Rules express invariants that involve more than one field, such as
+    /// "if `ssl_enabled` is true then `cert_file` is required". A rule returns
+    /// an error message when the invariant does not hold.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The function checking the invariant on the whole configuration.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `ValidationSchema` instance for method chaining.
+    pub fn rule<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&Config) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.rules.push(RuleFn::new(f));
+        self
+    }
+
+    /// Declares a deprecated name of a key, accepted in place of the new one.
+    ///
+    /// This eases migrations when a key is renamed. `validate` reads a present
+    /// `old_key` as `new_key` when `new_key` is absent, and never reports it as
+    /// unknown; `validate_all` reports it as a warning. `apply_aliases` (also run
+    /// by `apply_defaults`) copies its value to `new_key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The name of the section containing the key.
+    /// * `old_key` - The deprecated name of the key.
+    /// * `new_key` - The current name of the key.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `ValidationSchema` instance for method chaining.
+    pub fn deprecated_alias(&mut self, section: &str, old_key: &str, new_key: &str) -> &mut Self {
+        self.aliases.push((section.to_string(), old_key.to_string(), new_key.to_string()));
+        self
+    }
+
+    /// Requires exactly one of a set of keys to be set in a section.
+    ///
+    /// This expresses alternatives such as "set exactly one of `url`, `socket`
+    /// or `host`": `validate` reports `ValidationError::ExactlyOneOf`, listing
+    /// the keys that are set, when none or more than one of them is. Null
+    /// values count as not set.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The name of the section containing the keys.
+    /// * `keys` - The alternative keys.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `ValidationSchema` instance for method chaining.
+    pub fn exactly_one_of(&mut self, section: &str, keys: &[&str]) -> &mut Self {
+        self.exactly_one.push((section.to_string(), keys.iter().map(|k| k.to_string()).collect()));
+        self
+    }
+
+    /// Copies the values of deprecated keys to their new names.
+    ///
+    /// A value is copied only if the new key is absent; the deprecated key is
+    /// left in place. A warning is returned for each deprecated key found.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A mutable reference to the `Config` instance to migrate.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec` containing a `ValidationError::DeprecatedKey` warning for each
+    /// deprecated key present in the configuration.
+    pub fn apply_aliases(&self, config: &mut Config) -> Vec<ValidationError> {
+        self.copy_aliases(config, None)
+    }
+
+    /// Copies the values of the deprecated keys of one or all sections.
+    fn copy_aliases(&self, config: &mut Config, only_section: Option<&str>) -> Vec<ValidationError> {
+        let mut warnings = Vec::new();
+
+        for (section, old_key, new_key) in &self.aliases {
+            if only_section.is_some_and(|only| only != section) {
+                continue;
+            }
+
+            if let Some(value) = config.get(section, old_key).cloned() {
+                if config.get(section, new_key).is_none() {
+                    config.set(section, new_key, value);
+                }
+                warnings.push(deprecated_key(section, old_key, new_key));
+            }
+        }
+
+        warnings
+    }
+
+    /// Returns the deprecated name of a key, if any.
+    fn alias_of(&self, section: &str, new_key: &str) -> Option<&str> {
+        self.aliases.iter()
+            .find(|(alias_section, _, alias_new)| alias_section == section && alias_new == new_key)
+            .map(|(_, old_key, _)| old_key.as_str())
+    }
+
+    /// Checks whether a key is the deprecated name of another key.
+    fn is_alias(&self, section: &str, key: &str) -> bool {
+        self.aliases.iter().any(|(alias_section, old_key, _)| alias_section == section && old_key == key)
+    }
+
     /// Validates a configuration against the schema.
     ///
+    /// The configuration is never modified, and every error is collected
+    /// instead of stopping at the first one.
+    ///
     /// # Arguments
     ///
     /// * `config` - A reference to the `Config` instance to validate.
@@ -729,80 +1777,247 @@ impl ValidationSchema {
     /// * `Ok(())` - If the configuration is valid.
     /// * `Err(ValidationErrors)` - If validation errors are found.
     pub fn validate(&self, config: &Config) -> Result<(), ValidationErrors> {
-        let mut errors = Vec::new();
+        let errors: Vec<ValidationError> = self.findings(config).into_iter()
+            .filter(|finding| finding.severity == Severity::Error)
+            .map(|finding| finding.error)
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(errors))
+        }
+    }
+
+    /// Checks whether a configuration file on disk satisfies the schema.
+    ///
+    /// The file is loaded into a throwaway `Config`, the schema defaults are
+    /// applied and the result is validated, as done by `Config::load_and_validate`.
+    /// This is meant for lint tools and CI checks that do not need the values.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A reference to a `Path` representing the file to check.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - If the file is loaded and valid.
+    /// * `Err(ConfigError)` - If the file cannot be loaded, or if it does not
+    ///   satisfy the schema, in which case the validation errors are reported.
+    pub fn validate_file(&self, path: &Path) -> Result<(), ConfigError> {
+        Config::load_and_validate("validate_file", path, self).map(|_| ())
+    }
+
+    /// Lints a configuration against the schema.
+    ///
+    /// Like `validate`, this method does not modify the configuration and
+    /// collects every problem. Unknown sections and keys are always reported:
+    /// as errors when the schema rejects them, and as informational findings
+    /// when `allow_unknown_sections` or `allow_unknown_keys` accept them.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A reference to the `Config` instance to lint.
+    ///
+    /// # Returns
+    ///
+    /// A `LintReport` containing every finding.
+    pub fn validate_all(&self, config: &Config) -> LintReport {
+        LintReport { findings: self.findings(config) }
+    }
+
+    /// Collects the findings of a configuration against the schema.
+    fn findings(&self, config: &Config) -> Vec<Finding> {
+        let mut findings = Vec::new();
 
         // Check required sections.
         for section in &self.required_sections {
             if !config.values.contains_key(section) {
-                errors.push(ValidationError::MissingSection {
+                findings.push(Finding::error(ValidationError::MissingSection {
                     section: section.clone(),
-                });
+                }));
             }
         }
 
         // Validate each section in the configuration.
         for (section_name, section_values) in &config.values {
             // Handle undefined sections.
-            if !self.sections.contains_key(section_name) {
-                if !self.allow_unknown_sections {
-                    errors.push(ValidationError::UnknownSection {
-                        section: section_name.clone(),
-                    });
-                }
+            let Some(section_schema) = self.sections.get(section_name) else {
+                findings.push(Finding::new(Severity::of_unknown(self.allow_unknown_sections),
+                                           ValidationError::UnknownSection {
+                                               section: section_name.clone(),
+                                           }));
                 continue;
-            }
+            };
 
-            // Validate fields in the section.
-            if let Some(section_schema) = self.sections.get(section_name) {
-                // Check for required fields.
-                for (field_name, field_def) in section_schema {
-                    let field_path = format!("{}.{}", section_name, field_name);
-                    let field_value = section_values.get(field_name);
+            // Check the fields of the section, reading deprecated keys for missing ones.
+            for (field_name, field_def) in section_schema {
+                let field_path = format!("{}.{}", section_name, field_name);
+                let field_value = section_values.get(field_name).or_else(|| {
+                    self.alias_of(section_name, field_name).and_then(|old_key| section_values.get(old_key))
+                });
 
-                    if let Err(err) = field_def.validate(field_value, &field_path) {
-                        errors.push(err);
-                    }
+                if let Err(err) = field_def.validate_in(field_value, &field_path, Some(config),
+                                                     self.profile.as_deref()) {
+                    findings.push(Finding::error(err));
                 }
+            }
 
-                // Check for undefined keys if necessary.
-                if !self.allow_unknown_keys {
-                    for key in section_values.keys() {
-                        if !section_schema.contains_key(key) {
-                            errors.push(ValidationError::UnknownKey {
-                                section: section_name.clone(),
-                                key: key.clone(),
-                            });
-                        }
-                    }
+            // Check for undefined keys.
+            for key in section_values.keys() {
+                if !section_schema.contains_key(key) && !self.is_alias(section_name, key) {
+                    findings.push(Finding::new(Severity::of_unknown(self.allow_unknown_keys),
+                                               ValidationError::UnknownKey {
+                                                   section: section_name.clone(),
+                                                   key: key.clone(),
+                                               }));
                 }
             }
         }
 
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(ValidationErrors(errors))
+        // Report the deprecated keys.
+        for (section, old_key, new_key) in &self.aliases {
+            if config.get(section, old_key).is_some() {
+                findings.push(Finding::new(Severity::Warning, deprecated_key(section, old_key, new_key)));
+            }
+        }
+
+        // Check the groups of alternative keys.
+        for (section, keys) in &self.exactly_one {
+            let present: Vec<String> = keys.iter()
+                .filter(|key| !matches!(config.get(section, key), None | Some(ConfigValue::Null)))
+                .cloned()
+                .collect();
+            if present.len() != 1 {
+                findings.push(Finding::error(ValidationError::ExactlyOneOf {
+                    section: section.clone(),
+                    keys: keys.clone(),
+                    present,
+                }));
+            }
+        }
+
+        // Check the configuration-wide rules.
+        for rule in &self.rules {
+            if let Err(message) = rule.validate(config) {
+                findings.push(Finding::error(ValidationError::RuleFailed { message }));
+            }
         }
+
+        findings
     }
 
+    /// Reports which fields of the schema are set in a configuration.
+    ///
+    /// Unlike `validate`, this method does not check the values: it lists every
+    /// field defined in the schema and whether the configuration sets it, would
+    /// fall back to its default, or leaves it missing. It is meant to be used on
+    /// a configuration before defaults are applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A reference to the `Config` instance to inspect.
+    ///
+    /// # Returns
+    ///
+    /// A `SchemaReport` with the status of each field, sorted by path.
+    pub fn report(&self, config: &Config) -> SchemaReport {
+        let mut fields = Vec::new();
+
+        for (section_name, section_schema) in &self.sections {
+            for (field_name, field_def) in section_schema {
+                let status = if config.get(section_name, field_name).is_some() {
+                    FieldStatus::Present
+                } else if field_def.has_default() {
+                    FieldStatus::Default
+                } else {
+                    FieldStatus::Missing
+                };
+
+                fields.push(FieldReport {
+                    path: format!("{}.{}", section_name, field_name),
+                    status,
+                    required: field_def.is_required_in(self.profile.as_deref()),
+                });
+            }
+        }
+
+        fields.sort_by(|a, b| a.path.cmp(&b.path));
+        SchemaReport { fields }
+    }
+
+    /// Returns the fraction of schema fields set in a configuration.
+    ///
+    /// Fields that would fall back to their default value are not counted as
+    /// set, so like `report` this is meant to be used before defaults are applied.
+    /// The configuration is not validated nor modified.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A reference to the `Config` instance to inspect.
+    ///
+    /// # Returns
+    ///
+    /// A value between `0.0` and `1.0`; a schema without fields has a coverage of `1.0`.
+    pub fn coverage(&self, config: &Config) -> f64 {
+        let report = self.report(config);
+        if report.fields.is_empty() {
+            return 1.0;
+        }
+        let present = report.paths_with(FieldStatus::Present).len();
+        present as f64 / report.fields.len() as f64
+    }
 
     /// Applies default values to missing fields in the configuration.
     ///
     /// This method iterates through the schema's sections and fields, checking if each field
     /// has a default value and is missing in the provided configuration. If so, it sets the
-    /// default value in the configuration.
+    /// default value in the configuration. Defaults derived from other fields, set with
+    /// `FieldDefinition::default_from`, are applied last, so they see the other defaults.
     ///
     /// # Arguments
     ///
     /// * `config` - A mutable reference to the `Config` instance where default values will be applied.
     pub fn apply_defaults(&self, config: &mut Config) {
-        for (section_name, section_fields) in &self.sections {
+        self.copy_aliases(config, None);
+        for derived in [false, true] {
+            for section_name in self.sections.keys() {
+                self.apply_section_defaults(config, section_name, derived);
+            }
+        }
+    }
+
+    /// Applies default values to missing fields of a single section.
+    ///
+    /// This method behaves like `apply_defaults`, but only considers the fields
+    /// defined for the given section. Other sections of the configuration are left
+    /// untouched, which is useful when sections are added incrementally (e.g. a
+    /// plugin section registered at runtime).
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - A mutable reference to the `Config` instance where default values will be applied.
+    /// * `section` - The name of the section whose defaults should be applied.
+    pub fn apply_defaults_for_section(&self, config: &mut Config, section: &str) {
+        self.copy_aliases(config, Some(section));
+        for derived in [false, true] {
+            self.apply_section_defaults(config, section, derived);
+        }
+    }
+
+    /// Applies the derived or the other default values to missing fields of a section.
+    fn apply_section_defaults(&self, config: &mut Config, section: &str, derived: bool) {
+        if let Some(section_fields) = self.sections.get(section) {
             for (field_name, field_def) in section_fields {
-                // If the field has a default value and is not present in the configuration
-                if let Some(default_value) = &field_def.default_value {
-                    if !config.values.get(section_name).map_or(false, |s| s.contains_key(field_name)) {
+                if field_def.default_from.is_some() != derived {
+                    continue;
+                }
+
+                // If the field is not present in the configuration and has a default value
+                if !config.values.get(section).map_or(false, |s| s.contains_key(field_name)) {
+                    if let Some(default_value) = field_def.resolve_default_in(config) {
                         // Add the default value
-                        config.set(section_name, field_name, default_value.clone());
+                        config.set(section, field_name, default_value);
                     }
                 }
             }
@@ -861,7 +2076,7 @@ pub enum ValidationError {
     /// * `path` - The path of the field.
     /// * `expected` - The expected value type.
     /// * `actual` - The actual value type.
-    #[error("Type mismatch for {path}: expected {expected:?}, found {actual:?}")]
+    #[error("Type mismatch for {path}: expected {expected}, found {actual}")]
     TypeMismatch {
         path: String,
         expected: ValueType,
@@ -907,6 +2122,33 @@ pub enum ValidationError {
         value: String,
     },
 
+    /// Error for a pattern that cannot be compiled.
+    ///
+    /// # Fields
+    /// * `pattern` - The invalid pattern.
+    /// * `message` - The error of the regex compiler.
+    #[error("Invalid pattern {pattern}: {message}")]
+    InvalidPattern {
+        pattern: String,
+        message: String,
+    },
+
+    /// Error for a constraint attached to a value of another type.
+    ///
+    /// This usually reveals a mistake in the schema, such as an integer
+    /// constraint on a string field.
+    ///
+    /// # Fields
+    /// * `path` - The path of the field.
+    /// * `constraint` - The type of the values the constraint applies to.
+    /// * `actual` - The actual type of the value.
+    #[error("Constraint type mismatch for {path}: {constraint} constraint on a {actual} value")]
+    ConstraintTypeMismatch {
+        path: String,
+        constraint: ValueType,
+        actual: ValueType,
+    },
+
     /// Error for an invalid value in a field.
     ///
     /// # Fields
@@ -959,6 +2201,34 @@ pub enum ValidationError {
         actual: i64,
     },
 
+    /// Error for an integer that does not fit in the target integer type.
+    ///
+    /// # Fields
+    /// * `path` - The path of the field.
+    /// * `width` - The target integer type.
+    /// * `actual` - The actual value.
+    #[error("Integer out of range for {path}: {actual} does not fit in {width}")]
+    IntegerOutOfWidth {
+        path: String,
+        width: IntegerWidth,
+        actual: i64,
+    },
+
+    /// Error for an integer that is not on the step of its constraint.
+    ///
+    /// # Fields
+    /// * `path` - The path of the field.
+    /// * `step` - The step between valid values.
+    /// * `offset` - The offset of the valid values.
+    /// * `actual` - The actual value.
+    #[error("Integer not on step for {path}: expected {offset} + a multiple of {step}, actual {actual}")]
+    IntegerNotOnStep {
+        path: String,
+        step: i64,
+        offset: i64,
+        actual: i64,
+    },
+
     /// Error for a float that is too small.
     ///
     /// # Fields
@@ -1011,6 +2281,88 @@ pub enum ValidationError {
         actual: usize,
     },
 
+    /// Error for an array item equal to an earlier one.
+    ///
+    /// # Fields
+    /// * `path` - The path of the field.
+    /// * `index` - The index of the first duplicated item.
+    /// * `first_index` - The index of the earlier item it repeats.
+    #[error("Duplicate item in {path}: item {index} repeats item {first_index}")]
+    ArrayDuplicateItem {
+        path: String,
+        index: usize,
+        first_index: usize,
+    },
+
+    /// Error for a table with too few entries.
+    ///
+    /// # Fields
+    /// * `path` - The path of the field.
+    /// * `min` - The minimum allowed number of entries.
+    /// * `actual` - The actual number of entries.
+    #[error("Table too small for {path}: minimum entries {min}, actual {actual}")]
+    TableTooSmall {
+        path: String,
+        min: usize,
+        actual: usize,
+    },
+
+    /// Error for a table with too many entries.
+    ///
+    /// # Fields
+    /// * `path` - The path of the field.
+    /// * `max` - The maximum allowed number of entries.
+    /// * `actual` - The actual number of entries.
+    #[error("Table too large for {path}: maximum entries {max}, actual {actual}")]
+    TableTooLarge {
+        path: String,
+        max: usize,
+        actual: usize,
+    },
+
+    /// Error for a string that is not in the expected format.
+    ///
+    /// # Fields
+    /// * `path` - The path of the field.
+    /// * `format` - The expected format.
+    /// * `value` - The actual string value.
+    /// * `reason` - Why the value is not in the expected format.
+    #[error("Invalid {format} for {path}: {value:?} ({reason})")]
+    InvalidFormat {
+        path: String,
+        format: StringFormat,
+        value: String,
+        reason: String,
+    },
+
+    /// Error for a filesystem path that fails its check.
+    ///
+    /// # Fields
+    /// * `path` - The path of the field.
+    /// * `check` - The failed check.
+    /// * `value` - The filesystem path held by the field.
+    /// * `reason` - Why the check failed.
+    #[error("Expected {check} for {path}: {value:?} ({reason})")]
+    InvalidPath {
+        path: String,
+        check: PathCheck,
+        value: String,
+        reason: String,
+    },
+
+    /// Error for a value naming a section or key that does not exist.
+    ///
+    /// # Fields
+    /// * `path` - The path of the field.
+    /// * `target` - The kind of name expected.
+    /// * `value` - The name held by the field.
+    #[error("Dangling reference for {path}: no {target} named {value:?}")]
+    DanglingReference {
+        path: String,
+        target: ReferenceTarget,
+        value: String,
+    },
+
     /// Error for a custom constraint that failed.
     ///
     /// # Fields
@@ -1023,6 +2375,59 @@ pub enum ValidationError {
         description: String,
         message: String,
     },
+
+    /// Error for a configuration-wide rule that failed.
+    ///
+    /// # Fields
+    /// * `message` - The error message from the rule.
+    #[error("Rule failed: {message}")]
+    RuleFailed {
+        message: String,
+    },
+
+    /// Warning for a key set under a deprecated name.
+    ///
+    /// # Fields
+    /// * `section` - The section containing the key.
+    /// * `old_key` - The deprecated name of the key.
+    /// * `new_key` - The current name of the key.
+    #[error("Deprecated key {section}.{old_key}: use {section}.{new_key} instead")]
+    DeprecatedKey {
+        section: String,
+        old_key: String,
+        new_key: String,
+    },
+
+    /// Error for a group of alternative keys not set exactly once.
+    ///
+    /// # Fields
+    /// * `section` - The section containing the keys.
+    /// * `keys` - The alternative keys.
+    /// * `present` - The keys that are set, none or more than one.
+    #[error("Exactly one of {} must be set in {section}, found {}", .keys.join(", "), key_list(.present))]
+    ExactlyOneOf {
+        section: String,
+        keys: Vec<String>,
+        present: Vec<String>,
+    },
+}
+
+/// Formats a list of keys for an error message, `none` if it is empty.
+fn key_list(keys: &[String]) -> String {
+    if keys.is_empty() {
+        "none".to_string()
+    } else {
+        keys.join(", ")
+    }
+}
+
+/// Creates the warning for a key set under a deprecated name.
+fn deprecated_key(section: &str, old_key: &str, new_key: &str) -> ValidationError {
+    ValidationError::DeprecatedKey {
+        section: section.to_string(),
+        old_key: old_key.to_string(),
+        new_key: new_key.to_string(),
+    }
 }
 
 /// Collection of validation errors.
@@ -1052,6 +2457,139 @@ impl ValidationErrors {
     }
 }
 
+/// Severity of a lint finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The configuration is invalid
+    Error,
+    /// The configuration is valid, but uses a deprecated feature
+    Warning,
+    /// The configuration is valid, but the finding may deserve attention
+    Info,
+}
+
+impl Severity {
+    /// Returns the severity of an unknown section or key, given whether it is allowed
+    fn of_unknown(allowed: bool) -> Self {
+        if allowed { Severity::Info } else { Severity::Error }
+    }
+}
+
+/// A single finding of `ValidationSchema::validate_all`
+#[derive(Debug)]
+pub struct Finding {
+    /// Severity of the finding
+    pub severity: Severity,
+    /// Description of the problem
+    pub error: ValidationError,
+}
+
+impl Finding {
+    /// Creates a new finding
+    fn new(severity: Severity, error: ValidationError) -> Self {
+        Finding { severity, error }
+    }
+
+    /// Creates a new error finding
+    fn error(error: ValidationError) -> Self {
+        Finding::new(Severity::Error, error)
+    }
+}
+
+/// Lint report of a configuration against a schema.
+///
+/// This structure is returned by `ValidationSchema::validate_all` and lists
+/// every finding, including the informational ones that `validate` ignores.
+#[derive(Debug)]
+pub struct LintReport {
+    /// Findings in the order they were found
+    pub findings: Vec<Finding>,
+}
+
+impl LintReport {
+    /// Returns the errors of the report
+    pub fn errors(&self) -> Vec<&ValidationError> {
+        self.with_severity(Severity::Error)
+    }
+
+    /// Returns the warnings of the report
+    pub fn warnings(&self) -> Vec<&ValidationError> {
+        self.with_severity(Severity::Warning)
+    }
+
+    /// Returns the informational findings of the report
+    pub fn infos(&self) -> Vec<&ValidationError> {
+        self.with_severity(Severity::Info)
+    }
+
+    /// Checks whether the report contains no errors
+    pub fn is_valid(&self) -> bool {
+        self.findings.iter().all(|f| f.severity != Severity::Error)
+    }
+
+    /// Returns the findings with the given severity
+    fn with_severity(&self, severity: Severity) -> Vec<&ValidationError> {
+        self.findings.iter()
+            .filter(|f| f.severity == severity)
+            .map(|f| &f.error)
+            .collect()
+    }
+}
+
+/// Presence status of a schema field in a configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldStatus {
+    /// The field is set in the configuration
+    Present,
+    /// The field is not set, and its default value would be used
+    Default,
+    /// The field is not set and has no default value
+    Missing,
+}
+
+/// Status of a single schema field
+#[derive(Debug, Clone)]
+pub struct FieldReport {
+    /// Path of the field (`section.key`)
+    pub path: String,
+    /// Presence status of the field
+    pub status: FieldStatus,
+    /// Indicates if the field is required by the schema
+    pub required: bool,
+}
+
+/// Coverage report of a configuration against a schema.
+///
+/// This structure is returned by `ValidationSchema::report` and lists, for each
+/// field defined in the schema, whether it is set in the configuration, relies
+/// on its default value, or is missing.
+#[derive(Debug, Clone)]
+pub struct SchemaReport {
+    /// Status of each field, sorted by path
+    pub fields: Vec<FieldReport>,
+}
+
+impl SchemaReport {
+    /// Returns the status of a field, if it is defined in the schema
+    pub fn status(&self, section: &str, key: &str) -> Option<FieldStatus> {
+        let path = format!("{}.{}", section, key);
+        self.fields.iter().find(|f| f.path == path).map(|f| f.status)
+    }
+
+    /// Returns the paths of the fields with the given status
+    pub fn paths_with(&self, status: FieldStatus) -> Vec<&str> {
+        self.fields.iter()
+            .filter(|f| f.status == status)
+            .map(|f| f.path.as_str())
+            .collect()
+    }
+
+    /// Checks whether every field of the schema is set in the configuration
+    pub fn is_complete(&self) -> bool {
+        self.fields.iter().all(|f| f.status == FieldStatus::Present)
+    }
+}
+
 /// Extension trait for `Config` to support validation.
 ///
 /// This trait provides methods to validate a configuration against a schema,
@@ -5,8 +5,9 @@ use std::fs;
 use std::path::PathBuf;
 use tempfile::{tempdir, NamedTempFile};
 
-//use confucius::{Config, ConfigValue, ConfigError, ConfigFormat};
-use confucius::{Config, ConfigValue, ConfigFormat};
+use confucius::{config_value, Config, ConfigValue, ConfigError, ConfigFormat, ValueSource};
+#[cfg(feature = "encryption")]
+use confucius::{Decryptor, Encryptor};
 
 // Una funzione helper per creare un file temporaneo con un contenuto specifico e tenerlo in vita
 // fino a quando non viene rilasciata
@@ -438,4 +439,1970 @@ fn test_config_value_conversions() {
     assert_eq!(bool_value.as_integer(), None);
     assert_eq!(bool_value.as_float(), None);
     assert_eq!(bool_value.as_boolean(), Some(true));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_interpolate_references() {
+    let mut config = Config::new("test");
+    config.set("server", "hostname", ConfigValue::String("localhost".to_string()));
+    config.set("server", "port", ConfigValue::Integer(8080));
+    config.set("api", "base_url", ConfigValue::String("http://${server.hostname}:${server.port}".to_string()));
+    config.set("api", "users_url", ConfigValue::String("${api.base_url}/users".to_string()));
+
+    let result = config.interpolate();
+    assert!(result.is_ok(), "Interpolazione fallita: {:?}", result.err());
+
+    // Entrambi i riferimenti devono essere espansi, anche in modo ricorsivo
+    assert_eq!(config.get_string("api", "base_url", None),
+               Some("http://localhost:8080".to_string()));
+    assert_eq!(config.get_string("api", "users_url", None),
+               Some("http://localhost:8080/users".to_string()));
+}
+
+#[test]
+fn test_interpolate_keeps_plain_tokens() {
+    let mut config = Config::new("test");
+    config.set("paths", "data", ConfigValue::String("/var/lib".to_string()));
+    config.set("paths", "cache", ConfigValue::String("${HOME}/.cache".to_string()));
+    config.set("paths", "backup", ConfigValue::String("${ HOME }/backup:${paths.data}".to_string()));
+
+    // I token senza punto non sono riferimenti e restano invariati
+    let result = config.interpolate();
+    assert!(result.is_ok(), "Interpolazione fallita: {:?}", result.err());
+    assert_eq!(config.get_string("paths", "cache", None), Some("${HOME}/.cache".to_string()));
+    assert_eq!(config.get_string("paths", "backup", None), Some("${ HOME }/backup:/var/lib".to_string()));
+}
+
+#[test]
+fn test_interpolate_cycle() {
+    let mut config = Config::new("test");
+    config.set("section", "a", ConfigValue::String("${section.b}".to_string()));
+    config.set("section", "b", ConfigValue::String("${section.a}".to_string()));
+
+    // Un ciclo di riferimenti deve produrre un errore
+    let result = config.interpolate();
+    assert!(matches!(result, Err(ConfigError::InterpolationError(_))),
+            "Dovrebbe dare un errore InterpolationError per un ciclo: {:?}", result);
+}
+
+#[test]
+fn test_ini_auto_type_disabled() {
+    let content = r#"#!config/ini
+[server]
+port = 8080
+version = 1.0
+name = "quoted"
+"#;
+
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.set_ini_auto_type(false);
+    let result = config.load_from_file(&file_path);
+    assert!(result.is_ok(), "Caricamento del file fallito: {:?}", result.err());
+
+    // Senza conversione automatica tutti i valori restano stringhe
+    assert_eq!(config.get("server", "port").and_then(|v| v.as_string()),
+               Some(&"8080".to_string()), "port dovrebbe essere una stringa");
+    assert_eq!(config.get("server", "version").and_then(|v| v.as_string()),
+               Some(&"1.0".to_string()), "version dovrebbe essere una stringa");
+    assert_eq!(config.get("server", "name").and_then(|v| v.as_string()),
+               Some(&"quoted".to_string()), "name dovrebbe essere senza virgolette");
+}
+
+#[test]
+fn test_push_array() {
+    let mut config = Config::new("test");
+
+    // La chiave non esiste: viene creato un nuovo array
+    assert!(config.push("auth", "users", ConfigValue::String("admin".to_string())).is_ok());
+    assert_eq!(config.get_array("auth", "users").map(|a| a.len()), Some(1));
+
+    // La chiave esiste: il valore viene aggiunto in coda
+    assert!(config.push("auth", "users", ConfigValue::String("user1".to_string())).is_ok());
+    let users = config.get_array("auth", "users").expect("users non trovata");
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[1].as_string(), Some(&"user1".to_string()));
+
+    // La chiave contiene un valore che non è un array
+    config.set("auth", "enabled", ConfigValue::Boolean(true));
+    let result = config.push("auth", "enabled", ConfigValue::Boolean(false));
+    assert!(matches!(result, Err(ConfigError::TypeError(_))),
+            "Dovrebbe dare un errore TypeError per un valore non array");
+}
+
+#[test]
+fn test_no_trailing_newline() {
+    // File senza newline finale, in tutti i formati supportati
+    let cases = [
+        ("#!config/ini\n[section]\nkey = value", "section", "key"),
+        ("[section]\nkey = value", "section", "key"),
+        ("#!config/toml\n[section]\nkey = \"value\"", "section", "key"),
+        ("#!config/yaml\nsection:\n  key: value", "section", "key"),
+        ("#!config/json\n{\"section\": {\"key\": \"value\"}}", "section", "key"),
+        ("#!config/ini\nkey = value", "default", "key"),
+    ];
+
+    for (content, section, key) in cases {
+        let (_file, file_path) = create_temp_file(content);
+
+        let mut config = Config::new("test");
+        let result = config.load_from_file(&file_path);
+        assert!(result.is_ok(), "Caricamento del file fallito: {:?}", result.err());
+
+        // L'ultima riga deve essere caricata anche senza newline finale
+        assert_eq!(config.get_string(section, key, None), Some("value".to_string()),
+                   "Ultima chiave persa per il contenuto:\n{}", content);
+    }
+}
+
+#[test]
+fn test_no_trailing_newline_with_include() {
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+
+    // Sia il file principale che quello incluso terminano senza newline
+    let main_path = temp_dir.path().join("main.conf");
+    let included_path = temp_dir.path().join("included.conf");
+    fs::write(&main_path, "#!config/ini\n[main]\nkey1 = \"main value\"\ninclude=included.conf")
+        .expect("Impossibile scrivere file main");
+    fs::write(&included_path, "#!config/ini\n[included]\nkey2 = \"included value\"")
+        .expect("Impossibile scrivere file included");
+
+    let mut config = Config::new("test");
+    let result = config.load_from_file(&main_path);
+    assert!(result.is_ok(), "Caricamento del file fallito: {:?}", result.err());
+
+    assert_eq!(config.get_string("main", "key1", None), Some("main value".to_string()));
+    assert_eq!(config.get_string("included", "key2", None), Some("included value".to_string()));
+}
+
+#[test]
+fn test_set_path() {
+    let mut config = Config::new("test");
+
+    // Impostiamo un percorso profondo su una configurazione vuota
+    let result = config.set_path("database.main.connection.host", ConfigValue::String("localhost".to_string()));
+    assert!(result.is_ok(), "set_path fallito: {:?}", result.err());
+
+    let main = config.get_table("database", "main").expect("database.main non trovata");
+    let host = match main.get("connection") {
+        Some(ConfigValue::Table(connection)) => connection.get("host").and_then(|v| v.as_string()).cloned(),
+        _ => None,
+    };
+    assert_eq!(host, Some("localhost".to_string()));
+
+    // Modifica in place tramite get_table_mut
+    config.get_table_mut("database", "main")
+        .expect("database.main non trovata")
+        .insert("port".to_string(), ConfigValue::Integer(5432));
+    assert_eq!(config.get_table("database", "main").and_then(|t| t.get("port")).and_then(|v| v.as_integer()),
+               Some(5432));
+
+    // Un segmento che non è una tabella deve dare errore
+    let result = config.set_path("database.main.port.value", ConfigValue::Integer(1));
+    assert!(matches!(result, Err(ConfigError::TypeError(_))),
+            "Dovrebbe dare un errore TypeError: {:?}", result);
+}
+
+/// Cifrario di prova: applica lo XOR con la chiave a ogni byte e codifica in esadecimale
+#[cfg(feature = "encryption")]
+struct XorCipher {
+    key: u8,
+}
+
+#[cfg(feature = "encryption")]
+impl Encryptor for XorCipher {
+    fn encrypt(&self, plaintext: &str) -> Result<String, String> {
+        Ok(plaintext.bytes().map(|b| format!("{:02x}", b ^ self.key)).collect())
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl Decryptor for XorCipher {
+    fn decrypt(&self, ciphertext: &str) -> Result<String, String> {
+        let bytes = (0..ciphertext.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&ciphertext[i..i + 2], 16).map(|b| b ^ self.key))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|e| e.to_string())?;
+        String::from_utf8(bytes).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn test_encrypted_values() {
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let save_path = temp_dir.path().join("secrets.conf");
+
+    // Salviamo un valore cifrato
+    let mut config = Config::new("test");
+    config.set_format(ConfigFormat::Ini);
+    config.set_encryptor(XorCipher { key: 42 });
+    config.set("auth", "jwt_secret", ConfigValue::String("super-secret".to_string()));
+    config.set("auth", "issuer", ConfigValue::String("confucius".to_string()));
+    config.mark_encrypted("auth", "jwt_secret");
+
+    let result = config.save_to_file(&save_path);
+    assert!(result.is_ok(), "Salvataggio del file fallito: {:?}", result.err());
+
+    // Il segreto non deve comparire in chiaro nel file
+    let content = fs::read_to_string(&save_path).expect("Impossibile leggere il file salvato");
+    assert!(!content.contains("super-secret"), "Il segreto è salvato in chiaro");
+    assert!(content.contains("jwt_secret = \"enc:"), "Manca il valore cifrato");
+
+    // Ricarichiamo con la chiave e leggiamo il testo in chiaro
+    let mut loaded = Config::new("test");
+    loaded.set_decryptor(XorCipher { key: 42 });
+    let result = loaded.load_from_file(&save_path);
+    assert!(result.is_ok(), "Caricamento del file fallito: {:?}", result.err());
+
+    assert_eq!(loaded.get_string("auth", "jwt_secret", None), Some("super-secret".to_string()));
+    assert_eq!(loaded.get_string("auth", "issuer", None), Some("confucius".to_string()));
+    assert!(loaded.is_encrypted("auth", "jwt_secret"));
+    assert!(!loaded.is_encrypted("auth", "issuer"));
+}
+
+#[test]
+fn test_reject_unknown_sections() {
+    let content = r#"#!config/ini
+[server]
+port = 8080
+
+[databse]
+host = "localhost"
+"#;
+
+    let (_file, file_path) = create_temp_file(content);
+
+    // Senza il rifiuto delle sezioni sconosciute il caricamento riesce
+    let mut config = Config::new("test");
+    config.set_known_sections(&["server", "database"]);
+    assert!(config.load_from_file(&file_path).is_ok());
+
+    // Con il rifiuto attivo la sezione con il refuso deve dare errore
+    let mut config = Config::new("test");
+    config.set_known_sections(&["server", "database"])
+        .set_reject_unknown_sections(true);
+    let result = config.load_from_file(&file_path);
+    match result {
+        Err(ConfigError::UnknownSection(section)) => assert_eq!(section, "databse"),
+        other => panic!("Dovrebbe dare un errore UnknownSection: {:?}", other),
+    }
+
+    // Il file rifiutato non lascia valori nella configurazione
+    assert!(config.get("databse", "host").is_none(), "La sezione sconosciuta non dovrebbe essere caricata");
+    assert!(config.get("server", "port").is_none(), "Il file rifiutato non dovrebbe lasciare valori");
+
+    // Solo le sezioni del file sono controllate, non quelle impostate prima del caricamento
+    let content = "#!config/json\n{\"server\": {\"port\": 8080, \"tls\": {\"cert\": \"a.pem\"}}}\n";
+    let (_file, file_path) = create_temp_file(content);
+    let mut config = Config::new("test");
+    config.set_known_sections(&["server"])
+        .set_reject_unknown_sections(true)
+        .set_nested_sections(true);
+    config.set("runtime", "mode", config_value!("debug"));
+    config.load_from_file(&file_path).expect("Le sezioni del file sono note");
+    // Le sotto-sezioni di una sezione nota sono accettate, anche ricaricando il file
+    assert_eq!(config.get_string("server.tls", "cert", None), Some("a.pem".to_string()));
+    config.load_from_file(&file_path).expect("Il ricaricamento dovrebbe riuscire");
+    assert_eq!(config.get_string("runtime", "mode", None), Some("debug".to_string()));
+}
+
+#[test]
+fn test_config_display() {
+    let mut config = Config::new("test");
+    config.set("server", "host", ConfigValue::String("localhost".to_string()));
+    config.set("server", "port", ConfigValue::Integer(8080));
+    config.set_path("server.tls.enabled", ConfigValue::Boolean(true)).unwrap();
+
+    // La rappresentazione deve essere leggibile, in stile INI
+    let dump = config.to_string();
+    assert!(dump.contains("[server]"), "Manca la sezione:\n{}", dump);
+    assert!(dump.contains("host = \"localhost\""), "Manca host:\n{}", dump);
+    assert!(dump.contains("port = 8080"), "Manca port:\n{}", dump);
+    assert!(dump.contains("tls.enabled = true"), "Manca la chiave annidata:\n{}", dump);
+}
+
+#[test]
+fn test_root_array_json_yaml() {
+    let json_content = r#"#!config/json
+[
+  {"name": "web", "port": 8080},
+  {"name": "api", "port": 9090}
+]
+"#;
+
+    let (_file, file_path) = create_temp_file(json_content);
+
+    // Un array JSON nella root viene salvato nella sezione root
+    let mut config = Config::new("test");
+    let result = config.load_from_file(&file_path);
+    assert!(result.is_ok(), "Caricamento del file JSON fallito: {:?}", result.err());
+
+    let items = config.get_array("default", confucius::ROOT_ARRAY_KEY).expect("Array root non trovato");
+    assert_eq!(items.len(), 2);
+    if let ConfigValue::Table(first) = &items[0] {
+        assert_eq!(first.get("port").and_then(|v| v.as_integer()), Some(8080));
+    } else {
+        panic!("Il primo elemento dovrebbe essere una tabella");
+    }
+
+    // Lo stesso vale per una sequenza YAML, anche con chiavi numeriche
+    let yaml_content = "#!config/yaml\n- 80: http\n  443: https\n- plain\n";
+    let (_file, file_path) = create_temp_file(yaml_content);
+
+    let mut config = Config::new("test");
+    let result = config.load_from_file(&file_path);
+    assert!(result.is_ok(), "Caricamento del file YAML fallito: {:?}", result.err());
+
+    let items = config.get_array("default", confucius::ROOT_ARRAY_KEY).expect("Array root non trovato");
+    assert_eq!(items.len(), 2);
+    if let ConfigValue::Table(ports) = &items[0] {
+        assert_eq!(ports.get("443").and_then(|v| v.as_string()), Some(&"https".to_string()));
+    } else {
+        panic!("Il primo elemento dovrebbe essere una tabella");
+    }
+}
+
+#[test]
+fn test_root_array_saved_as_root() {
+    let json_content = "#!config/json\n[{\"name\": \"web\", \"port\": 8080}, \"plain\"]\n";
+    let yaml_content = "#!config/yaml\n- name: web\n  port: 8080\n- plain\n";
+
+    for (content, root_start) in [(json_content, "["), (yaml_content, "- ")] {
+        let (_file, file_path) = create_temp_file(content);
+
+        let mut config = Config::new("test");
+        config.load_from_file(&file_path).expect("Caricamento fallito");
+        config.save_to_file(&file_path).expect("Salvataggio fallito");
+
+        // L'array torna la radice del documento, senza la chiave items
+        let saved = fs::read_to_string(&file_path).unwrap();
+        let body = saved.lines().nth(1).unwrap_or_default();
+        assert!(body.starts_with(root_start), "Array radice atteso:\n{}", saved);
+        assert!(!saved.contains(confucius::ROOT_ARRAY_KEY), "Chiave items inattesa:\n{}", saved);
+
+        let mut reloaded = Config::new("test");
+        reloaded.load_from_file(&file_path).expect("Ricaricamento fallito");
+        assert_eq!(reloaded.get_values(), config.get_values());
+
+        // Con altri valori l'array non può più essere la radice
+        reloaded.set("server", "port", config_value!(9090));
+        reloaded.save_to_file(&file_path).expect("Salvataggio fallito");
+        let mut mixed = Config::new("test");
+        mixed.load_from_file(&file_path).expect("Ricaricamento fallito");
+        assert_eq!(mixed.get_values(), reloaded.get_values());
+    }
+}
+
+#[test]
+fn test_custom_root_section() {
+    let content = r#"#!config/ini
+name = "app"
+
+[server]
+port = 8080
+"#;
+
+    let (_file, file_path) = create_temp_file(content);
+
+    // Le chiavi fuori da ogni sezione finiscono nella sezione root configurata
+    let mut config = Config::new("test");
+    config.set_root_section("global");
+    let result = config.load_from_file(&file_path);
+    assert!(result.is_ok(), "Caricamento del file fallito: {:?}", result.err());
+
+    assert_eq!(config.get_root_section(), "global");
+    assert_eq!(config.get_string("global", "name", None), Some("app".to_string()));
+    assert!(config.get("default", "name").is_none());
+}
+
+#[test]
+fn test_get_or_insert_with() {
+    let mut config = Config::new("test");
+    config.set("server", "port", ConfigValue::Integer(8080));
+
+    // La chiave esiste: la closure non deve essere eseguita
+    let mut calls = 0;
+    let port = config.get_or_insert_with("server", "port", || {
+        calls += 1;
+        ConfigValue::Integer(9090)
+    }).as_integer();
+    assert_eq!(port, Some(8080));
+    assert_eq!(calls, 0, "La closure non dovrebbe essere eseguita");
+
+    // La chiave manca: la closure viene eseguita e il valore salvato
+    let workers = config.get_or_insert_with("server", "workers", || {
+        calls += 1;
+        ConfigValue::Integer(4)
+    }).as_integer();
+    assert_eq!(workers, Some(4));
+    assert_eq!(calls, 1, "La closure dovrebbe essere eseguita una volta");
+    assert_eq!(config.get_integer("server", "workers", None), Some(4));
+}
+
+#[test]
+fn test_yaml_default_section_roundtrip() {
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let file_path = temp_dir.path().join("config.yaml");
+
+    // Sezione chiamata "default" con una tabella annidata e una chiave che
+    // coincide con il nome di un'altra sezione
+    let mut config = Config::new("test");
+    config.set_format(ConfigFormat::Yaml);
+    let mut pool = std::collections::HashMap::new();
+    pool.insert("size".to_string(), ConfigValue::Integer(10));
+    config.set("default", "pool", ConfigValue::Table(pool));
+    config.set("default", "server", ConfigValue::String("primary".to_string()));
+    config.set("server", "port", ConfigValue::Integer(8080));
+
+    config.save_to_file(&file_path).expect("Impossibile salvare il file YAML");
+
+    let mut reloaded = Config::new("test");
+    reloaded.load_from_file(&file_path).expect("Impossibile ricaricare il file YAML");
+
+    // La sezione "default" deve sopravvivere come sezione
+    assert_eq!(reloaded.get_string("default", "server", None), Some("primary".to_string()));
+    assert_eq!(reloaded.get_integer("server", "port", None), Some(8080));
+    assert!(reloaded.get("pool", "size").is_none(), "La tabella non deve diventare una sezione");
+    match reloaded.get("default", "pool") {
+        Some(ConfigValue::Table(table)) => assert_eq!(table.get("size").and_then(|v| v.as_integer()), Some(10)),
+        other => panic!("Tabella attesa, trovato {:?}", other),
+    }
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn test_get_datetime() {
+    use chrono::{TimeZone, Utc};
+
+    let mut config = Config::new("test");
+    config.set("maintenance", "window_start", ConfigValue::String("2024-05-01T23:30:00+01:00".to_string()));
+    config.set("maintenance", "malformed", ConfigValue::String("2024-05-01 23:30".to_string()));
+    config.set("maintenance", "number", ConfigValue::Integer(1714602600));
+
+    // Il timestamp viene convertito in UTC
+    assert_eq!(config.get_datetime("maintenance", "window_start"),
+               Some(Utc.with_ymd_and_hms(2024, 5, 1, 22, 30, 0).unwrap()));
+
+    // Valori non validi o mancanti restituiscono None
+    assert_eq!(config.get_datetime("maintenance", "malformed"), None);
+    assert_eq!(config.get_datetime("maintenance", "number"), None);
+    assert_eq!(config.get_datetime("maintenance", "missing"), None);
+}
+
+#[test]
+fn test_get_string_null_empty_absent() {
+    let content = r#"#!config/json
+{
+  "app": {
+    "empty": "",
+    "nothing": null
+  }
+}"#;
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Impossibile caricare il file JSON");
+
+    // null viene caricato come ConfigValue::Null, non come stringa vuota
+    assert!(matches!(config.get("app", "nothing"), Some(ConfigValue::Null)));
+
+    // get_string usa il default sia per null che per chiave assente
+    assert_eq!(config.get_string("app", "nothing", Some("fallback")), Some("fallback".to_string()));
+    assert_eq!(config.get_string("app", "absent", Some("fallback")), Some("fallback".to_string()));
+    assert_eq!(config.get_string("app", "empty", Some("fallback")), Some("".to_string()));
+
+    // get_string_strict restituisce Some("") solo per una vera stringa vuota
+    assert_eq!(config.get_string_strict("app", "empty"), Some("".to_string()));
+    assert_eq!(config.get_string_strict("app", "nothing"), None);
+    assert_eq!(config.get_string_strict("app", "absent"), None);
+}
+
+#[test]
+fn test_flatten() {
+    let mut config = Config::new("test");
+    config.set_path("database.main.host", ConfigValue::String("db.local".to_string())).unwrap();
+    config.set_path("database.main.port", ConfigValue::Integer(5432)).unwrap();
+    config.set("server", "hosts", ConfigValue::Array(vec![
+        ConfigValue::String("a".to_string()),
+        ConfigValue::String("b".to_string()),
+    ]));
+    config.set("server", "debug", ConfigValue::Boolean(false));
+    config.set("server", "empty", ConfigValue::Array(vec![]));
+
+    let flat: Vec<(String, String)> = config.flatten()
+        .into_iter()
+        .map(|(path, value)| (path, value.to_string()))
+        .collect();
+
+    // Percorsi puntati per le tabelle, indici per gli array, ordinati per percorso
+    let expected = [
+        ("database.main.host", "db.local"),
+        ("database.main.port", "5432"),
+        ("server.debug", "false"),
+        ("server.hosts[0]", "a"),
+        ("server.hosts[1]", "b"),
+    ];
+    let expected: Vec<(String, String)> = expected.iter()
+        .map(|(p, v)| (p.to_string(), v.to_string()))
+        .collect();
+    assert_eq!(flat, expected);
+}
+
+#[test]
+fn test_ini_colon_separator() {
+    let content = "#!config/ini\n[server]\nhost: localhost\nport: 8080\nurl: \"http://localhost:8080\"\n";
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.set_ini_separator(':');
+    config.load_from_file(&file_path).expect("Impossibile caricare il file INI con ':'");
+
+    assert_eq!(config.get_string("server", "host", None), Some("localhost".to_string()));
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+    // Solo il primo ':' separa chiave e valore
+    assert_eq!(config.get_string("server", "url", None), Some("http://localhost:8080".to_string()));
+
+    // Il salvataggio usa lo stesso separatore
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let save_path = temp_dir.path().join("saved.ini");
+    config.save_to_file(&save_path).expect("Impossibile salvare il file INI");
+
+    let saved = fs::read_to_string(&save_path).unwrap();
+    assert!(saved.contains("port: 8080"), "Separatore ':' atteso:\n{}", saved);
+    assert!(!saved.contains(" = "), "Separatore '=' inatteso:\n{}", saved);
+}
+
+#[test]
+fn test_get_boolean_from_string() {
+    let mut config = Config::new("test");
+    config.set("features", "real", ConfigValue::Boolean(true));
+    config.set("features", "yes", ConfigValue::String("yes".to_string()));
+    config.set("features", "off", ConfigValue::String("OFF".to_string()));
+    config.set("features", "invalid", ConfigValue::String("maybe".to_string()));
+
+    // Booleano vero e stringa "yes" restituiscono entrambi true
+    assert_eq!(config.get_boolean("features", "real", None), Some(true));
+    assert_eq!(config.get_boolean("features", "yes", None), Some(true));
+    assert_eq!(config.get_boolean("features", "off", None), Some(false));
+
+    // Una stringa non riconosciuta usa il default
+    assert_eq!(config.get_boolean("features", "invalid", None), None);
+    assert_eq!(config.get_boolean("features", "invalid", Some(false)), Some(false));
+}
+
+#[test]
+fn test_toml_inline_table_ini_roundtrip() {
+    let content = r#"#!config/toml
+[main]
+app = { name = "demo", version = "1.0", limits = { workers = 4 } }
+"#;
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.set_ini_dotted_keys(true);
+    config.load_from_file(&file_path).expect("Impossibile caricare il file TOML");
+
+    // TOML -> INI: la tabella inline diventa una sottosezione, le tabelle interne chiavi puntate
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let ini_path = temp_dir.path().join("config.ini");
+    config.set_format(ConfigFormat::Ini);
+    config.save_to_file(&ini_path).expect("Impossibile salvare il file INI");
+
+    let ini_content = fs::read_to_string(&ini_path).unwrap();
+    assert!(ini_content.contains("[main.app]\n"), "Sottosezione attesa:\n{}", ini_content);
+    assert!(ini_content.contains("name = \"demo\""), "Chiave attesa:\n{}", ini_content);
+    assert!(ini_content.contains("limits.workers = 4"), "Chiave puntata attesa:\n{}", ini_content);
+
+    // INI -> TOML: le chiavi puntate tornano una tabella
+    let mut from_ini = Config::new("test");
+    from_ini.set_ini_dotted_keys(true);
+    from_ini.load_from_file(&ini_path).expect("Impossibile ricaricare il file INI");
+    let toml_path = temp_dir.path().join("config.toml");
+    from_ini.set_format(ConfigFormat::Toml);
+    from_ini.save_to_file(&toml_path).expect("Impossibile salvare il file TOML");
+
+    let mut roundtrip = Config::new("test");
+    roundtrip.load_from_file(&toml_path).expect("Impossibile ricaricare il file TOML");
+    let app = roundtrip.get_table("main", "app").expect("La tabella app dovrebbe esistere");
+    assert_eq!(app.get("name").and_then(|v| v.as_string()), Some(&"demo".to_string()));
+    assert_eq!(app.get("version").and_then(|v| v.as_string()), Some(&"1.0".to_string()));
+    match app.get("limits") {
+        Some(ConfigValue::Table(limits)) => assert_eq!(limits.get("workers").and_then(|v| v.as_integer()), Some(4)),
+        other => panic!("Tabella limits attesa, trovato {:?}", other),
+    }
+}
+
+#[test]
+fn test_input_limits() {
+    let content = format!("#!config/ini\n[main]\n{}", (0..50).map(|i| format!("key{} = {}\n", i, i)).collect::<String>());
+    let (_file, file_path) = create_temp_file(&content);
+
+    // File più grande del limite: errore pulito prima della lettura
+    let mut config = Config::new("test");
+    config.set_max_input_bytes(64);
+    match config.load_from_file(&file_path) {
+        Err(ConfigError::ParseError(message)) => assert!(message.contains("too large"), "Messaggio inatteso: {}", message),
+        other => panic!("ParseError atteso, trovato {:?}", other),
+    }
+    assert!(config.get("main", "key0").is_none(), "Nessun valore dovrebbe essere caricato");
+
+    // Troppe chiavi
+    let mut config = Config::new("test");
+    config.set_max_total_keys(10);
+    match config.load_from_file(&file_path) {
+        Err(ConfigError::ParseError(message)) => assert!(message.contains("Too many keys"), "Messaggio inatteso: {}", message),
+        other => panic!("ParseError atteso, trovato {:?}", other),
+    }
+    assert!(config.get("main", "key0").is_none(), "Il file rifiutato non dovrebbe lasciare valori");
+
+    // Il file rifiutato non altera la configurazione già caricata
+    let mut config = Config::new("test");
+    config.set("app", "name", ConfigValue::String("demo".to_string()));
+    config.set_max_total_keys(10);
+    assert!(config.load_from_file(&file_path).is_err());
+    assert_eq!(config.get_string("app", "name", None), Some("demo".to_string()));
+    assert!(config.get("main", "key0").is_none(), "Il file rifiutato non dovrebbe lasciare valori");
+
+    // Entro i limiti il caricamento riesce
+    let mut config = Config::new("test");
+    config.set_max_input_bytes(4096).set_max_total_keys(50);
+    assert!(config.load_from_file(&file_path).is_ok());
+    assert_eq!(config.get_integer("main", "key49", None), Some(49));
+
+    // Le voci delle tabelle annidate e gli elementi degli array contano, a ogni livello
+    let content = r#"#!config/json
+{"main": {"items": [1, 2, 3, [4, 5]], "nested": {"a": {"b": 1, "c": 2}}}}
+"#;
+    let (_nested_file, nested_path) = create_temp_file(content);
+    // items, 4 elementi e 2 interni; nested, a, b e c: 11 valori
+    let mut config = Config::new("test");
+    config.set_max_total_keys(10);
+    assert!(matches!(config.load_from_file(&nested_path), Err(ConfigError::ParseError(_))));
+    let mut config = Config::new("test");
+    config.set_max_total_keys(11);
+    config.load_from_file(&nested_path).expect("Il file entro il limite dovrebbe essere caricato");
+}
+
+#[test]
+fn test_find_key() {
+    let mut config = Config::new("test");
+    config.set("database", "password", ConfigValue::String("db_secret".to_string()));
+    config.set("smtp", "password", ConfigValue::String("smtp_secret".to_string()));
+    config.set("smtp", "user", ConfigValue::String("mailer".to_string()));
+
+    // Tutte le occorrenze, ordinate per sezione
+    let found = config.find_key("password");
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0], ("database", &ConfigValue::String("db_secret".to_string())));
+    assert_eq!(found[1], ("smtp", &ConfigValue::String("smtp_secret".to_string())));
+
+    assert!(config.find_key("missing").is_empty());
+}
+
+#[test]
+fn test_load_with_bom() {
+    // File salvato con BOM UTF-8, come fanno alcuni editor Windows
+    let content = "\u{FEFF}#!config/ini\n[server]\nhost = \"localhost\"\nport = 8080\n";
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Impossibile caricare il file con BOM");
+
+    assert_eq!(config.get_format(), ConfigFormat::Ini);
+    assert_eq!(config.get_string("server", "host", None), Some("localhost".to_string()));
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+
+    // Lo stesso vale per i file inclusi, in ogni formato e tramite glob
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let dir = temp_dir.path();
+    fs::write(dir.join("db.conf"), "\u{FEFF}[db]\nhost = \"db.local\"\n").unwrap();
+    fs::write(dir.join("db.json"), "\u{FEFF}#!config/json\n{\"db\": {\"host\": \"db.local\"}}\n").unwrap();
+    fs::write(dir.join("db.toml"), "\u{FEFF}#!config/toml\n[db]\nhost = \"db.local\"\n").unwrap();
+    fs::write(dir.join("db.yaml"), "\u{FEFF}#!config/yaml\ndb:\n  host: db.local\n").unwrap();
+    fs::create_dir(dir.join("frags")).unwrap();
+    fs::write(dir.join("frags/log.conf"), "\u{FEFF}#!config/ini\n[log]\nlevel = debug\n").unwrap();
+
+    let mains = [
+        ("main.conf", "#!config/ini\ninclude = db.conf\ninclude = frags/*.conf\n"),
+        ("main.json", "#!config/json\n{\"include\": [\"db.json\", \"frags/*.conf\"]}\n"),
+        ("main.toml", "#!config/toml\ninclude = [\"db.toml\", \"frags/*.conf\"]\n"),
+        ("main.yaml", "#!config/yaml\ninclude:\n  - db.yaml\n  - frags/*.conf\n"),
+    ];
+    for (name, content) in mains {
+        let main_path = dir.join(name);
+        fs::write(&main_path, content).unwrap();
+
+        let mut config = Config::new("test");
+        config.load_from_file(&main_path).unwrap_or_else(|e| panic!("{}: {:?}", name, e));
+        assert_eq!(config.get_string("db", "host", None), Some("db.local".to_string()), "{}", name);
+        assert_eq!(config.get_string("log", "level", None), Some("debug".to_string()), "{}", name);
+    }
+}
+
+#[test]
+fn test_to_env() {
+    let mut config = Config::new("test");
+    config.set("server", "port", ConfigValue::Integer(8080));
+    config.set("server", "motd", ConfigValue::String("it's up".to_string()));
+
+    // Nomi in maiuscolo, valori non sicuri per la shell tra apici
+    assert_eq!(config.to_env("myapp", "__"),
+               "MYAPP_SERVER__MOTD='it'\\''s up'\nMYAPP_SERVER__PORT=8080\n");
+}
+
+#[test]
+fn test_ini_heredoc() {
+    let content = r#"#!config/ini
+[hooks]
+pre_start = <<SCRIPT
+#!/bin/sh
+# commento dello script, non della configurazione
+if [ -f "$PIDFILE" ]; then
+    echo "already running" = true
+
+fi
+SCRIPT
+timeout = 30
+"#;
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Impossibile caricare il file INI");
+
+    // Il contenuto è letterale: commenti, righe vuote e indentazione inclusi
+    let script = "#!/bin/sh\n# commento dello script, non della configurazione\nif [ -f \"$PIDFILE\" ]; then\n    echo \"already running\" = true\n\nfi";
+    assert_eq!(config.get_string("hooks", "pre_start", None), Some(script.to_string()));
+    assert_eq!(config.get_integer("hooks", "timeout", None), Some(30));
+
+    // Il writer produce un heredoc che viene riletto identico
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let saved_path = temp_dir.path().join("hooks.ini");
+    config.save_to_file(&saved_path).expect("Impossibile salvare il file INI");
+
+    let mut reloaded = Config::new("test");
+    reloaded.load_from_file(&saved_path).expect("Impossibile ricaricare il file INI");
+    assert_eq!(reloaded.get_string("hooks", "pre_start", None), Some(script.to_string()));
+
+    // Un heredoc senza terminatore è un errore
+    let (_bad_file, bad_path) = create_temp_file("[hooks]\nscript = <<END\necho\n");
+    let mut bad = Config::new("test");
+    assert!(matches!(bad.load_from_file(&bad_path), Err(ConfigError::ParseError(_))));
+}
+
+#[test]
+fn test_yaml_crlf_block_scalar() {
+    // File YAML scritto su Windows, con terminatori di riga \r\n
+    let content = "#!config/yaml\r\nscripts:\r\n  motd: |\r\n    Benvenuto\r\n      su confucius\r\n  name: demo\r\n";
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Impossibile caricare il file YAML");
+
+    assert_eq!(config.get_format(), ConfigFormat::Yaml);
+    assert_eq!(config.get_string("scripts", "motd", None), Some("Benvenuto\n  su confucius\n".to_string()));
+    assert_eq!(config.get_string("scripts", "name", None), Some("demo".to_string()));
+}
+
+#[test]
+fn test_get_integer_lossy() {
+    let mut config = Config::new("test");
+    config.set("pool", "workers", ConfigValue::Float(3.0));
+    config.set("pool", "ratio", ConfigValue::Float(3.5));
+    config.set("pool", "size", ConfigValue::Integer(8));
+
+    // Float senza parte frazionaria accettato, get_integer resta rigoroso
+    assert_eq!(config.get_integer_lossy("pool", "workers", None), Some(3));
+    assert_eq!(config.get_integer("pool", "workers", None), None);
+
+    // Nessun arrotondamento: 3.5 non è un intero e restituisce il default
+    assert_eq!(config.get_integer_lossy("pool", "ratio", None), None);
+    assert_eq!(config.get_integer_lossy("pool", "ratio", Some(1)), Some(1));
+
+    assert_eq!(config.get_integer_lossy("pool", "size", None), Some(8));
+    assert_eq!(config.get_integer_lossy("pool", "missing", Some(2)), Some(2));
+}
+
+#[test]
+fn test_toml_preserves_comments_on_save() {
+    let content = r#"#!config/toml
+# Impostazioni del server
+[server]
+host = "localhost"   # nome host
+port = 8080 # porta di ascolto
+
+# Livello di log
+[log]
+level = "info"
+"#;
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Impossibile caricare il file TOML");
+    config.set("server", "port", ConfigValue::Integer(9090));
+    config.set("log", "file", ConfigValue::String("app.log".to_string()));
+
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let saved_path = temp_dir.path().join("config.toml");
+    config.save_to_file(&saved_path).expect("Impossibile salvare il file TOML");
+
+    // Solo il valore modificato cambia, commenti e layout restano
+    let saved = fs::read_to_string(&saved_path).unwrap();
+    assert!(saved.contains("# Impostazioni del server\n[server]"), "Commento perso:\n{}", saved);
+    assert!(saved.contains("host = \"localhost\"   # nome host"), "Riga invariata alterata:\n{}", saved);
+    assert!(saved.contains("port = 9090 # porta di ascolto"), "Valore non aggiornato:\n{}", saved);
+    assert!(saved.contains("# Livello di log\n[log]"), "Commento perso:\n{}", saved);
+    assert!(saved.contains("file = \"app.log\""), "Nuova chiave mancante:\n{}", saved);
+
+    let mut reloaded = Config::new("test");
+    reloaded.load_from_file(&saved_path).expect("Impossibile ricaricare il file TOML");
+    assert_eq!(reloaded.get_integer("server", "port", None), Some(9090));
+    assert_eq!(reloaded.get_string("log", "file", None), Some("app.log".to_string()));
+}
+
+#[test]
+fn test_config_value_macro() {
+    // Array
+    let ports = config_value!([80, 443, (-1)]);
+    assert_eq!(ports, ConfigValue::Array(vec![
+        ConfigValue::Integer(80),
+        ConfigValue::Integer(443),
+        ConfigValue::Integer(-1),
+    ]));
+
+    // Tabella annidata, con variabili e null
+    let host = String::from("localhost");
+    let server = config_value!({
+        "host": host,
+        "ports": [80, 443],
+        "tls": { "enabled": true, "min_version": 1.2 },
+        "proxy": null,
+    });
+
+    let mut tls = std::collections::HashMap::new();
+    tls.insert("enabled".to_string(), ConfigValue::Boolean(true));
+    tls.insert("min_version".to_string(), ConfigValue::Float(1.2));
+    let mut expected = std::collections::HashMap::new();
+    expected.insert("host".to_string(), ConfigValue::String("localhost".to_string()));
+    expected.insert("ports".to_string(), ConfigValue::Array(vec![ConfigValue::Integer(80), ConfigValue::Integer(443)]));
+    expected.insert("tls".to_string(), ConfigValue::Table(tls));
+    expected.insert("proxy".to_string(), ConfigValue::Null);
+
+    assert_eq!(server, ConfigValue::Table(expected));
+    assert_eq!(config_value!({}), ConfigValue::Table(std::collections::HashMap::new()));
+}
+
+#[test]
+fn test_set_many_and_set_section() {
+    let mut config = Config::new("test");
+    config.set("database", "name", ConfigValue::String("app".to_string()));
+
+    // Impostazione di un'intera sezione: le chiavi esistenti restano
+    let mut database = std::collections::HashMap::new();
+    database.insert("host".to_string(), ConfigValue::String("db.local".to_string()));
+    database.insert("port".to_string(), ConfigValue::Integer(5432));
+    database.insert("pool".to_string(), ConfigValue::Integer(10));
+    config.set_section("database", database);
+
+    assert_eq!(config.get_string("database", "host", None), Some("db.local".to_string()));
+    assert_eq!(config.get_integer("database", "port", None), Some(5432));
+    assert_eq!(config.get_integer("database", "pool", None), Some(10));
+    assert_eq!(config.get_string("database", "name", None), Some("app".to_string()));
+
+    // Tuple (sezione, chiave, valore) su più sezioni, applicate in ordine
+    config.set_many((0..3).map(|i| ("workers".to_string(), format!("worker{}", i), ConfigValue::Integer(i))))
+        .set_many(vec![("database".to_string(), "pool".to_string(), ConfigValue::Integer(20))]);
+
+    assert_eq!(config.get_integer("workers", "worker0", None), Some(0));
+    assert_eq!(config.get_integer("workers", "worker2", None), Some(2));
+    assert_eq!(config.get_integer("database", "pool", None), Some(20));
+}
+
+#[test]
+fn test_toml_root_inline_table_is_section() {
+    let content = r#"#!config/toml
+title = "demo"
+app = { name = "Application with includes", version = "1.0.0" }
+"#;
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Impossibile caricare il file TOML");
+
+    // La tabella inline alla radice diventa una sezione, non una chiave di default
+    assert_eq!(config.get_string("app", "name", None), Some("Application with includes".to_string()));
+    assert_eq!(config.get_string("app", "version", None), Some("1.0.0".to_string()));
+    assert!(config.get("default", "app").is_none(), "app non dovrebbe finire nella sezione default");
+    assert_eq!(config.get_string("default", "title", None), Some("demo".to_string()));
+}
+
+#[test]
+fn test_lock_rejects_changes() {
+    let mut config = Config::new("test");
+    config.set("server", "port", ConfigValue::Integer(8080));
+    config.lock();
+    assert!(config.is_locked());
+
+    // Le modifiche dopo lock restituiscono un errore e non modificano i valori
+    match config.try_set("server", "port", ConfigValue::Integer(9090)) {
+        Err(ConfigError::Generic(message)) => assert_eq!(message, "config is locked"),
+        other => panic!("Errore atteso, trovato {:?}", other.map(|_| ())),
+    }
+    assert!(config.push("server", "hosts", ConfigValue::String("a".to_string())).is_err());
+    assert!(config.set_path("server.port", ConfigValue::Integer(9090)).is_err());
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+
+    // La lettura resta possibile
+    assert_eq!(config.get("server", "port"), Some(&ConfigValue::Integer(8080)));
+}
+
+// set su una configurazione bloccata va in panic anche nelle build di release
+#[test]
+#[should_panic(expected = "config is locked")]
+fn test_lock_set_panics() {
+    let mut config = Config::new("test");
+    config.lock();
+    config.set("server", "port", ConfigValue::Integer(9090));
+}
+
+#[test]
+fn test_lock_set_leaves_values_unchanged() {
+    let mut config = Config::new("test");
+    config.set("server", "port", ConfigValue::Integer(8080));
+    config.lock();
+
+    // Il set rifiutato non modifica il valore, in qualsiasi profilo di build
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        config.set("server", "port", ConfigValue::Integer(9090));
+    }));
+    assert!(result.is_err(), "set dopo lock dovrebbe fallire");
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+}
+
+#[test]
+fn test_config_value_get_and_index() {
+    // Tabella di array di tabelle
+    let value = config_value!({
+        "servers": [
+            { "host": "alpha", "ports": [80, 443] },
+            { "host": "beta", "ports": [8080] },
+        ],
+    });
+
+    let servers = value.get("servers").expect("Chiave servers mancante");
+    assert_eq!(servers.index(0).and_then(|s| s.get("host")).and_then(|h| h.as_string()),
+               Some(&"alpha".to_string()));
+    assert_eq!(servers.index(0).and_then(|s| s.get("ports")).and_then(|p| p.index(1))
+                   .and_then(|p| p.as_integer()), Some(443));
+    assert_eq!(servers.index(1).and_then(|s| s.get("ports")).and_then(|p| p.index(0))
+                   .and_then(|p| p.as_integer()), Some(8080));
+
+    // Chiavi e posizioni mancanti, o tipi sbagliati, danno None
+    assert!(value.get("missing").is_none());
+    assert!(servers.index(2).is_none());
+    assert!(servers.get("host").is_none());
+    assert!(value.index(0).is_none());
+}
+
+#[test]
+fn test_json_output_is_deterministic() {
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let first = temp_dir.path().join("first.json");
+    let second = temp_dir.path().join("second.json");
+
+    let mut config = Config::new("test");
+    config.set_format(ConfigFormat::Json);
+    for i in 0..20 {
+        config.set(&format!("section{}", i), "value", ConfigValue::Integer(i));
+        config.set("many", &format!("key{}", i), ConfigValue::Integer(i));
+    }
+    config.set("many", "table", config_value!({ "z": 1, "a": 2, "m": 3 }));
+
+    // Due salvataggi della stessa configurazione producono gli stessi byte
+    config.save_to_file(&first).expect("Impossibile salvare il primo file");
+    config.clone().save_to_file(&second).expect("Impossibile salvare il secondo file");
+    let content = fs::read(&first).expect("Impossibile leggere il primo file");
+    assert_eq!(content, fs::read(&second).expect("Impossibile leggere il secondo file"));
+
+    // Le chiavi sono ordinate
+    let text = String::from_utf8(content).expect("Output non UTF-8");
+    assert!(text.find("\"a\"").unwrap() < text.find("\"m\"").unwrap());
+    assert!(text.find("\"m\"").unwrap() < text.find("\"z\"").unwrap());
+
+    // L'output compatto sta su una sola riga dopo l'intestazione
+    config.set_json_pretty(false);
+    config.save_to_file(&first).expect("Impossibile salvare il file compatto");
+    let compact = fs::read_to_string(&first).expect("Impossibile leggere il file compatto");
+    assert_eq!(compact.lines().count(), 2);
+    assert!(compact.starts_with("#!config/json\n{\"many\":{"), "Output inatteso: {}", compact);
+
+    let mut loaded = Config::new("test");
+    loaded.load_from_file(&first).expect("Impossibile ricaricare il file compatto");
+    assert_eq!(loaded.get_integer("section7", "value", None), Some(7));
+}
+
+#[test]
+fn test_get_values_exposes_all_sections() {
+    let mut config = Config::new("test");
+    config.set("server", "host", ConfigValue::String("localhost".to_string()));
+    config.set("server", "port", ConfigValue::Integer(8080));
+    config.set("log", "level", ConfigValue::String("info".to_string()));
+
+    // La mappa grezza permette analisi non coperte dagli accessori
+    let values = config.get_values();
+    assert_eq!(values.len(), 2);
+
+    let mut keys: Vec<String> = values.iter()
+        .flat_map(|(section, keys)| keys.keys().map(move |key| format!("{}.{}", section, key)))
+        .collect();
+    keys.sort();
+    assert_eq!(keys, vec!["log.level", "server.host", "server.port"]);
+
+    let integers = values.values()
+        .flat_map(|keys| keys.values())
+        .filter(|value| value.as_integer().is_some())
+        .count();
+    assert_eq!(integers, 1);
+}
+
+#[test]
+fn test_ini_rejects_unrepresentable_values() {
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let path = temp_dir.path().join("out.conf");
+
+    // Le tabelle annidate sono scritte come chiavi puntate e rilette come tabelle
+    let mut config = Config::new("test");
+    config.set_format(ConfigFormat::Ini);
+    config.set("app", "db", config_value!({ "host": "localhost", "pool": { "size": 4 } }));
+    config.set("app", "methods", config_value!(["GET", "POST"]));
+    config.save_to_file(&path).expect("Le tabelle annidate dovrebbero essere salvate");
+
+    let mut loaded = Config::new("test");
+    loaded.load_from_file(&path).expect("Impossibile ricaricare il file INI");
+    let db = loaded.get("app", "db").expect("Tabella app.db mancante");
+    assert_eq!(db.get("pool").and_then(|p| p.get("size")).and_then(|s| s.as_integer()), Some(4));
+    // Gli array di scalari diventano stringhe
+    assert_eq!(loaded.get_string("app", "methods", None), Some("GET, POST".to_string()));
+
+    // Un array di tabelle non è rappresentabile: errore invece di un file corrotto
+    config.set("app", "servers", config_value!([{ "host": "alpha" }, { "host": "beta" }]));
+    let result = config.save_to_file(&path);
+    assert!(matches!(&result, Err(ConfigError::TypeError(msg)) if msg.contains("app.servers")),
+            "Errore inatteso: {:?}", result);
+
+    // Lo stesso vale per una tabella vuota dentro una sottosezione, con le chiavi puntate
+    let mut config = Config::new("test");
+    config.set_format(ConfigFormat::Ini);
+    config.set_ini_dotted_keys(true);
+    config.set("app", "db", config_value!({ "empty": {} }));
+    assert!(matches!(config.save_to_file(&path), Err(ConfigError::TypeError(_))));
+
+    // Senza chiavi puntate diventa una sottosezione vuota
+    config.set_ini_dotted_keys(false);
+    config.save_to_file(&path).expect("La tabella vuota dovrebbe essere salvata");
+    let mut loaded = Config::new("test");
+    loaded.load_from_file(&path).expect("Impossibile ricaricare il file INI");
+    assert_eq!(loaded.get("app", "db"), Some(&config_value!({ "empty": {} })));
+}
+
+#[test]
+fn test_get_path_expands_home() {
+    let home = home::home_dir().expect("Directory home non determinabile");
+
+    let mut config = Config::new("test");
+    config.set("paths", "data", ConfigValue::String("~/data".to_string()));
+    config.set("paths", "home", ConfigValue::String("~".to_string()));
+    config.set("paths", "absolute", ConfigValue::String("/var/lib/app".to_string()));
+    config.set("paths", "other_user", ConfigValue::String("~bob/data".to_string()));
+    config.set("paths", "port", ConfigValue::Integer(8080));
+
+    // Il ~ iniziale diventa la directory home
+    let data = config.get_path("paths", "data").expect("Percorso mancante");
+    assert!(data.starts_with(&home), "{} non inizia con {}", data.display(), home.display());
+    assert_eq!(data, home.join("data"));
+    assert_eq!(config.get_path("paths", "home"), Some(home.clone()));
+
+    // Gli altri percorsi restano invariati, e get_string non espande
+    assert_eq!(config.get_path("paths", "absolute"), Some(PathBuf::from("/var/lib/app")));
+    assert_eq!(config.get_path("paths", "other_user"), Some(PathBuf::from("~bob/data")));
+    assert_eq!(config.get_string("paths", "data", None), Some("~/data".to_string()));
+    assert_eq!(config.get_path("paths", "port"), None);
+    assert_eq!(config.get_path("paths", "missing"), None);
+}
+
+#[test]
+fn test_yaml_flow_scalar_arrays() {
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let path = temp_dir.path().join("out.yaml");
+
+    let mut config = Config::new("test");
+    config.set_format(ConfigFormat::Yaml);
+    config.set_yaml_flow_arrays(true);
+    config.set("http", "allowed_methods", config_value!(["GET", "POST", "a, b"]));
+    config.set("http", "routes", config_value!([{ "path": "/api", "ports": [80, 443] }]));
+    config.set("http", "matrix", config_value!([[1, 2], [3]]));
+    config.set("http", "limits", config_value!({ "codes": [429, 503] }));
+    config.set("http", "banner", config_value!("line one\nline two"));
+    config.save_to_file(&path).expect("Impossibile salvare il file YAML");
+
+    let content = fs::read_to_string(&path).expect("Impossibile leggere il file YAML");
+
+    // Gli array di scalari stanno su una riga, con le stringhe ambigue quotate
+    assert!(content.contains("  allowed_methods: [GET, POST, 'a, b']\n")
+                || content.contains("  allowed_methods: [GET, POST, \"a, b\"]\n"),
+            "Output inatteso:\n{}", content);
+    // Le tabelle e gli array annidati in un array restano in stile block
+    assert!(content.contains("  routes:\n    - "), "Output inatteso:\n{}", content);
+    assert!(content.contains("ports:\n") && !content.contains("[80, 443]"), "Output inatteso:\n{}", content);
+    assert!(content.contains("  matrix:\n    - - 1\n      - 2\n    - - 3\n"), "Output inatteso:\n{}", content);
+    // Fuori dagli array, anche in una tabella annidata, restano su una riga
+    assert!(content.contains("    codes: [429, 503]\n"), "Output inatteso:\n{}", content);
+
+    // Il file viene riletto con gli stessi valori
+    let mut loaded = Config::new("test");
+    loaded.load_from_file(&path).expect("Impossibile ricaricare il file YAML");
+    for key in ["allowed_methods", "routes", "matrix", "limits", "banner"] {
+        assert_eq!(loaded.get("http", key), config.get("http", key), "Valore diverso per {}", key);
+    }
+
+    // Senza l'opzione gli array restano in stile block
+    config.set_yaml_flow_arrays(false);
+    config.save_to_file(&path).expect("Impossibile salvare il file YAML");
+    let content = fs::read_to_string(&path).expect("Impossibile leggere il file YAML");
+    assert!(!content.contains("[GET"), "Output inatteso:\n{}", content);
+}
+
+#[test]
+fn test_try_get() {
+    let mut config = Config::new("test");
+    config.set("server", "host", ConfigValue::String("localhost".to_string()));
+    config.set("server", "port", ConfigValue::Integer(8080));
+
+    assert_eq!(config.try_get("server", "port").ok(), Some(&ConfigValue::Integer(8080)));
+    assert_eq!(config.try_get_string("server", "host").ok(), Some("localhost".to_string()));
+    assert_eq!(config.try_get_integer("server", "port").ok(), Some(8080));
+
+    // L'errore indica il percorso mancante
+    let err = config.try_get("database", "url").expect_err("La chiave non esiste");
+    assert!(matches!(err, ConfigError::Generic(_)));
+    assert!(err.to_string().contains("database.url"), "Messaggio inatteso: {}", err);
+    let err = config.try_get_integer("server", "workers").expect_err("La chiave non esiste");
+    assert!(err.to_string().contains("server.workers"), "Messaggio inatteso: {}", err);
+
+    // Un tipo diverso è un errore di tipo
+    let err = config.try_get_integer("server", "host").expect_err("Il valore non è un intero");
+    assert!(matches!(&err, ConfigError::TypeError(msg) if msg.contains("server.host")));
+    assert!(matches!(config.try_get_string("server", "port"), Err(ConfigError::TypeError(_))));
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn test_load_gzip_file() {
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let path = temp_dir.path().join("app.toml.gz");
+
+    // Il formato interno viene riconosciuto dal contenuto decompresso
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let content = format!("#!config/toml\n[server]\nhost = \"localhost\"\nport = 8080\n# {}\n", "x".repeat(2000));
+    encoder.write_all(content.as_bytes()).expect("Impossibile comprimere il contenuto");
+    fs::write(&path, encoder.finish().expect("Impossibile comprimere il contenuto"))
+        .expect("Impossibile scrivere il file compresso");
+
+    let mut config = Config::new("test");
+    config.load_from_file(&path).expect("Impossibile caricare il file compresso");
+    assert_eq!(config.get_format(), ConfigFormat::Toml);
+    assert_eq!(config.get_string("server", "host", None), Some("localhost".to_string()));
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+
+    // Il limite di dimensione vale anche per il contenuto decompresso
+    let mut config = Config::new("test");
+    config.set_max_input_bytes(500);
+    let result = config.load_from_file(&path);
+    assert!(matches!(&result, Err(ConfigError::ParseError(msg)) if msg.contains("decompressed")),
+            "Errore inatteso: {:?}", result);
+}
+
+#[test]
+fn test_map_strings() {
+    let mut config = Config::new("test");
+    config.set("server", "host", ConfigValue::String("localhost".to_string()));
+    config.set("server", "port", ConfigValue::Integer(8080));
+    config.set("server", "methods", config_value!(["get", "post"]));
+    config.set("server", "tls", config_value!({ "cert": "cert.pem", "enabled": true, "ciphers": ["aes"] }));
+
+    config.map_strings(|s| s.to_uppercase());
+
+    assert_eq!(config.get_string("server", "host", None), Some("LOCALHOST".to_string()));
+    assert_eq!(config.get("server", "methods"), Some(&config_value!(["GET", "POST"])));
+    // Anche le stringhe annidate cambiano, le chiavi e gli altri tipi no
+    assert_eq!(config.get("server", "tls"), Some(&config_value!({ "cert": "CERT.PEM", "enabled": true, "ciphers": ["AES"] })));
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+}
+
+#[test]
+fn test_include_format_prefix() {
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    fs::create_dir(temp_dir.path().join("data")).expect("Impossibile creare directory data");
+
+    // File INI senza estensione né intestazione
+    fs::write(temp_dir.path().join("data/settings"), "[extra]\nkey = \"from ini\"\nlevel = 3\n")
+        .expect("Impossibile scrivere il file incluso");
+    // Frammenti TOML senza intestazione, inclusi con un glob
+    fs::write(temp_dir.path().join("data/a.frag"), "[fragments]\na = 1\n")
+        .expect("Impossibile scrivere il frammento");
+    fs::write(temp_dir.path().join("data/b.frag"), "[fragments]\nb = [1, 2]\n")
+        .expect("Impossibile scrivere il frammento");
+
+    // Da un file INI
+    let main_path = temp_dir.path().join("main.conf");
+    fs::write(&main_path, "#!config/ini\n[main]\nkey = \"main\"\ninclude=ini:data/settings\n")
+        .expect("Impossibile scrivere il file principale");
+    let mut config = Config::new("test");
+    config.load_from_file(&main_path).expect("Impossibile caricare il file INI");
+    assert_eq!(config.get_string("extra", "key", None), Some("from ini".to_string()));
+
+    // Da un file YAML, che altrimenti leggerebbe il file incluso come YAML
+    let main_path = temp_dir.path().join("main.yaml");
+    fs::write(&main_path, "#!config/yaml\ninclude:\n  - ini:data/settings\n  - toml:data/*.frag\nmain:\n  key: main\n")
+        .expect("Impossibile scrivere il file principale");
+    let mut config = Config::new("test");
+    config.load_from_file(&main_path).expect("Impossibile caricare il file YAML");
+    assert_eq!(config.get_string("extra", "key", None), Some("from ini".to_string()));
+    assert_eq!(config.get_integer("extra", "level", None), Some(3));
+    assert_eq!(config.get_integer("fragments", "a", None), Some(1));
+    assert_eq!(config.get("fragments", "b"), Some(&config_value!([1, 2])));
+}
+
+#[test]
+fn test_config_value_ordering() {
+    // Prima per tipo: null, booleani, interi, float, stringhe, array, tabelle
+    let mut values = vec![
+        config_value!({ "b": 1 }),
+        config_value!("beta"),
+        config_value!([2]),
+        config_value!(2.5),
+        config_value!(10),
+        config_value!(true),
+        config_value!(null),
+        config_value!(f64::NAN),
+        config_value!(-1),
+        config_value!(f64::NEG_INFINITY),
+        config_value!("alpha"),
+        config_value!([1, 2]),
+        config_value!(false),
+        config_value!({ "a": 2 }),
+    ];
+    values.sort();
+
+    assert_eq!(values, vec![
+        config_value!(null),
+        config_value!(false),
+        config_value!(true),
+        config_value!(-1),
+        config_value!(10),
+        config_value!(f64::NEG_INFINITY),
+        config_value!(2.5),
+        config_value!(f64::NAN),
+        config_value!("alpha"),
+        config_value!("beta"),
+        config_value!([1, 2]),
+        config_value!([2]),
+        config_value!({ "a": 2 }),
+        config_value!({ "b": 1 }),
+    ]);
+
+    // Un intero precede sempre un float, e 0.0 è uguale a -0.0
+    assert!(config_value!(100) < config_value!(1.5));
+    assert_eq!(config_value!(0.0), config_value!(-0.0));
+    assert_ne!(config_value!(1), config_value!(1.0));
+}
+
+#[test]
+fn test_save_is_atomic() {
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let path = temp_dir.path().join("app.conf");
+    fs::write(&path, "#!config/ini\n[server]\nport = 1\n").expect("Impossibile scrivere il file");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).expect("Impossibile cambiare i permessi");
+    }
+
+    let mut config = Config::new("test");
+    config.load_from_file(&path).expect("Impossibile caricare il file");
+    config.set("server", "port", ConfigValue::Integer(8080));
+    config.set("server", "host", ConfigValue::String("localhost".to_string()));
+    config.save().expect("Impossibile salvare il file");
+    config.save().expect("Impossibile salvare di nuovo il file");
+
+    // Nessun file temporaneo rimasto nella directory
+    let entries: Vec<String> = fs::read_dir(temp_dir.path()).expect("Impossibile leggere la directory")
+        .map(|entry| entry.expect("Voce non valida").file_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(entries, vec!["app.conf".to_string()]);
+
+    // Il contenuto è integro
+    let mut loaded = Config::new("test");
+    loaded.load_from_file(&path).expect("Impossibile ricaricare il file");
+    assert_eq!(loaded.get_integer("server", "port", None), Some(8080));
+    assert_eq!(loaded.get_string("server", "host", None), Some("localhost".to_string()));
+
+    // I permessi del file originale sono mantenuti
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path).expect("Impossibile leggere i metadati").permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[test]
+fn test_get_percent() {
+    let mut config = Config::new("test");
+    config.set("limits", "memory", ConfigValue::String("80%".to_string()));
+    config.set("limits", "cpu", ConfigValue::String("100%".to_string()));
+    config.set("limits", "disk", ConfigValue::Float(0.5));
+    config.set("limits", "swap", ConfigValue::String(" 12.5 % ".to_string()));
+    config.set("limits", "invalid", ConfigValue::String("eighty%".to_string()));
+
+    assert_eq!(config.get_percent("limits", "memory"), Some(0.8));
+    assert_eq!(config.get_percent("limits", "cpu"), Some(1.0));
+    assert_eq!(config.get_percent("limits", "disk"), Some(0.5));
+    assert_eq!(config.get_percent("limits", "swap"), Some(0.125));
+    assert_eq!(config.get_percent("limits", "invalid"), None);
+    assert_eq!(config.get_percent("limits", "missing"), None);
+}
+
+#[test]
+fn test_unknown_format_fallback_keeps_header() {
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let path = temp_dir.path().join("app.conf");
+    fs::write(&path, "#!config/myformat v2\n[server]\nport = 8080\n").expect("Impossibile scrivere il file");
+
+    // Senza fallback il formato sconosciuto è un errore
+    let mut config = Config::new("test");
+    assert!(matches!(config.load_from_file(&path), Err(ConfigError::UnsupportedFormat(_))));
+
+    // Con il fallback INI il file viene letto e l'intestazione conservata
+    let mut config = Config::new("test");
+    config.set_unknown_format_fallback(ConfigFormat::Ini);
+    config.load_from_file(&path).expect("Il fallback INI dovrebbe leggere il file");
+    assert_eq!(config.get_format(), ConfigFormat::Ini);
+    assert_eq!(config.format_header(), Some("#!config/myformat v2"));
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+
+    config.set("server", "port", ConfigValue::Integer(9090));
+    config.save().expect("Impossibile salvare il file");
+    let content = fs::read_to_string(&path).expect("Impossibile leggere il file");
+    assert!(content.starts_with("#!config/myformat v2\n"), "Intestazione persa:\n{}", content);
+    assert!(!content.contains("#!config/ini"), "Intestazione duplicata:\n{}", content);
+
+    let mut reloaded = Config::new("test");
+    reloaded.set_unknown_format_fallback(ConfigFormat::Ini);
+    reloaded.load_from_file(&path).expect("Impossibile ricaricare il file");
+    assert_eq!(reloaded.get_integer("server", "port", None), Some(9090));
+
+    // Cambiando formato l'intestazione originale non vale più
+    config.set_format(ConfigFormat::Json);
+    assert_eq!(config.format_header(), None);
+}
+
+#[test]
+fn test_section_as_table() {
+    let mut config = Config::new("test");
+    config.set("database", "url", ConfigValue::String("postgres://localhost/app".to_string()));
+    config.set("database", "pool", config_value!({ "size": 10, "timeout": 30 }));
+
+    let table = config.section_as_table("database").expect("Sezione database mancante");
+    assert_eq!(table.get("url").and_then(|v| v.as_string()), Some(&"postgres://localhost/app".to_string()));
+    assert_eq!(table.get("pool").and_then(|p| p.get("size")).and_then(|s| s.as_integer()), Some(10));
+    assert_eq!(table, config_value!({
+        "url": "postgres://localhost/app",
+        "pool": { "size": 10, "timeout": 30 },
+    }));
+
+    assert_eq!(config.section_as_table("missing"), None);
+}
+
+#[test]
+fn test_ini_escape_sequences() {
+    let content = r#"#!config/ini
+[section]
+lines = "first\nsecond"
+path = "C:\\temp\\new"
+tab = "a\tb"
+accent = "caff\u00e8"
+unknown = "keep \q as is"
+"#;
+
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Caricamento del file fallito");
+
+    assert_eq!(config.get_string("section", "lines", None), Some("first\nsecond".to_string()));
+    assert_eq!(config.get_string("section", "path", None), Some("C:\\temp\\new".to_string()));
+    assert_eq!(config.get_string("section", "tab", None), Some("a\tb".to_string()));
+    assert_eq!(config.get_string("section", "accent", None), Some("caffè".to_string()));
+    // Le sequenze sconosciute restano invariate
+    assert_eq!(config.get_string("section", "unknown", None), Some("keep \\q as is".to_string()));
+
+    // Il salvataggio riscrive le sequenze di escape e il ricaricamento le ripristina
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("escaped.ini");
+    config.save_to_file(&path).expect("Salvataggio fallito");
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(saved.contains(r#"path = "C:\\temp\\new""#), "Backslash non riscritti:\n{}", saved);
+    assert!(saved.contains(r#"tab = "a\tb""#), "Tab non riscritto:\n{}", saved);
+
+    let mut reloaded = Config::new("test");
+    reloaded.load_from_file(&path).expect("Ricaricamento fallito");
+    for key in ["lines", "path", "tab", "accent", "unknown"] {
+        assert_eq!(reloaded.get("section", key), config.get("section", key), "{} non conservata", key);
+    }
+}
+
+#[test]
+fn test_get_owned_outlives_config() {
+    let mut config = Config::new("test");
+    config.set("server", "hosts", config_value!(["a.local", "b.local"]));
+    config.set("server", "limits", config_value!({ "rps": 100 }));
+    config.set("server", "name", ConfigValue::String("api".to_string()));
+
+    let name = config.get_owned("server", "name");
+    let hosts = config.get_array_owned("server", "hosts");
+    let limits = config.get_table_owned("server", "limits");
+
+    // I tipi non corrispondenti o le chiavi mancanti restituiscono None
+    assert_eq!(config.get_array_owned("server", "name"), None);
+    assert_eq!(config.get_table_owned("server", "hosts"), None);
+    assert_eq!(config.get_owned("server", "missing"), None);
+
+    // I valori restano utilizzabili dopo aver rilasciato la configurazione
+    drop(config);
+
+    assert_eq!(name, Some(ConfigValue::String("api".to_string())));
+    let hosts = hosts.expect("Array mancante");
+    assert_eq!(hosts.len(), 2);
+    assert_eq!(hosts[1].as_string(), Some(&"b.local".to_string()));
+    assert_eq!(limits.and_then(|l| l.get("rps").and_then(|v| v.as_integer())), Some(100));
+}
+
+#[test]
+fn test_ini_decimal_comma() {
+    let content = "#!config/ini\n[sensor]\nratio = 3,14\nplain = 2.5\nlist = 1,2,3\nquoted = \"0,5\"\nenabled = TRUE\n";
+    let (_file, file_path) = create_temp_file(content);
+
+    // Per default la virgola non è un separatore decimale
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Caricamento del file fallito");
+    assert_eq!(config.get("sensor", "ratio"), Some(&ConfigValue::String("3,14".to_string())));
+
+    let mut config = Config::new("test");
+    config.set_decimal_separator(',');
+    config.load_from_file(&file_path).expect("Caricamento del file fallito");
+
+    assert_eq!(config.get_float("sensor", "ratio", None), Some(314.0 / 100.0));
+    assert_eq!(config.get_float("sensor", "plain", None), Some(2.5));
+    // Più separatori o valori tra virgolette restano stringhe
+    assert_eq!(config.get("sensor", "list"), Some(&ConfigValue::String("1,2,3".to_string())));
+    assert_eq!(config.get("sensor", "quoted"), Some(&ConfigValue::String("0,5".to_string())));
+    assert_eq!(config.get_boolean("sensor", "enabled", None), Some(true));
+}
+
+#[test]
+fn test_get_or() {
+    let mut config = Config::new("test");
+    config.set("server", "port", ConfigValue::Integer(8080));
+
+    assert_eq!(config.get_or("server", "port", ConfigValue::Integer(80)), ConfigValue::Integer(8080));
+    assert_eq!(config.get_or("server", "host", ConfigValue::String("localhost".to_string())),
+               ConfigValue::String("localhost".to_string()));
+    assert_eq!(config.get_or("missing", "port", ConfigValue::Null), ConfigValue::Null);
+}
+
+#[test]
+fn test_save_if_changed() {
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let path = temp_dir.path().join("app.conf");
+
+    let mut config = Config::new("test");
+    config.set_format(ConfigFormat::Ini);
+    config.set("server", "port", ConfigValue::Integer(8080));
+
+    // Il file non esiste ancora: viene scritto
+    assert!(config.save_if_changed(&path).expect("Salvataggio fallito"));
+
+    // Data di modifica nel passato, per riconoscere una nuova scrittura
+    let past = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+    fs::File::options().write(true).open(&path).unwrap().set_modified(past).unwrap();
+
+    // Contenuto invariato: nessuna scrittura
+    assert!(!config.save_if_changed(&path).expect("Salvataggio fallito"));
+    assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), past);
+
+    // Contenuto cambiato: il file viene riscritto
+    config.set("server", "port", ConfigValue::Integer(9090));
+    assert!(config.save_if_changed(&path).expect("Salvataggio fallito"));
+    assert_ne!(fs::metadata(&path).unwrap().modified().unwrap(), past);
+
+    let mut loaded = Config::new("test");
+    loaded.load_from_file(&path).expect("Impossibile ricaricare il file");
+    assert_eq!(loaded.get_integer("server", "port", None), Some(9090));
+}
+
+#[test]
+fn test_ini_subsections() {
+    let content = r#"#!config/ini
+[database]
+host = "db.local"
+
+[database.pool]
+size = 10
+limits.timeout = 30
+
+[database.replica]
+"#;
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.set_ini_dotted_keys(true);
+    config.load_from_file(&file_path).expect("Caricamento del file fallito");
+
+    // La sottosezione diventa una tabella della sezione padre
+    assert_eq!(config.get_string("database", "host", None), Some("db.local".to_string()));
+    assert_eq!(config.get("database", "pool"), Some(&config_value!({ "size": 10, "limits": { "timeout": 30 } })));
+    assert_eq!(config.get("database", "replica"), Some(&config_value!({})));
+    assert!(config.get_values().get("database.pool").is_none());
+
+    // Il salvataggio usa la stessa sintassi, e la rilettura ritrova le stesse tabelle
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("subsections.ini");
+    config.save_to_file(&path).expect("Salvataggio fallito");
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("[database.pool]\nlimits.timeout = 30\nsize = 10\n"), "Sottosezione attesa:\n{}", saved);
+    assert!(saved.contains("[database.replica]\n"), "Sottosezione vuota attesa:\n{}", saved);
+
+    let mut reloaded = Config::new("test");
+    reloaded.set_ini_dotted_keys(true);
+    reloaded.load_from_file(&path).expect("Ricaricamento fallito");
+    assert_eq!(reloaded.get_values(), config.get_values());
+}
+
+#[test]
+fn test_config_value_iterators() {
+    let value = config_value!({
+        "pool": { "size": 10, "timeout": 30 },
+        "hosts": ["alpha", "beta"],
+    });
+
+    // Iterazione sulle voci di una tabella annidata
+    let pool = value.get("pool").unwrap();
+    let mut entries: Vec<(String, i64)> = pool.as_table_iter().expect("Tabella attesa")
+        .map(|(key, value)| (key.clone(), value.as_integer().unwrap()))
+        .collect();
+    entries.sort();
+    assert_eq!(entries, vec![("size".to_string(), 10), ("timeout".to_string(), 30)]);
+
+    // Iterazione sugli elementi di un array, in ordine
+    let hosts: Vec<&String> = value.get("hosts").and_then(|h| h.as_array_iter()).expect("Array atteso")
+        .filter_map(|host| host.as_string())
+        .collect();
+    assert_eq!(hosts, vec!["alpha", "beta"]);
+
+    // Tipi diversi non sono iterabili
+    assert!(pool.as_array_iter().is_none());
+    assert!(ConfigValue::Integer(1).as_table_iter().is_none());
+}
+
+#[test]
+fn test_nested_sections() {
+    let content = r#"#!config/json
+{
+    "database": {
+        "host": "db.local",
+        "pool": { "size": 10, "limits": { "timeout": 30 } },
+        "replicas": [{ "host": "r1" }]
+    }
+}
+"#;
+    let (_file, file_path) = create_temp_file(content);
+
+    // Per default le tabelle annidate restano valori
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Caricamento del file fallito");
+    assert!(config.get("database", "pool").is_some());
+    assert!(config.get("database.pool", "size").is_none());
+
+    let mut config = Config::new("test");
+    config.set_nested_sections(true);
+    config.load_from_file(&file_path).expect("Caricamento del file fallito");
+
+    assert_eq!(config.get_string("database", "host", None), Some("db.local".to_string()));
+    assert_eq!(config.get_integer("database.pool", "size", None), Some(10));
+    assert_eq!(config.get_integer("database.pool.limits", "timeout", None), Some(30));
+    assert!(config.get("database", "pool").is_none());
+    assert!(config.get("database.pool", "limits").is_none());
+    // Le tabelle dentro gli array restano valori
+    assert_eq!(config.get("database", "replicas"), Some(&config_value!([{ "host": "r1" }])));
+
+    // La provenienza segue le chiavi spostate
+    assert_eq!(config.value_source("database.pool", "size"), Some(ValueSource::File(file_path.clone())));
+}
+
+#[test]
+fn test_nested_sections_save_keeps_structure() {
+    let json = r#"#!config/json
+{
+    "database": {
+        "host": "db.local",
+        "pool": { "size": 10, "limits": { "timeout": 30 } }
+    }
+}
+"#;
+    let toml = r#"#!config/toml
+[database]
+host = "db.local"
+
+[database.pool]
+size = 10
+
+[database.pool.limits]
+timeout = 30
+"#;
+    let yaml = r#"#!config/yaml
+database:
+  host: db.local
+  pool:
+    size: 10
+    limits:
+      timeout: 30
+"#;
+
+    for (content, flat_key) in [(json, "\"database.pool\""), (toml, "[\"database.pool\"]"), (yaml, "database.pool:")] {
+        let (_file, file_path) = create_temp_file(content);
+
+        let mut config = Config::new("test");
+        config.set_nested_sections(true);
+        config.load_from_file(&file_path).expect("Caricamento del file fallito");
+        config.set("database.pool", "size", config_value!(20));
+        config.save_to_file(&file_path).expect("Salvataggio fallito");
+
+        // Le sotto-sezioni tornano tabelle annidate, non chiavi puntate
+        let saved = fs::read_to_string(&file_path).unwrap();
+        assert!(!saved.contains(flat_key), "struttura cambiata:\n{}", saved);
+
+        // Riletto senza sezioni annidate, il file ha la struttura originale
+        let mut reloaded = Config::new("test");
+        reloaded.load_from_file(&file_path).expect("Ricaricamento fallito");
+        assert!(reloaded.get_values().keys().all(|section| !section.contains('.')), "{}", saved);
+        assert_eq!(reloaded.get("database", "host"), Some(&config_value!("db.local")));
+        assert_eq!(reloaded.get("database", "pool"), Some(&config_value!({ "size": 20, "limits": { "timeout": 30 } })));
+
+        let mut nested = Config::new("test");
+        nested.set_nested_sections(true);
+        nested.load_from_file(&file_path).expect("Ricaricamento fallito");
+        assert_eq!(nested.get_integer("database.pool", "size", None), Some(20));
+        assert_eq!(nested.get_integer("database.pool.limits", "timeout", None), Some(30));
+    }
+}
+
+#[test]
+fn test_retain() {
+    let mut config = Config::new("test_app");
+    config.set("server", "host", config_value!("localhost"));
+    config.set("server", "deprecated_port", config_value!(80));
+    config.set("legacy", "deprecated_mode", config_value!(true));
+    config.set("app", "name", config_value!("demo"));
+
+    config.retain(|_, key, _| !key.starts_with("deprecated_"));
+
+    // Le chiavi con il prefisso sono rimosse, le altre restano
+    assert!(config.get("server", "deprecated_port").is_none());
+    assert_eq!(config.get_string("server", "host", None), Some("localhost".to_string()));
+    assert_eq!(config.get_string("app", "name", None), Some("demo".to_string()));
+
+    // La sezione rimasta vuota viene eliminata
+    assert!(config.section_as_table("legacy").is_none());
+}
+
+#[test]
+fn test_ini_quoted_keys() {
+    let content = r#"#!config/ini
+["odd]section"]
+"a=b" = 10
+"c#d" = "value" # commento
+"app.name" = "flat"
+app."x.y" = 2
+"#;
+
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.set_ini_dotted_keys(true);
+    config.load_from_file(&file_path).expect("Caricamento del file fallito");
+
+    // I nomi tra virgolette non sono divisi su separatori, punti o commenti
+    assert_eq!(config.get_integer("odd]section", "a=b", None), Some(10));
+    assert_eq!(config.get_string("odd]section", "c#d", None), Some("value".to_string()));
+    assert_eq!(config.get_string("odd]section", "app.name", None), Some("flat".to_string()));
+    let app = config.get_table("odd]section", "app").expect("Tabella app mancante");
+    assert_eq!(app.get("x.y"), Some(&config_value!(2)));
+
+    // Il salvataggio mette tra virgolette i nomi e il ricaricamento li ripristina
+    let mut written = Config::new("test");
+    written.set_format(ConfigFormat::Ini);
+    written.set("section", "a=b", config_value!("value"));
+    written.set("section", "include", config_value!("not a directive"));
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("quoted.ini");
+    written.save_to_file(&path).expect("Salvataggio fallito");
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(saved.contains(r#""a=b" = "value""#), "Chiave non quotata:\n{}", saved);
+
+    let mut reloaded = Config::new("test");
+    reloaded.load_from_file(&path).expect("Ricaricamento fallito");
+    assert_eq!(reloaded.get_string("section", "a=b", None), Some("value".to_string()));
+    assert_eq!(reloaded.get_string("section", "include", None), Some("not a directive".to_string()));
+}
+
+#[test]
+fn test_get_csv_array() {
+    let mut config = Config::new("test_app");
+    config.set("server", "hosts", config_value!("a, b, c"));
+    config.set("server", "empty", config_value!(""));
+    config.set("server", "ports", ConfigValue::Array(vec![config_value!("x"), config_value!(8080)]));
+    config.set("server", "port", config_value!(8080));
+
+    // La stringa separata da virgole diventa una lista di elementi senza spazi
+    assert_eq!(config.get_csv_array("server", "hosts"),
+               Some(vec!["a".to_string(), "b".to_string(), "c".to_string()]));
+    assert_eq!(config.get_csv_array("server", "empty"), Some(Vec::new()));
+
+    // Un vero array viene restituito come stringhe
+    assert_eq!(config.get_csv_array("server", "ports"), Some(vec!["x".to_string(), "8080".to_string()]));
+
+    assert_eq!(config.get_csv_array("server", "port"), None);
+    assert_eq!(config.get_csv_array("server", "missing"), None);
+}
+
+#[test]
+fn test_debug_redacts_sensitive_values() {
+    let mut config = Config::new("test_app");
+    config.set("database", "password", config_value!("hunter2"));
+    config.set("database", "API_TOKEN", config_value!("abc123"));
+    config.set("database", "host", config_value!("db.example.com"));
+    config.set("database", "options", config_value!({ "client_secret": "s3cr3t", "timeout": 30 }));
+
+    // I valori sensibili sono mascherati, anche nelle tabelle annidate, sia con {:?} che con {}
+    for output in [format!("{:?}", config), format!("{}", config)] {
+        assert!(!output.contains("hunter2"), "Password visibile:\n{}", output);
+        assert!(!output.contains("abc123"), "Token visibile:\n{}", output);
+        assert!(!output.contains("s3cr3t"), "Segreto annidato visibile:\n{}", output);
+        assert!(output.contains("***"));
+        assert!(output.contains("db.example.com"));
+    }
+
+    // Le parole sensibili sono configurabili
+    config.set_sensitive_keys(&["host"]);
+    for output in [format!("{:?}", config), format!("{}", config)] {
+        assert!(output.contains("hunter2"));
+        assert!(!output.contains("db.example.com"), "Host visibile:\n{}", output);
+    }
+}
+
+#[test]
+fn test_ini_align() {
+    let mut config = Config::new("test_app");
+    config.set_format(ConfigFormat::Ini);
+    config.set("server", "host", config_value!("localhost"));
+    config.set("server", "max_connections", config_value!(100));
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("aligned.ini");
+
+    // Senza allineamento le chiavi non vengono riempite
+    config.save_to_file(&path).expect("Salvataggio fallito");
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("host = \"localhost\"\n"), "Output inatteso:\n{}", saved);
+
+    // Con l'allineamento la chiave più corta viene riempita fino al separatore
+    config.set_ini_align(true);
+    config.save_to_file(&path).expect("Salvataggio fallito");
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("host            = \"localhost\"\n"), "Output non allineato:\n{}", saved);
+    assert!(saved.contains("max_connections = 100\n"), "Output non allineato:\n{}", saved);
+
+    // Il file allineato viene riletto con gli stessi valori
+    let mut reloaded = Config::new("test_app");
+    reloaded.load_from_file(&path).expect("Ricaricamento fallito");
+    assert_eq!(reloaded.get_string("server", "host", None), Some("localhost".to_string()));
+    assert_eq!(reloaded.get_integer("server", "max_connections", None), Some(100));
+}
+
+#[test]
+fn test_get_array_first() {
+    let mut config = Config::new("test_app");
+    config.set("cluster", "servers", config_value!(["primary.local", "backup.local"]));
+    config.set("cluster", "ports", config_value!([8080, 8081]));
+    config.set("cluster", "flags", config_value!([true, false]));
+    config.set("cluster", "empty", ConfigValue::Array(Vec::new()));
+    config.set("cluster", "name", config_value!("main"));
+
+    // Il primo elemento viene restituito convertito
+    assert_eq!(config.get_array_first_string("cluster", "servers"), Some("primary.local".to_string()));
+    assert_eq!(config.get_array_first_integer("cluster", "ports"), Some(8080));
+    assert_eq!(config.get_array_first_boolean("cluster", "flags"), Some(true));
+
+    // Array vuoto, tipo errato, valore non array o chiave mancante
+    assert_eq!(config.get_array_first_string("cluster", "empty"), None);
+    assert_eq!(config.get_array_first_string("cluster", "ports"), None);
+    assert_eq!(config.get_array_first_string("cluster", "name"), None);
+    assert_eq!(config.get_array_first_string("cluster", "missing"), None);
+}
+
+#[test]
+fn test_toml_root_section_designated() {
+    let mut config = Config::new("test_app");
+    config.set_format(ConfigFormat::Toml);
+    config.set("meta", "schema_version", config_value!(2));
+    config.set("server", "port", config_value!(8080));
+
+    // Senza sezione radice designata tutte le chiavi finiscono in tabelle
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("root.toml");
+    config.save_to_file(&path).expect("Salvataggio fallito");
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("[meta]"), "Output inatteso:\n{}", saved);
+
+    // La sezione designata viene scritta alla radice del documento
+    config.set_root_section("meta");
+    config.save_to_file(&path).expect("Salvataggio fallito");
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(!saved.contains("[meta]"), "Sezione radice scritta come tabella:\n{}", saved);
+    let root_key = saved.find("schema_version = 2").expect("Chiave radice mancante");
+    let server_table = saved.find("[server]").expect("Tabella server mancante");
+    assert!(root_key < server_table, "La chiave non è alla radice:\n{}", saved);
+
+    // Rileggendo con la stessa sezione radice i valori tornano al loro posto
+    let mut reloaded = Config::new("test_app");
+    reloaded.set_root_section("meta");
+    reloaded.load_from_file(&path).expect("Ricaricamento fallito");
+    assert_eq!(reloaded.get_integer("meta", "schema_version", None), Some(2));
+    assert_eq!(reloaded.get_integer("server", "port", None), Some(8080));
+}
+
+#[test]
+fn test_ini_dotted_key_conflict() {
+    // Una chiave puntata sotto un valore non tabella è un errore, non una sovrascrittura
+    let content = "#!config/ini\n[section]\na = 1\na.b = 2\n";
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.set_ini_dotted_keys(true);
+    match config.load_from_file(&file_path) {
+        Err(ConfigError::ParseError(message)) => {
+            assert!(message.contains("section.a is not a table"), "Messaggio inatteso: {}", message);
+        },
+        other => panic!("ParseError atteso, trovato {:?}", other),
+    }
+
+    // Lo stesso vale per l'intestazione di una sottosezione
+    let content = "#!config/ini\n[server]\ntls = true\n[server.tls]\ncert = \"a.pem\"\n";
+    let (_file, file_path) = create_temp_file(content);
+    let mut config = Config::new("test");
+    assert!(matches!(config.load_from_file(&file_path), Err(ConfigError::ParseError(_))));
+
+    // Una chiave tra virgolette resta piatta
+    let content = "#!config/ini\n[section]\n\"log.file\" = \"app.log\"\n";
+    let (_file, file_path) = create_temp_file(content);
+    let mut config = Config::new("test");
+    config.set_ini_dotted_keys(true);
+    config.load_from_file(&file_path).expect("Caricamento fallito");
+    assert_eq!(config.get_string("section", "log.file", None), Some("app.log".to_string()));
+}
+
+#[test]
+fn test_yaml_preserves_comments_on_save() {
+    let content = r#"#!config/yaml
+# Impostazioni del server
+server:
+  host: localhost   # nome host
+  port: 8080 # porta di ascolto
+  tags:
+    - web
+    - api
+
+# Livello di log
+log:
+  level: info
+"#;
+    let (_file, file_path) = create_temp_file(content);
+
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Impossibile caricare il file YAML");
+    config.set("server", "port", ConfigValue::Integer(9090));
+    config.set("log", "file", ConfigValue::String("app.log".to_string()));
+
+    let temp_dir = tempdir().expect("Impossibile creare directory temporanea");
+    let saved_path = temp_dir.path().join("config.yaml");
+    config.save_to_file(&saved_path).expect("Impossibile salvare il file YAML");
+
+    // Solo il valore modificato cambia, commenti e layout restano
+    let saved = fs::read_to_string(&saved_path).unwrap();
+    assert!(saved.contains("# Impostazioni del server\nserver:\n"), "Commento perso:\n{}", saved);
+    assert!(saved.contains("  host: localhost   # nome host\n"), "Riga invariata alterata:\n{}", saved);
+    assert!(saved.contains("  port: 9090 # porta di ascolto\n"), "Valore non aggiornato:\n{}", saved);
+    assert!(saved.contains("  tags:\n    - web\n    - api\n"), "Sequenza alterata:\n{}", saved);
+    assert!(saved.contains("# Livello di log\nlog:\n  level: info\n  file: app.log\n"), "Nuova chiave mancante:\n{}", saved);
+
+    let mut reloaded = Config::new("test");
+    reloaded.load_from_file(&saved_path).expect("Impossibile ricaricare il file YAML");
+    assert_eq!(reloaded.get_integer("server", "port", None), Some(9090));
+    assert_eq!(reloaded.get_string("log", "file", None), Some("app.log".to_string()));
+}
+
+#[test]
+fn test_ini_dotted_keys_opt_in() {
+    let content = "#!config/ini\n[section]\nlog.file = app.log\n";
+    let (_file, file_path) = create_temp_file(content);
+
+    // Per default le chiavi puntate restano piatte, come nelle versioni precedenti
+    let mut config = Config::new("test");
+    config.load_from_file(&file_path).expect("Caricamento fallito");
+    assert_eq!(config.get_string("section", "log.file", None), Some("app.log".to_string()));
+    assert!(config.get("section", "log").is_none());
+
+    // E sono riscritte come erano, senza virgolette
+    config.save_to_file(&file_path).expect("Salvataggio fallito");
+    let saved = fs::read_to_string(&file_path).unwrap();
+    assert!(saved.contains("log.file = \"app.log\"\n"), "Chiave cambiata:\n{}", saved);
+
+    // Con l'opzione diventano tabelle
+    let mut dotted = Config::new("test");
+    dotted.set_ini_dotted_keys(true);
+    dotted.load_from_file(&file_path).expect("Caricamento fallito");
+    assert_eq!(dotted.get("section", "log"), Some(&config_value!({ "file": "app.log" })));
+
+    // Senza l'opzione le tabelle annidate sono scritte come sottosezioni e rilette uguali
+    let mut nested = Config::new("test");
+    nested.set_format(ConfigFormat::Ini);
+    nested.set("app", "db", config_value!({ "host": "localhost", "pool": { "size": 4 } }));
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("nested.ini");
+    nested.save_to_file(&path).expect("Salvataggio fallito");
+    let saved = fs::read_to_string(&path).unwrap();
+    assert!(saved.contains("[app.db.pool]\nsize = 4\n"), "Sottosezione attesa:\n{}", saved);
+
+    let mut reloaded = Config::new("test");
+    reloaded.load_from_file(&path).expect("Ricaricamento fallito");
+    assert_eq!(reloaded.get_values(), nested.get_values());
+}
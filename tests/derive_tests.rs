@@ -0,0 +1,62 @@
+//! Test per il binding delle configurazioni alle struct con #[derive(FromConfig)]
+//! Richiedono la feature `derive`
+#![cfg(feature = "derive")]
+
+use confucius::{Config, ConfigError, ConfigValue, FromConfig};
+use std::fs;
+use tempfile::tempdir;
+
+fn default_workers() -> u32 {
+    4
+}
+
+#[derive(Debug, FromConfig)]
+#[config(section = "server")]
+struct ServerSettings {
+    host: String,
+    #[config(key = "listen_port")]
+    port: u16,
+    #[config(default = "default_workers")]
+    workers: u32,
+    #[config(default)]
+    debug: bool,
+    #[config(section = "tls", key = "cert_file")]
+    cert: Option<String>,
+    allowed: Vec<String>,
+}
+
+#[test]
+fn test_derive_from_config() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("server.conf");
+    fs::write(&path, "#!config/toml\n[server]\nhost = \"0.0.0.0\"\nlisten_port = 8443\nallowed = [\"10.0.0.1\", \"10.0.0.2\"]\n").unwrap();
+
+    let mut config = Config::new("derive");
+    config.load_from_file(&path).expect("Impossibile caricare la configurazione");
+
+    let settings = ServerSettings::from_config(&config).expect("Binding fallito");
+    assert_eq!(settings.host, "0.0.0.0");
+    assert_eq!(settings.port, 8443);
+    // I campi mancanti usano i default, o None se facoltativi
+    assert_eq!(settings.workers, 4);
+    assert!(!settings.debug);
+    assert_eq!(settings.cert, None);
+    assert_eq!(settings.allowed, vec!["10.0.0.1", "10.0.0.2"]);
+
+    config.set("tls", "cert_file", ConfigValue::String("/etc/ssl/app.pem".to_string()));
+    config.set("server", "workers", ConfigValue::Integer(16));
+    let settings = ServerSettings::from_config(&config).unwrap();
+    assert_eq!(settings.cert.as_deref(), Some("/etc/ssl/app.pem"));
+    assert_eq!(settings.workers, 16);
+
+    // Tipo errato e campo obbligatorio mancante
+    config.set("server", "listen_port", ConfigValue::Integer(70000));
+    assert!(matches!(ServerSettings::from_config(&config), Err(ConfigError::TypeError(_))));
+
+    let mut empty = Config::new("derive");
+    empty.set("server", "listen_port", ConfigValue::Integer(80));
+    match ServerSettings::from_config(&empty) {
+        Err(ConfigError::Generic(message)) => assert!(message.contains("server.host"), "{}", message),
+        other => panic!("Errore inatteso: {:?}", other),
+    }
+}
@@ -0,0 +1,37 @@
+//! Test per la configurazione globale condivisa della libreria Confucius
+//! Sono in un file separato perché lo stato globale è condiviso da tutto il processo
+//! Richiedono la feature `global`
+#![cfg(feature = "global")]
+
+use confucius::{global, Config, ConfigValue};
+
+#[test]
+fn test_global_init_get_reload() {
+    assert!(global::try_get().is_none(), "La configurazione globale non dovrebbe essere inizializzata");
+    assert!(global::reload(Config::new("test")).is_err(), "Il reload senza init dovrebbe fallire");
+
+    // Inizializzazione e lettura
+    let mut config = Config::new("test");
+    config.set("server", "port", ConfigValue::Integer(8080));
+    global::init(config).expect("Impossibile inizializzare la configurazione globale");
+    assert!(global::is_initialized());
+
+    let snapshot = global::get();
+    assert_eq!(snapshot.get_integer("server", "port", None), Some(8080));
+
+    // Una seconda inizializzazione deve fallire
+    assert!(global::init(Config::new("test")).is_err());
+
+    // Il reload deve essere visibile anche da altri thread
+    let mut new_config = Config::new("test");
+    new_config.set("server", "port", ConfigValue::Integer(9090));
+    global::reload(new_config).expect("Impossibile ricaricare la configurazione globale");
+
+    let port = std::thread::spawn(|| global::get().get_integer("server", "port", None))
+        .join()
+        .expect("Il thread non dovrebbe andare in panic");
+    assert_eq!(port, Some(9090));
+
+    // Lo snapshot ottenuto prima del reload resta invariato
+    assert_eq!(snapshot.get_integer("server", "port", None), Some(8080));
+}
@@ -5,7 +5,8 @@ use std::fs;
 use std::path::PathBuf;
 use tempfile::{tempdir, TempDir};
 
-use confucius::{Config, ConfigValue, ConfigError, ConfigFormat};
+use confucius::{Config, ConfigValue, ConfigError, ConfigFormat, FormatHandler, ValueSource};
+use confucius::{FieldConstraint, FieldDefinition, ValidationSchema, ValueType};
 
 /// Struttura per gestire un ambiente di test con file di configurazione
 struct TestEnv {
@@ -123,7 +124,7 @@ fn test_config_search_paths() {
     assert!(result.is_ok(), "Caricamento del file fallito: {:?}", result.err());
 
     // Stampiamo il contenuto di config per debug
-    println!("Config dopo caricamento: {:?}", config);
+    println!("Config dopo caricamento:\n{}", config);
 
     // Otteniamo tutte le sezioni e chiavi
     // Stampiamo il contenuto manualmente
@@ -347,6 +348,201 @@ fn test_error_handling() {
             "Dovrebbe dare un errore IncludeError per pattern glob non valido");
 }
 
+#[test]
+fn test_optional_include() {
+    let env = TestEnv::new("optional");
+
+    // 1. Inclusione opzionale di un file mancante: deve essere ignorata
+    env.create_config_file(
+        "main.conf",
+        "#!config/ini\n[section]\nkey = \"value\"\ninclude?=local-overrides.conf\n"
+    );
+
+    let mut config = Config::new("optional");
+    let result = config.load_from_file(&env.path("main.conf"));
+    assert!(result.is_ok(), "L'inclusione opzionale mancante non dovrebbe dare errore: {:?}", result);
+    assert_eq!(config.get_string("section", "key", None), Some("value".to_string()));
+
+    // 2. Inclusione opzionale di un file esistente: deve essere caricata
+    env.create_config_file("local-overrides.conf", "#!config/ini\n[section]\nkey = \"override\"\n");
+
+    let mut config = Config::new("optional");
+    config.load_from_file(&env.path("main.conf")).expect("Impossibile caricare la configurazione");
+    assert_eq!(config.get_string("section", "key", None), Some("override".to_string()));
+
+    // 3. Inclusione opzionale di un file non valido: deve dare errore
+    env.create_config_file("broken.yaml", "#!config/yaml\nsection: [unclosed\n");
+    env.create_config_file("main.yaml", "#!config/yaml\ninclude?: broken.yaml\nsection:\n  key: value\n");
+
+    let mut config = Config::new("optional");
+    let result = config.load_from_file(&env.path("main.yaml"));
+    assert!(matches!(result, Err(ConfigError::ParseError(_))),
+            "Dovrebbe dare un errore ParseError per inclusione opzionale non valida");
+}
+
+#[test]
+fn test_include_before_after() {
+    let env = TestEnv::new("order");
+
+    env.create_config_file("defaults.conf", "#!config/ini\n[server]\nport = 1000\nhost = \"defaults\"\n");
+    env.create_config_file("overrides.conf", "#!config/ini\n[server]\nport = 3000\n");
+
+    // include_before dopo la chiave: la chiave del file principale vince comunque
+    env.create_config_file(
+        "before.conf",
+        "#!config/ini\n[server]\nport = 2000\ninclude_before=defaults.conf\n"
+    );
+    let mut config = Config::new("order");
+    config.load_from_file(&env.path("before.conf")).expect("Impossibile caricare before.conf");
+    assert_eq!(config.get_integer("server", "port", None), Some(2000));
+    assert_eq!(config.get_string("server", "host", None), Some("defaults".to_string()));
+
+    // include_after prima della chiave: il file incluso vince comunque
+    env.create_config_file(
+        "after.conf",
+        "#!config/ini\ninclude_after=overrides.conf\n[server]\nport = 2000\n"
+    );
+    let mut config = Config::new("order");
+    config.load_from_file(&env.path("after.conf")).expect("Impossibile caricare after.conf");
+    assert_eq!(config.get_integer("server", "port", None), Some(3000));
+
+    // Stessa precedenza nei formati strutturati
+    env.create_config_file(
+        "main.yaml",
+        "#!config/yaml\ninclude_before: defaults.conf\ninclude_after: overrides.conf\nserver:\n  port: 2000\n  host: main\n"
+    );
+    let mut config = Config::new("order");
+    config.load_from_file(&env.path("main.yaml")).expect("Impossibile caricare main.yaml");
+    assert_eq!(config.get_integer("server", "port", None), Some(3000));
+    assert_eq!(config.get_string("server", "host", None), Some("main".to_string()));
+}
+
+#[test]
+fn test_value_source() {
+    let env = TestEnv::new("source");
+
+    env.create_config_file("override.conf", "#!config/ini\n[server]\nport = 9090\n");
+    env.create_config_file(
+        "main.conf",
+        "#!config/ini\n[server]\nhost = \"localhost\"\nport = 8080\ninclude=override.conf\n"
+    );
+
+    let mut config = Config::new("source");
+    config.load_from_file(&env.path("main.conf")).expect("Impossibile caricare main.conf");
+
+    // L'inclusione vince ed è registrata come origine del valore
+    assert_eq!(config.get_integer("server", "port", None), Some(9090));
+    assert_eq!(config.value_source("server", "port"), Some(ValueSource::File(env.path("override.conf"))));
+    assert_eq!(config.value_source("server", "host"), Some(ValueSource::File(env.path("main.conf"))));
+
+    // Un valore impostato a runtime sovrascrive l'origine
+    config.set("server", "host", ConfigValue::String("example.com".to_string()));
+    assert_eq!(config.value_source("server", "host"), Some(ValueSource::Runtime));
+    assert_eq!(config.value_source("server", "missing"), None);
+}
+
+#[test]
+fn test_load_layered() {
+    let env = TestEnv::new("layered");
+
+    // Tre livelli: sistema, utente e locale, anche in formati diversi
+    env.create_config_file(
+        "system.conf",
+        "#!config/ini\n[server]\nhost = \"0.0.0.0\"\nport = 8080\n[log]\nlevel = \"info\"\n"
+    );
+    env.create_config_file(
+        "user.toml",
+        "#!config/toml\n[server]\nworkers = 4\n[server.tls]\nenabled = false\ncert = \"/etc/cert.pem\"\n"
+    );
+    env.create_config_file(
+        "local.yaml",
+        "#!config/yaml\nserver:\n  port: 9090\n  tls:\n    enabled: true\n"
+    );
+
+    let system = env.path("system.conf");
+    let user = env.path("user.toml");
+    let local = env.path("local.yaml");
+
+    let mut config = Config::new("layered");
+    config.load_layered(&[system.as_path(), user.as_path(), local.as_path()])
+        .expect("Impossibile caricare la configurazione a livelli");
+
+    // Il terzo livello sovrascrive la porta del primo
+    assert_eq!(config.get_integer("server", "port", None), Some(9090));
+    // I valori non sovrascritti restano
+    assert_eq!(config.get_string("server", "host", None), Some("0.0.0.0".to_string()));
+    assert_eq!(config.get_integer("server", "workers", None), Some(4));
+    assert_eq!(config.get_string("log", "level", None), Some("info".to_string()));
+    // Le tabelle vengono unite chiave per chiave
+    let tls = config.get_table("server", "tls").expect("Tabella server.tls mancante");
+    assert_eq!(tls.get("enabled").and_then(|v| v.as_boolean()), Some(true));
+    assert_eq!(tls.get("cert").and_then(|v| v.as_string()), Some(&"/etc/cert.pem".to_string()));
+    assert_eq!(config.get_format(), ConfigFormat::Yaml);
+
+    // Un file mancante è un errore, a meno di usare load_layered_optional
+    let missing = env.path("missing.conf");
+    let mut config = Config::new("layered");
+    let result = config.load_layered(&[system.as_path(), missing.as_path()]);
+    assert!(matches!(result, Err(ConfigError::Io(_))), "Dovrebbe dare un errore Io per file mancante");
+
+    let mut config = Config::new("layered");
+    config.load_layered_optional(&[system.as_path(), missing.as_path(), local.as_path()])
+        .expect("I file mancanti dovrebbero essere ignorati");
+    assert_eq!(config.get_integer("server", "port", None), Some(9090));
+
+    // Un livello intermedio non valido lascia la configurazione com'era, senza il primo livello
+    let broken = env.create_config_file("broken.json", "#!config/json\n{ invalid");
+    let mut config = Config::new("layered");
+    config.set("server", "port", ConfigValue::Integer(7070));
+    let result = config.load_layered(&[system.as_path(), broken.as_path(), local.as_path()]);
+    assert!(matches!(result, Err(ConfigError::ParseError(_))), "Errore di parsing atteso: {:?}", result);
+    assert_eq!(config.get_integer("server", "port", None), Some(7070));
+    assert!(config.get("server", "host").is_none(), "Il primo livello non dovrebbe restare applicato");
+    assert!(config.get("log", "level").is_none());
+    assert_eq!(config.get_format(), ConfigFormat::Unknown);
+}
+
+#[test]
+fn test_load_conf_d() {
+    let env = TestEnv::new("confd");
+
+    // File principale e due frammenti nella directory .d, anche in formati diversi
+    let main = env.create_config_file(
+        "myapp.conf",
+        "#!config/ini\n[server]\nhost = \"0.0.0.0\"\nport = 8080\n[log]\nlevel = \"info\"\n"
+    );
+    env.create_config_file("myapp.conf.d/20-port.conf", "#!config/ini\n[server]\nport = 9090\n");
+    env.create_config_file("myapp.conf.d/10-log.yaml", "#!config/yaml\nlog:\n  level: debug\nserver:\n  port: 8081\n");
+    // I file nascosti, i backup e i file che non sono di configurazione vengono ignorati
+    for junk in [".30-backup.conf", "30-port.conf~", "30-port.conf.bak", "30-port.conf.rpmsave",
+                 "30-port.conf.dpkg-old", "README"] {
+        env.create_config_file(&format!("myapp.conf.d/{}", junk), "#!config/ini\n[server]\nport = 1\n");
+    }
+
+    let mut config = Config::new("confd");
+    config.load_conf_d(&main).expect("Impossibile caricare la configurazione con frammenti");
+
+    // I frammenti sono applicati in ordine di nome: 20-port sovrascrive 10-log
+    assert_eq!(config.get_integer("server", "port", None), Some(9090));
+    assert_eq!(config.get_string("log", "level", None), Some("debug".to_string()));
+    assert_eq!(config.get_string("server", "host", None), Some("0.0.0.0".to_string()));
+    // Il file principale resta quello da salvare
+    assert_eq!(config.get_format(), ConfigFormat::Ini);
+
+    // Senza directory .d viene caricato solo il file principale
+    let other = env.create_config_file("other.conf", "#!config/ini\n[server]\nport = 7070\n");
+    let mut config = Config::new("confd");
+    config.load_conf_d(&other).expect("La directory .d dovrebbe essere facoltativa");
+    assert_eq!(config.get_integer("server", "port", None), Some(7070));
+
+    // Un frammento non valido non lascia applicati i precedenti
+    env.create_config_file("other.conf.d/10-port.conf", "#!config/ini\n[server]\nport = 9191\n");
+    env.create_config_file("other.conf.d/20-broken.json", "#!config/json\n{ invalid");
+    config.set("server", "port", ConfigValue::Integer(6060));
+    assert!(config.load_conf_d(&other).is_err());
+    assert_eq!(config.get_integer("server", "port", None), Some(6060));
+}
+
 #[test]
 fn test_config_edge_cases() {
     let env = TestEnv::new("edge");
@@ -489,4 +685,427 @@ fn test_config_modifications() {
         config2.get("section2", "key").and_then(|v| v.as_string()).cloned(),
         Some("sezione 2".to_string())
     );
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_custom_config_filename() {
+    let env = TestEnv::new("customapp");
+    let search_dirs = [env.path("etc"), env.path("home")];
+
+    // YAML senza intestazione: il formato deriva dall'estensione
+    env.create_config_file("home/config.yaml", "server:\n  port: 9090\n");
+
+    let mut config = Config::new("customapp");
+    config.set_search_dirs(&search_dirs).set_config_filename("config.yaml");
+    config.load().expect("Il file con il nome personalizzato dovrebbe essere trovato");
+    assert_eq!(config.get_format(), ConfigFormat::Yaml);
+    assert_eq!(config.get_integer("server", "port", None), Some(9090));
+
+    // Le directory sono cercate in ordine
+    env.create_config_file("etc/config.yaml", "server:\n  port: 8080\n");
+    let mut config = Config::new("customapp");
+    config.set_search_dirs(&search_dirs).set_config_filename("config.yaml");
+    config.load().expect("Caricamento fallito");
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+
+    // Senza il nome personalizzato il file non viene trovato
+    let mut default_config = Config::new("customapp");
+    default_config.set_search_dirs(&search_dirs);
+    assert!(matches!(default_config.load(), Err(ConfigError::ConfigNotFound(_))));
+}
+
+/// Formato personalizzato minimale: una riga `sezione.chiave=valore` per ogni stringa
+struct KeyValueFormat;
+
+impl FormatHandler for KeyValueFormat {
+    fn parse(&self, config: &mut Config, content: &str, _path: &std::path::Path) -> Result<(), ConfigError> {
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let (path, value) = line.split_once('=')
+                .ok_or_else(|| ConfigError::ParseError(format!("Riga non valida: {}", line)))?;
+            let (section, key) = path.split_once('.')
+                .ok_or_else(|| ConfigError::ParseError(format!("Chiave non valida: {}", path)))?;
+            config.set(section, key, ConfigValue::String(value.to_string()));
+        }
+        Ok(())
+    }
+
+    fn write(&self, config: &Config, path: &std::path::Path) -> Result<(), ConfigError> {
+        let mut lines: Vec<String> = config.flatten().into_iter()
+            .map(|(path, value)| format!("{}={}", path, value))
+            .collect();
+        lines.sort();
+        fs::write(path, format!("#!config/kv\n{}\n", lines.join("\n"))).map_err(ConfigError::Io)
+    }
+}
+
+#[test]
+fn test_custom_format_handler() {
+    let env = TestEnv::new("customformat");
+
+    let mut config = Config::new("customformat");
+    config.register_format("kv", KeyValueFormat);
+    config.set("server", "host", ConfigValue::String("localhost".to_string()));
+    config.set("server", "name", ConfigValue::String("demo".to_string()));
+    config.set_format(ConfigFormat::Custom("kv".to_string()));
+
+    // Salvataggio tramite il gestore registrato
+    let path = env.path("config.kv");
+    config.save_to_file(&path).expect("Impossibile salvare nel formato personalizzato");
+    let content = fs::read_to_string(&path).unwrap();
+    assert_eq!(content, "#!config/kv\nserver.host=localhost\nserver.name=demo\n");
+
+    // Caricamento: il formato è riconosciuto dall'intestazione
+    let mut loaded = Config::new("customformat");
+    loaded.register_format("kv", KeyValueFormat);
+    loaded.load_from_file(&path).expect("Impossibile caricare il formato personalizzato");
+    assert_eq!(loaded.get_format(), ConfigFormat::Custom("kv".to_string()));
+    assert_eq!(loaded.get_string("server", "host", None), Some("localhost".to_string()));
+    assert_eq!(loaded.get_string("server", "name", None), Some("demo".to_string()));
+
+    // Senza il gestore registrato il formato non è supportato
+    let mut unregistered = Config::new("customformat");
+    assert!(matches!(unregistered.load_from_file(&path), Err(ConfigError::UnsupportedFormat(_))));
+}
+
+#[test]
+fn test_root_section_clash_roundtrip() {
+    let env = TestEnv::new("rootclash");
+
+    // La sezione radice ha una chiave con lo stesso nome di una sezione e una tabella
+    let mut app = std::collections::HashMap::new();
+    app.insert("name".to_string(), ConfigValue::String("demo".to_string()));
+
+    let mut config = Config::new("rootclash");
+    config.set("default", "server", ConfigValue::String("primary".to_string()));
+    config.set("default", "app", ConfigValue::Table(app));
+    config.set("server", "port", ConfigValue::Integer(8080));
+
+    for format in [ConfigFormat::Ini, ConfigFormat::Toml, ConfigFormat::Yaml, ConfigFormat::Json] {
+        config.set_format(format.clone());
+        let path = env.path(&format!("config.{}", format));
+        config.save_to_file(&path).expect("Impossibile salvare la configurazione");
+
+        // Rileggendo il file, le chiavi restano nella sezione da cui provengono
+        let mut loaded = Config::new("rootclash");
+        loaded.load_from_file(&path).expect("Impossibile ricaricare la configurazione");
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(loaded.get_string("default", "server", None), Some("primary".to_string()),
+                   "Chiave radice persa in {}:\n{}", format, content);
+        assert_eq!(loaded.get_integer("server", "port", None), Some(8080),
+                   "Sezione persa in {}:\n{}", format, content);
+        assert!(loaded.get_table("default", "app").is_some(),
+                "La tabella radice dovrebbe restare nella sezione default in {}:\n{}", format, content);
+        assert!(loaded.get("app", "name").is_none(),
+                "La tabella radice non dovrebbe diventare una sezione in {}:\n{}", format, content);
+    }
+}
+
+#[test]
+fn test_ini_include_list() {
+    let env = TestEnv::new("includelist");
+
+    env.create_config_file("database.conf", "#!config/ini\n[database]\nhost = \"db.local\"\n");
+    env.create_config_file("cache.conf", "#!config/ini\n[cache]\nsize = 64\n");
+    env.create_config_file("plugins/auth.conf", "#!config/ini\n[auth]\nenabled = true\n");
+
+    // Elenco separato da virgole, con un pattern glob
+    env.create_config_file(
+        "main.conf",
+        "#!config/ini\ninclude = database.conf, cache.conf, plugins/*.conf\n[main]\nname = \"app\"\n"
+    );
+
+    let mut config = Config::new("includelist");
+    config.load_from_file(&env.path("main.conf")).expect("Impossibile caricare la configurazione");
+
+    assert_eq!(config.get_string("database", "host", None), Some("db.local".to_string()));
+    assert_eq!(config.get_integer("cache", "size", None), Some(64));
+    assert_eq!(config.get_boolean("auth", "enabled", None), Some(true));
+    assert_eq!(config.get_string("main", "name", None), Some("app".to_string()));
+}
+
+#[test]
+fn test_load_and_validate() {
+    let env = TestEnv::new("load_and_validate");
+
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "port", FieldDefinition::new(ValueType::Integer)
+        .required()
+        .constraint(FieldConstraint::integer().min_int(1).max_int(65535)));
+    schema.field("server", "workers", FieldDefinition::new(ValueType::Integer)
+        .default(ConfigValue::Integer(4)));
+
+    // File valido: i default dello schema vengono applicati
+    let valid = env.create_config_file("valid.conf", "#!config/ini\n[server]\nport = 8080\n");
+    let config = Config::load_and_validate("load_and_validate", &valid, &schema)
+        .expect("Il file valido dovrebbe essere caricato");
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+    assert_eq!(config.get_integer("server", "workers", None), Some(4));
+
+    // File non valido: gli errori di validazione diventano un ConfigError
+    let invalid = env.create_config_file("invalid.conf", "#!config/ini\n[server]\nport = 70000\n");
+    let error = Config::load_and_validate("load_and_validate", &invalid, &schema)
+        .expect_err("Il file non valido dovrebbe essere rifiutato");
+    assert!(error.to_string().contains("server.port"), "Messaggio inatteso: {}", error);
+
+    // Un file mancante resta un errore di I/O
+    let missing = env.path("missing.conf");
+    let result = Config::load_and_validate("load_and_validate", &missing, &schema);
+    assert!(matches!(result, Err(ConfigError::Io(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_include_through_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let env = TestEnv::new("include_symlinks");
+
+    env.create_config_file("shared/base.conf", "#!config/ini\n[log]\nlevel = \"info\"\n");
+    env.create_config_file("override.conf", "#!config/ini\n[log]\nlevel = \"debug\"\n");
+    // Il file figlio include di nuovo il file principale, attraverso un link alla directory
+    env.create_config_file("sub/child.conf", "#!config/ini\ninclude = up/main.conf\n[child]\nloaded = true\n");
+    symlink(env.path("shared"), env.path("linked")).expect("Impossibile creare il link");
+    symlink(env.path(""), env.path("sub/up")).expect("Impossibile creare il link");
+
+    // linked/base.conf è lo stesso file di shared/base.conf, e non viene riletto
+    env.create_config_file(
+        "main.conf",
+        "#!config/ini\ninclude = shared/base.conf, override.conf, linked/base.conf, sub/child.conf\n[main]\nname = \"app\"\n"
+    );
+
+    let mut config = Config::new("include_symlinks");
+    config.load_from_file(&env.path("main.conf")).expect("Impossibile caricare la configurazione");
+
+    assert_eq!(config.get_string("log", "level", None), Some("debug".to_string()));
+    assert_eq!(config.get_boolean("child", "loaded", None), Some(true));
+    assert_eq!(config.get_string("main", "name", None), Some("app".to_string()));
+}
+
+#[test]
+fn test_include_env_var() {
+    let env = TestEnv::new("include_env");
+
+    env.create_config_file("overrides/staging.conf", "#!config/ini\n[deploy]\nname = \"staging\"\n");
+    env.create_config_file("overrides/production.conf", "#!config/ini\n[deploy]\nname = \"production\"\n");
+    env.create_config_file("main.conf", "#!config/ini\ninclude = overrides/${APP_ENV}.conf\n[main]\nname = \"app\"\n");
+    env.create_config_file("undefined.conf", "#!config/ini\ninclude = overrides/${CONFUCIUS_UNDEFINED_VAR}.conf\n");
+
+    // Nessun altro test usa queste variabili d'ambiente
+    unsafe {
+        std::env::set_var("APP_ENV", "staging");
+        std::env::remove_var("CONFUCIUS_UNDEFINED_VAR");
+    }
+
+    let mut config = Config::new("include_env");
+    config.load_from_file(&env.path("main.conf")).expect("Impossibile caricare la configurazione");
+    assert_eq!(config.get_string("deploy", "name", None), Some("staging".to_string()));
+
+    // Una variabile non definita è un errore che la nomina
+    let mut config = Config::new("include_env");
+    let err = config.load_from_file(&env.path("undefined.conf")).unwrap_err();
+    assert!(err.to_string().contains("CONFUCIUS_UNDEFINED_VAR"), "Errore inatteso: {}", err);
+}
+
+#[test]
+fn test_validate_file() {
+    let env = TestEnv::new("validate_file");
+
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "port", FieldDefinition::new(ValueType::Integer)
+        .required()
+        .constraint(FieldConstraint::integer().min_int(1).max_int(65535)));
+    schema.field("server", "workers", FieldDefinition::new(ValueType::Integer)
+        .required()
+        .default(ConfigValue::Integer(4)));
+
+    // Il campo mancante con default non è un errore
+    let valid = env.create_config_file("valid.conf", "#!config/ini\n[server]\nport = 8080\n");
+    assert!(schema.validate_file(&valid).is_ok());
+
+    let invalid = env.create_config_file("invalid.conf", "#!config/ini\n[server]\nport = 0\n");
+    let error = schema.validate_file(&invalid).expect_err("Il file non valido dovrebbe essere rifiutato");
+    assert!(error.to_string().contains("server.port"), "Messaggio inatteso: {}", error);
+
+    assert!(matches!(schema.validate_file(&env.path("missing.conf")), Err(ConfigError::Io(_))));
+}
+
+#[test]
+fn test_load_defaults_from_str() {
+    let env = TestEnv::new("embedded_defaults");
+
+    // Default incorporati nel binario, ad esempio con include_str!
+    const DEFAULTS: &str = "#!config/toml\n[server]\nhost = \"127.0.0.1\"\nport = 8080\n[log]\nlevel = \"info\"\n";
+
+    let overrides = env.create_config_file("app.conf", "#!config/ini\n[server]\nport = 9090\n");
+
+    let mut config = Config::new("embedded_defaults");
+    config.load_defaults_from_str(DEFAULTS).expect("Impossibile caricare i default");
+    config.load_from_file(&overrides).expect("Impossibile caricare il file");
+
+    // Il file sovrascrive solo la chiave che imposta
+    assert_eq!(config.get_integer("server", "port", None), Some(9090));
+    assert_eq!(config.get_string("server", "host", None), Some("127.0.0.1".to_string()));
+    assert_eq!(config.get_string("log", "level", None), Some("info".to_string()));
+    assert_eq!(config.get_format(), ConfigFormat::Ini);
+
+    // Un contenuto non valido è un errore
+    let mut config = Config::new("embedded_defaults");
+    assert!(config.load_defaults_from_str("#!config/json\n{ invalid").is_err());
+}
+
+#[test]
+fn test_with_schema_validates_on_load() {
+    let env = TestEnv::new("with_schema");
+
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "port", FieldDefinition::new(ValueType::Integer)
+        .required()
+        .constraint(FieldConstraint::integer().min_int(1).max_int(65535)));
+    schema.field("server", "workers", FieldDefinition::new(ValueType::Integer)
+        .default(ConfigValue::Integer(4)));
+
+    let mut config = Config::new("with_schema");
+    config.with_schema(schema.clone());
+
+    // Il caricamento stesso fallisce su un file non valido
+    let invalid = env.create_config_file("invalid.conf", "#!config/ini\n[server]\nport = 70000\n");
+    let error = config.load_from_file(&invalid).expect_err("Il file non valido dovrebbe essere rifiutato");
+    assert!(error.to_string().contains("server.port"), "Messaggio inatteso: {}", error);
+
+    // Il file rifiutato non lascia né i suoi valori né i default dello schema
+    assert_eq!(config.get("server", "port"), None);
+    assert_eq!(config.get("server", "workers"), None);
+
+    // Lo stesso vale per i caricamenti a livelli e con directory .d
+    let base = env.create_config_file("base.conf", "#!config/ini
+[server]
+port = 8080
+");
+    assert!(config.load_layered(&[&base, &invalid]).is_err());
+    env.create_config_file("app.conf.d/10-port.conf", "#!config/ini
+[server]
+port = 70000
+");
+    let app = env.create_config_file("app.conf", "#!config/ini
+[server]
+port = 8080
+");
+    assert!(config.load_conf_d(&app).is_err());
+    assert!(config.get_values().is_empty(), "Valori rimasti: {:?}", config.get_values());
+
+    // Un file valido viene caricato con i default dello schema
+    let mut config = Config::new("with_schema");
+    config.with_schema(schema);
+    let valid = env.create_config_file("valid.conf", "#!config/ini\n[server]\nport = 8080\n");
+    config.load_from_file(&valid).expect("Il file valido dovrebbe essere caricato");
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+    assert_eq!(config.get_integer("server", "workers", None), Some(4));
+}
+
+#[test]
+fn test_include_base_dir_for_string_content() {
+    let env = TestEnv::new("include_base_dir");
+    env.create_config_file("conf/database.toml", "#!config/toml\n[database]\nhost = \"db.local\"\n");
+
+    // Contenuto senza file: l'include relativo usa la directory base impostata
+    const DEFAULTS: &str = "#!config/toml\ninclude = \"database.toml\"\n[server]\nport = 8080\n";
+
+    let mut config = Config::new("include_base_dir");
+    config.set_include_base_dir(env.path("conf"));
+    config.load_defaults_from_str(DEFAULTS).expect("Impossibile caricare i default");
+
+    assert_eq!(config.get_string("database", "host", None), Some("db.local".to_string()));
+    assert_eq!(config.get_integer("server", "port", None), Some(8080));
+
+    // Un secondo caricamento legge di nuovo lo stesso include
+    config.set("database", "host", ConfigValue::String("other".to_string()));
+    config.load_defaults_from_str(DEFAULTS).expect("Impossibile ricaricare i default");
+    assert_eq!(config.get_string("database", "host", None), Some("db.local".to_string()));
+
+    // Senza directory base l'include viene cercato nella directory corrente
+    let mut config = Config::new("include_base_dir");
+    assert!(matches!(config.load_defaults_from_str(DEFAULTS), Err(ConfigError::IncludeError(_))));
+}
+
+#[test]
+fn test_save_keeps_include_directives() {
+    let env = TestEnv::new("save_keeps_includes");
+    env.create_config_file("database.toml", "#!config/toml\n[database]\nhost = \"db.local\"\n");
+    env.create_config_file("main.toml", "#!config/toml\n# Impostazioni del database\ninclude = \"database.toml\"\n\n[server]\nport = 8080\n");
+    env.create_config_file("database.yaml", "#!config/yaml\ndatabase:\n  host: db.local\n");
+    env.create_config_file("main.yaml", "#!config/yaml\n# Impostazioni del database\ninclude: database.yaml\n\nserver:\n  port: 8080\n");
+
+    for (main, directive) in [("main.toml", "include = \"database.toml\""), ("main.yaml", "include: database.yaml")] {
+        let mut config = Config::new("save_keeps_includes");
+        config.load_from_file(&env.path(main)).expect("Impossibile caricare il file principale");
+        config.set("server", "port", ConfigValue::Integer(9090));
+        config.save_to_file(&env.path(main)).expect("Impossibile salvare il file principale");
+
+        // L'include e il suo commento restano, i valori inclusi non vengono copiati
+        let saved = fs::read_to_string(env.path(main)).unwrap();
+        assert!(saved.contains(&format!("# Impostazioni del database\n{}", directive)), "Include perso:\n{}", saved);
+        assert!(!saved.contains("db.local"), "Valori inclusi copiati:\n{}", saved);
+
+        let mut reloaded = Config::new("save_keeps_includes");
+        reloaded.load_from_file(&env.path(main)).expect("Impossibile ricaricare il file principale");
+        assert_eq!(reloaded.get_integer("server", "port", None), Some(9090));
+        assert_eq!(reloaded.get_string("database", "host", None), Some("db.local".to_string()));
+
+        // Esportato altrove, il file contiene tutti i valori e nessun include
+        let exported_path = env.path(&format!("export/{}", main));
+        fs::create_dir_all(env.path("export")).unwrap();
+        reloaded.save_to_file(&exported_path).expect("Impossibile esportare il file");
+        let exported = fs::read_to_string(&exported_path).unwrap();
+        assert!(!exported.contains("include"), "Include copiato nell'esportazione:\n{}", exported);
+
+        let mut imported = Config::new("save_keeps_includes");
+        imported.load_from_file(&exported_path).expect("Impossibile caricare il file esportato");
+        assert_eq!(imported.get_integer("server", "port", None), Some(9090));
+        assert_eq!(imported.get_string("database", "host", None), Some("db.local".to_string()));
+    }
+}
+
+/// Formato personalizzato che registra il file e i permessi ricevuti in scrittura
+#[cfg(unix)]
+struct ProbeFormat {
+    written: std::sync::Arc<std::sync::Mutex<Option<(PathBuf, u32)>>>,
+}
+
+#[cfg(unix)]
+impl FormatHandler for ProbeFormat {
+    fn parse(&self, _config: &mut Config, _content: &str, _path: &std::path::Path) -> Result<(), ConfigError> {
+        Ok(())
+    }
+
+    fn write(&self, _config: &Config, path: &std::path::Path) -> Result<(), ConfigError> {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path).map_err(ConfigError::Io)?.permissions().mode();
+        *self.written.lock().unwrap() = Some((path.to_path_buf(), mode & 0o777));
+        fs::write(path, "#!config/probe\nsecret\n").map_err(ConfigError::Io)
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn test_custom_format_save_is_atomic_and_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = TestEnv::new("customatomic");
+    env.create_config_file("secrets.probe", "#!config/probe\n");
+    let target = env.path("secrets.probe");
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).expect("Impossibile cambiare i permessi");
+
+    let written = std::sync::Arc::new(std::sync::Mutex::new(None));
+    let mut config = Config::new("customatomic");
+    config.register_format("probe", ProbeFormat { written: written.clone() });
+    config.load_from_file(&target).expect("Impossibile caricare il file");
+    config.save().expect("Impossibile salvare il file");
+
+    // Il gestore scrive su un file temporaneo già privato, poi rinominato sul file
+    let (temp_path, mode) = written.lock().unwrap().clone().expect("Il gestore non ha scritto");
+    assert_ne!(temp_path, target);
+    assert_eq!(mode, 0o600, "Il file temporaneo è leggibile da altri durante la scrittura");
+    assert!(!temp_path.exists(), "File temporaneo rimasto");
+    assert_eq!(fs::read_to_string(&target).unwrap(), "#!config/probe\nsecret\n");
+    assert_eq!(fs::metadata(&target).unwrap().permissions().mode() & 0o777, 0o600);
+}
@@ -0,0 +1,682 @@
+//! Test per il modulo di validazione della libreria Confucius
+//! Questi test verificano schemi, vincoli e valori di default
+
+use confucius::{Config, ConfigFormat, ConfigValue};
+use confucius::{FieldConstraint, FieldDefinition, FieldStatus, PathCheck, ReferenceTarget, StringFormat, ValidationError, ValidationSchema, ValueType};
+
+#[test]
+fn test_apply_defaults_for_section() {
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "port",
+                 FieldDefinition::new(ValueType::Integer).default(ConfigValue::Integer(8080)));
+    schema.field("plugin", "enabled",
+                 FieldDefinition::new(ValueType::Boolean).default(ConfigValue::Boolean(true)));
+
+    let mut config = Config::new("test");
+
+    // Applichiamo i default solo alla sezione "plugin"
+    schema.apply_defaults_for_section(&mut config, "plugin");
+
+    assert_eq!(config.get_boolean("plugin", "enabled", None), Some(true),
+               "Il default di plugin.enabled dovrebbe essere applicato");
+    assert!(config.get("server", "port").is_none(),
+            "La sezione server non dovrebbe essere modificata");
+}
+
+#[test]
+fn test_type_mismatch_message() {
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "hostname", FieldDefinition::new(ValueType::String));
+
+    let mut config = Config::new("test");
+    config.set("server", "hostname", ConfigValue::Integer(42));
+
+    // Il messaggio deve usare i nomi dei tipi in minuscolo
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    let message = errors.0[0].to_string();
+    assert!(message.contains("expected string, found integer"),
+            "Messaggio inatteso: {}", message);
+}
+
+/// Crea uno schema con una regola: se ssl_enabled è true, cert_file è obbligatorio
+fn ssl_schema() -> ValidationSchema {
+    let mut schema = ValidationSchema::new();
+    schema.rule(|config| {
+        if config.get_boolean("server", "ssl_enabled", Some(false)) == Some(true)
+            && config.get("server", "cert_file").is_none() {
+            return Err("server.cert_file is required when server.ssl_enabled is true".to_string());
+        }
+        Ok(())
+    });
+    schema
+}
+
+#[test]
+fn test_rule_co_required_fails() {
+    let schema = ssl_schema();
+
+    let mut config = Config::new("test");
+    config.set("server", "ssl_enabled", ConfigValue::Boolean(true));
+
+    // La regola deve fallire ed essere riportata tra gli errori
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert_eq!(errors.0.len(), 1);
+    assert!(matches!(errors.0[0], ValidationError::RuleFailed { .. }));
+}
+
+#[test]
+fn test_rule_co_required_passes() {
+    let schema = ssl_schema();
+
+    let mut config = Config::new("test");
+    config.set("server", "ssl_enabled", ConfigValue::Boolean(true));
+    config.set("server", "cert_file", ConfigValue::String("/etc/ssl/cert.pem".to_string()));
+
+    assert!(schema.validate(&config).is_ok(), "La validazione dovrebbe avere successo");
+}
+
+#[test]
+fn test_save_with_schema_descriptions() {
+    let temp_dir = tempfile::tempdir().expect("Impossibile creare directory temporanea");
+
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "port",
+                 FieldDefinition::new(ValueType::Integer).description("Port the server listens on"));
+    schema.field("server", "tls",
+                 FieldDefinition::new(ValueType::Table).description("TLS settings"));
+
+    let mut tls = std::collections::HashMap::new();
+    tls.insert("enabled".to_string(), ConfigValue::Boolean(true));
+
+    for format in [ConfigFormat::Ini, ConfigFormat::Toml, ConfigFormat::Yaml] {
+        let mut config = Config::new("test");
+        config.set_format(format.clone());
+        config.set("server", "port", ConfigValue::Integer(8080));
+        config.set("server", "host", ConfigValue::String("localhost".to_string()));
+        if format != ConfigFormat::Ini {
+            config.set("server", "tls", ConfigValue::Table(tls.clone()));
+        }
+
+        let path = temp_dir.path().join(format!("documented.{}", format));
+        let result = config.save_with_schema(&path, &schema);
+        assert!(result.is_ok(), "Salvataggio {} fallito: {:?}", format, result.err());
+
+        // La descrizione deve comparire come commento nel file
+        let content = std::fs::read_to_string(&path).expect("Impossibile leggere il file salvato");
+        assert!(content.contains("# Port the server listens on"),
+                "Manca la descrizione nel file {}:\n{}", format, content);
+        if format != ConfigFormat::Ini {
+            assert!(content.contains("# TLS settings"),
+                    "Manca la descrizione della tabella nel file {}:\n{}", format, content);
+        }
+
+        // Il file documentato deve restare caricabile
+        let mut loaded = Config::new("test");
+        let result = loaded.load_from_file(&path);
+        assert!(result.is_ok(), "Caricamento {} fallito: {:?}\n{}", format, result.err(), content);
+        assert_eq!(loaded.get_integer("server", "port", None), Some(8080));
+        assert_eq!(loaded.get_string("server", "host", None), Some("localhost".to_string()));
+    }
+}
+
+/// Crea uno schema con vincoli di formato sulle stringhe
+fn format_schema() -> ValidationSchema {
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "url", FieldDefinition::new(ValueType::String).constraint(FieldConstraint::url()));
+    schema.field("server", "admin", FieldDefinition::new(ValueType::String).constraint(FieldConstraint::email()));
+    schema.field("server", "bind", FieldDefinition::new(ValueType::String).constraint(FieldConstraint::ip_addr()));
+    schema.field("server", "listen", FieldDefinition::new(ValueType::String).constraint(FieldConstraint::socket_addr()));
+    schema
+}
+
+#[test]
+fn test_format_constraints_valid() {
+    let schema = format_schema();
+
+    let mut config = Config::new("test");
+    config.set("server", "url", ConfigValue::String("https://user@example.com:8443/api?v=1".to_string()));
+    config.set("server", "admin", ConfigValue::String("admin@example.com".to_string()));
+    config.set("server", "bind", ConfigValue::String("::1".to_string()));
+    config.set("server", "listen", ConfigValue::String("127.0.0.1:8080".to_string()));
+
+    assert!(schema.validate(&config).is_ok(), "Tutti i valori dovrebbero essere validi");
+}
+
+#[test]
+fn test_format_constraints_invalid() {
+    let schema = format_schema();
+
+    let mut config = Config::new("test");
+    config.set("server", "url", ConfigValue::String("example.com/api".to_string()));
+    config.set("server", "admin", ConfigValue::String("admin.example.com".to_string()));
+    config.set("server", "bind", ConfigValue::String("300.1.1.1".to_string()));
+    config.set("server", "listen", ConfigValue::String("127.0.0.1".to_string()));
+
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert_eq!(errors.0.len(), 4, "Ci dovrebbe essere un errore per campo: {}", errors);
+
+    // L'errore sull'URL deve indicare il formato e il motivo
+    let url_error = errors.0.iter()
+        .find(|e| matches!(e, ValidationError::InvalidFormat { format: StringFormat::Url, .. }))
+        .expect("Manca l'errore sull'URL");
+    let message = url_error.to_string();
+    assert!(message.contains("server.url") && message.contains("missing scheme"),
+            "Messaggio inatteso: {}", message);
+}
+
+#[test]
+fn test_url_constraint_schemes() {
+    let schema = format_schema();
+
+    // URL file senza host e URL senza autorità sono validi
+    for url in ["file:///etc/app.pem", "mailto:ops@example.com", "urn:isbn:0451450523", "tel:+39-06-1234567",
+                "data:text/plain,ciao", "http://[::1]:8080/"] {
+        let mut config = Config::new("test");
+        config.set("server", "url", ConfigValue::String(url.to_string()));
+        assert!(schema.validate(&config).is_ok(), "{} dovrebbe essere valido", url);
+    }
+
+    // Host mancante fuori dagli URL file, host:porta, percorsi Windows, credenziali
+    // e schema senza percorso non lo sono
+    for url in ["https:///api", "localhost:8080", "localhost:8080/api", "db.example.com:5432/mydb", "c:/x",
+                "user:pass", "mailto:", "1http://example.com"] {
+        let mut config = Config::new("test");
+        config.set("server", "url", ConfigValue::String(url.to_string()));
+        assert!(schema.validate(&config).is_err(), "{} non dovrebbe essere valido", url);
+    }
+}
+
+#[test]
+fn test_default_with_computed_value() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    // Il default viene calcolato ad ogni applicazione
+    let counter = Arc::new(AtomicI64::new(0));
+    let generator = Arc::clone(&counter);
+
+    let mut schema = ValidationSchema::new();
+    schema.field("node", "id", FieldDefinition::new(ValueType::Integer)
+        .default_with(move || ConfigValue::Integer(generator.fetch_add(1, Ordering::SeqCst) + 100)));
+    schema.field("node", "pid", FieldDefinition::new(ValueType::Integer)
+        .default_with(|| ConfigValue::Integer(std::process::id() as i64)));
+
+    let mut first = Config::new("test");
+    schema.apply_defaults(&mut first);
+    let mut second = Config::new("test");
+    schema.apply_defaults(&mut second);
+
+    assert_eq!(first.get_integer("node", "id", None), Some(100));
+    assert_eq!(second.get_integer("node", "id", None), Some(101));
+    assert_eq!(first.get_integer("node", "pid", None), Some(std::process::id() as i64));
+
+    // Un valore già presente non deve essere sovrascritto né ricalcolato
+    let mut existing = Config::new("test");
+    existing.set("node", "id", ConfigValue::Integer(7));
+    schema.apply_defaults(&mut existing);
+    assert_eq!(existing.get_integer("node", "id", None), Some(7));
+    assert_eq!(counter.load(Ordering::SeqCst), 2, "La funzione non dovrebbe essere chiamata");
+}
+
+#[test]
+fn test_schema_report() {
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "host", FieldDefinition::new(ValueType::String).required());
+    schema.field("server", "port",
+                 FieldDefinition::new(ValueType::Integer).default(ConfigValue::Integer(8080)));
+    schema.field("server", "cert_file", FieldDefinition::new(ValueType::String));
+
+    let mut config = Config::new("test");
+    config.set("server", "host", ConfigValue::String("example.com".to_string()));
+
+    let report = schema.report(&config);
+
+    // Presente nella configurazione, coperto dal default, mancante
+    assert_eq!(report.status("server", "host"), Some(FieldStatus::Present));
+    assert_eq!(report.status("server", "port"), Some(FieldStatus::Default));
+    assert_eq!(report.status("server", "cert_file"), Some(FieldStatus::Missing));
+    assert_eq!(report.status("server", "unknown"), None);
+
+    assert_eq!(report.paths_with(FieldStatus::Missing), vec!["server.cert_file"]);
+    assert!(!report.is_complete());
+    assert!(report.fields.iter().find(|f| f.path == "server.host").unwrap().required);
+}
+
+#[test]
+fn test_save_non_defaults() {
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "port",
+                 FieldDefinition::new(ValueType::Integer).default(ConfigValue::Integer(8080)));
+    schema.field("server", "workers",
+                 FieldDefinition::new(ValueType::Integer).default(ConfigValue::Integer(4)));
+    schema.field("log", "level",
+                 FieldDefinition::new(ValueType::String).default(ConfigValue::String("info".to_string())));
+
+    let mut config = Config::new("test");
+    config.set_format(ConfigFormat::Ini);
+    schema.apply_defaults(&mut config);
+    // Solo workers viene modificato rispetto al default
+    config.set("server", "workers", ConfigValue::Integer(16));
+    config.set("server", "host", ConfigValue::String("example.com".to_string()));
+
+    let temp_dir = tempfile::tempdir().expect("Impossibile creare directory temporanea");
+    let path = temp_dir.path().join("lean.ini");
+    config.save_non_defaults(&path, &schema).expect("Impossibile salvare la configurazione");
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains("workers = 16"), "La chiave modificata deve essere scritta:\n{}", content);
+    assert!(content.contains("host = \"example.com\""), "La chiave senza default deve essere scritta:\n{}", content);
+    assert!(!content.contains("port"), "La chiave con valore di default deve essere omessa:\n{}", content);
+    assert!(!content.contains("[log]"), "La sezione rimasta vuota deve essere omessa:\n{}", content);
+}
+
+#[test]
+fn test_table_constraint() {
+    let mut schema = ValidationSchema::new();
+    schema.field("pool", "limits", FieldDefinition::new(ValueType::Table).constraint(
+        FieldConstraint::table()
+            .key_pattern("^[a-z_]+$")
+            .value_type(FieldDefinition::new(ValueType::Integer)
+                .constraint(FieldConstraint::integer().min_int(1).max_int(100)))
+            .max_entries(3)));
+
+    let mut limits = std::collections::HashMap::new();
+    limits.insert("workers".to_string(), ConfigValue::Integer(8));
+    limits.insert("max_queue".to_string(), ConfigValue::String("lots".to_string()));
+
+    let mut config = Config::new("test");
+    config.set("pool", "limits", ConfigValue::Table(limits.clone()));
+
+    // L'errore deve riportare il percorso della chiave della tabella
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert_eq!(errors.0.len(), 1);
+    match &errors.0[0] {
+        ValidationError::TypeMismatch { path, .. } => assert_eq!(path, "pool.limits.max_queue"),
+        other => panic!("TypeMismatch atteso, trovato {:?}", other),
+    }
+
+    // Chiave non conforme al pattern
+    limits.insert("max_queue".to_string(), ConfigValue::Integer(50));
+    limits.insert("Timeout".to_string(), ConfigValue::Integer(30));
+    config.set("pool", "limits", ConfigValue::Table(limits.clone()));
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert!(matches!(&errors.0[0], ValidationError::PatternMismatch { path, .. } if path == "pool.limits.Timeout"));
+
+    // Tabella valida
+    limits.remove("Timeout");
+    config.set("pool", "limits", ConfigValue::Table(limits));
+    assert!(schema.validate(&config).is_ok());
+}
+
+#[test]
+fn test_validate_all_reports_allowed_unknowns() {
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "port", FieldDefinition::new(ValueType::Integer));
+    schema.allow_unknown_keys(true).allow_unknown_sections(true);
+
+    let mut config = Config::new("test");
+    config.set("server", "port", ConfigValue::String("80".to_string()));
+    config.set("server", "extra", ConfigValue::Boolean(true));
+    config.set("plugins", "enabled", ConfigValue::Boolean(true));
+
+    // validate ignora le chiavi e le sezioni sconosciute ammesse
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert_eq!(errors.0.len(), 1);
+
+    // validate_all le riporta come informazioni, senza modificare la configurazione
+    let report = schema.validate_all(&config);
+    assert!(!report.is_valid());
+    assert_eq!(report.errors().len(), 1);
+    assert!(matches!(report.errors()[0], ValidationError::TypeMismatch { .. }));
+
+    let infos = report.infos();
+    assert_eq!(infos.len(), 2);
+    assert!(infos.iter().any(|e| matches!(e, ValidationError::UnknownKey { section, key } if section == "server" && key == "extra")));
+    assert!(infos.iter().any(|e| matches!(e, ValidationError::UnknownSection { section } if section == "plugins")));
+    assert_eq!(config.get_string("server", "port", None), Some("80".to_string()));
+}
+
+#[test]
+fn test_validate_single_value() {
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "port", FieldDefinition::new(ValueType::Integer)
+        .constraint(FieldConstraint::integer().min_int(1).max_int(65535)));
+
+    // Recuperiamo la definizione e validiamo un valore alla volta
+    let definition = schema.field_definition("server", "port").expect("Il campo dovrebbe essere definito");
+    assert!(definition.validate(Some(&ConfigValue::Integer(8080)), "server.port").is_ok());
+
+    let error = definition.validate(Some(&ConfigValue::Integer(70000)), "server.port")
+        .expect_err("Il valore dovrebbe essere rifiutato");
+    assert!(matches!(error, ValidationError::IntegerTooLarge { ref path, max: 65535, actual: 70000 } if path == "server.port"));
+
+    assert!(schema.field_definition("server", "host").is_none());
+}
+
+#[test]
+fn test_path_constraints() {
+    let temp_dir = tempfile::tempdir().expect("Impossibile creare directory temporanea");
+    let cert_path = temp_dir.path().join("cert.pem");
+    std::fs::write(&cert_path, "certificato").expect("Impossibile scrivere il file");
+
+    let mut schema = ValidationSchema::new();
+    schema.field("tls", "cert_file", FieldDefinition::new(ValueType::String).constraint(FieldConstraint::path_is_file()));
+    schema.field("tls", "cert_dir", FieldDefinition::new(ValueType::String).constraint(FieldConstraint::path_is_dir()));
+    schema.field("tls", "key_file", FieldDefinition::new(ValueType::String).constraint(FieldConstraint::path_exists()));
+
+    // Percorsi esistenti: la validazione passa
+    let mut config = Config::new("test");
+    config.set("tls", "cert_file", ConfigValue::String(cert_path.display().to_string()));
+    config.set("tls", "cert_dir", ConfigValue::String(temp_dir.path().display().to_string()));
+    config.set("tls", "key_file", ConfigValue::String(cert_path.display().to_string()));
+    assert!(schema.validate(&config).is_ok(), "I percorsi esistenti dovrebbero essere validi");
+
+    // Percorso inesistente e file al posto di una directory
+    let missing = temp_dir.path().join("missing.pem").display().to_string();
+    config.set("tls", "key_file", ConfigValue::String(missing.clone()));
+    config.set("tls", "cert_dir", ConfigValue::String(cert_path.display().to_string()));
+
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert_eq!(errors.0.len(), 2);
+    assert!(errors.0.iter().any(|e| matches!(e,
+        ValidationError::InvalidPath { path, check: PathCheck::Exists, value, .. } if path == "tls.key_file" && *value == missing)));
+    assert!(errors.0.iter().any(|e| matches!(e,
+        ValidationError::InvalidPath { check: PathCheck::IsDir, reason, .. } if reason == "not a directory")));
+}
+
+#[test]
+fn test_integer_step_constraint() {
+    let mut schema = ValidationSchema::new();
+    schema.field("io", "buffer_size", FieldDefinition::new(ValueType::Integer)
+        .constraint(FieldConstraint::integer().min_int(512).step(512)));
+    schema.field("io", "odd_port", FieldDefinition::new(ValueType::Integer)
+        .constraint(FieldConstraint::integer().step(2).offset(1)));
+
+    let mut config = Config::new("test");
+    config.set("io", "buffer_size", ConfigValue::Integer(1024));
+    config.set("io", "odd_port", ConfigValue::Integer(8081));
+    assert!(schema.validate(&config).is_ok(), "1024 e 8081 dovrebbero essere validi");
+
+    // 513 non è un multiplo di 512: l'errore indica il passo
+    config.set("io", "buffer_size", ConfigValue::Integer(513));
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert_eq!(errors.0.len(), 1);
+    assert!(matches!(errors.0[0], ValidationError::IntegerNotOnStep { step: 512, offset: 0, actual: 513, .. }));
+    assert!(errors.0[0].to_string().contains("multiple of 512"), "Messaggio inatteso: {}", errors.0[0]);
+
+    // Con offset 1 e passo 2 solo i dispari sono validi
+    config.set("io", "buffer_size", ConfigValue::Integer(2048));
+    config.set("io", "odd_port", ConfigValue::Integer(8080));
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert!(matches!(errors.0[0], ValidationError::IntegerNotOnStep { step: 2, offset: 1, actual: 8080, .. }));
+}
+
+#[test]
+fn test_deprecated_alias() {
+    let mut schema = ValidationSchema::new();
+    schema.allow_unknown_keys(false);
+    schema.field("server", "host", FieldDefinition::new(ValueType::String).required());
+    schema.deprecated_alias("server", "hostname", "host");
+
+    let mut config = Config::new("test");
+    config.set("server", "hostname", ConfigValue::String("example.com".to_string()));
+
+    // La vecchia chiave soddisfa il campo obbligatorio e non è sconosciuta
+    assert!(schema.validate(&config).is_ok(), "La chiave deprecata dovrebbe essere accettata");
+
+    // L'uso della vecchia chiave è segnalato come warning
+    let report = schema.validate_all(&config);
+    assert!(report.is_valid());
+    let warnings = report.warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(matches!(warnings[0], ValidationError::DeprecatedKey { old_key, new_key, .. }
+        if old_key == "hostname" && new_key == "host"));
+
+    // apply_aliases copia il valore sotto il nuovo nome
+    let warnings = schema.apply_aliases(&mut config);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].to_string().contains("use server.host instead"), "Messaggio inatteso: {}", warnings[0]);
+    assert_eq!(config.get_string("server", "host", None), Some("example.com".to_string()));
+
+    // Il nuovo nome ha la precedenza se entrambi sono presenti
+    config.set("server", "host", ConfigValue::String("new.example.com".to_string()));
+    schema.apply_aliases(&mut config);
+    assert_eq!(config.get_string("server", "host", None), Some("new.example.com".to_string()));
+}
+
+#[test]
+fn test_reference_constraints() {
+    let mut schema = ValidationSchema::new();
+    schema.field("app", "active_profile", FieldDefinition::new(ValueType::String)
+        .constraint(FieldConstraint::references_section()));
+    schema.field("app", "default_user", FieldDefinition::new(ValueType::String)
+        .constraint(FieldConstraint::references_key("users")));
+
+    let mut config = Config::new("test");
+    config.set("app", "active_profile", ConfigValue::String("production".to_string()));
+    config.set("app", "default_user", ConfigValue::String("admin".to_string()));
+    config.set("production", "debug", ConfigValue::Boolean(false));
+    config.set("users", "admin", ConfigValue::String("Administrator".to_string()));
+    assert!(schema.validate(&config).is_ok(), "I riferimenti esistenti dovrebbero essere validi");
+
+    // Il profilo attivo punta a una sezione inesistente
+    config.set("app", "active_profile", ConfigValue::String("staging".to_string()));
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert_eq!(errors.0.len(), 1);
+    assert!(matches!(&errors.0[0], ValidationError::DanglingReference { path, target: ReferenceTarget::Section, value }
+        if path == "app.active_profile" && value == "staging"));
+    assert!(errors.0[0].to_string().contains("no section named \"staging\""), "Messaggio inatteso: {}", errors.0[0]);
+
+    // L'utente predefinito deve essere una chiave della sezione users
+    config.set("app", "active_profile", ConfigValue::String("production".to_string()));
+    config.set("app", "default_user", ConfigValue::String("guest".to_string()));
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert!(matches!(&errors.0[0], ValidationError::DanglingReference { target: ReferenceTarget::Key(section), .. }
+        if section == "users"));
+}
+
+#[test]
+fn test_array_unique_constraint() {
+    let mut schema = ValidationSchema::new();
+    schema.field("auth", "allowed_users", FieldDefinition::new(ValueType::Array)
+        .constraint(FieldConstraint::array().min_length(1).unique()));
+
+    let mut config = Config::new("test");
+    config.set("auth", "allowed_users", ConfigValue::Array(vec![
+        ConfigValue::String("alice".to_string()),
+        ConfigValue::String("bob".to_string()),
+    ]));
+    assert!(schema.validate(&config).is_ok(), "Elementi distinti dovrebbero essere validi");
+
+    // "alice" compare di nuovo in posizione 2
+    config.set("auth", "allowed_users", ConfigValue::Array(vec![
+        ConfigValue::String("alice".to_string()),
+        ConfigValue::String("bob".to_string()),
+        ConfigValue::String("alice".to_string()),
+        ConfigValue::String("bob".to_string()),
+    ]));
+    let errors = schema.validate(&config).expect_err("La validazione dovrebbe fallire");
+    assert_eq!(errors.0.len(), 1);
+    assert!(matches!(&errors.0[0], ValidationError::ArrayDuplicateItem { path, index: 2, first_index: 0 }
+        if path == "auth.allowed_users"));
+}
+
+#[test]
+fn test_schema_coverage() {
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "host", FieldDefinition::new(ValueType::String).required());
+    schema.field("server", "port",
+                 FieldDefinition::new(ValueType::Integer).default(ConfigValue::Integer(8080)));
+    schema.field("log", "level", FieldDefinition::new(ValueType::String));
+    schema.field("log", "file", FieldDefinition::new(ValueType::String));
+
+    let mut config = Config::new("test");
+    assert_eq!(schema.coverage(&config), 0.0);
+
+    // Il campo con default non conta come impostato
+    config.set("server", "host", ConfigValue::String("example.com".to_string()));
+    config.set("log", "level", ConfigValue::String("debug".to_string()));
+    config.set("log", "extra", ConfigValue::Boolean(true));
+    assert_eq!(schema.coverage(&config), 0.5);
+
+    config.set("server", "port", ConfigValue::Integer(9090));
+    config.set("log", "file", ConfigValue::String("app.log".to_string()));
+    assert_eq!(schema.coverage(&config), 1.0);
+
+    // Uno schema senza campi è sempre completo
+    assert_eq!(ValidationSchema::new().coverage(&config), 1.0);
+}
+
+#[test]
+fn test_required_in_profiles() {
+    let build_schema = |profile: &str| {
+        let mut schema = ValidationSchema::new();
+        schema.field("tls", "enabled", FieldDefinition::new(ValueType::Boolean));
+        schema.field("tls", "cert_file", FieldDefinition::new(ValueType::String)
+            .required_in_profiles(&["production", "staging"]));
+        schema.active_profile(profile);
+        schema
+    };
+
+    let mut config = Config::new("test");
+    config.set("tls", "enabled", ConfigValue::Boolean(true));
+
+    // In sviluppo il certificato è facoltativo
+    assert!(build_schema("development").validate(&config).is_ok());
+
+    // In produzione è obbligatorio, e manca
+    let errors = build_schema("production").validate(&config).unwrap_err();
+    assert_eq!(errors.0.len(), 1);
+    assert!(matches!(&errors.0[0], ValidationError::MissingField { path } if path == "tls.cert_file"));
+
+    // Senza profilo attivo il campo è facoltativo
+    let mut schema = ValidationSchema::new();
+    schema.field("tls", "cert_file", FieldDefinition::new(ValueType::String).required_in_profiles(&["production"]));
+    assert!(schema.validate(&config).is_ok());
+
+    config.set("tls", "cert_file", ConfigValue::String("/etc/ssl/app.pem".to_string()));
+    assert!(build_schema("production").validate(&config).is_ok());
+}
+
+#[cfg(feature = "fancy-regex")]
+#[test]
+fn test_pattern_fancy_lookahead() {
+    // Almeno una cifra, e il nome riservato "admin" escluso
+    let constraint = FieldConstraint::pattern_fancy(r"^(?!admin$)(?=.*\d)\w+$").expect("Pattern valido");
+    let field = FieldDefinition::new(ValueType::String).constraint(constraint);
+
+    assert!(field.validate(Some(&ConfigValue::String("user1".to_string())), "auth.user").is_ok());
+    let error = field.validate(Some(&ConfigValue::String("admin".to_string())), "auth.user").unwrap_err();
+    assert!(matches!(error, ValidationError::PatternMismatch { ref path, .. } if path == "auth.user"));
+    assert!(field.validate(Some(&ConfigValue::String("nodigits".to_string())), "auth.user").is_err());
+
+    // Un pattern non valido è un errore, non un panic
+    assert!(matches!(FieldConstraint::pattern_fancy("(abc"), Err(ValidationError::InvalidPattern { .. })));
+}
+
+#[test]
+fn test_constraint_type_mismatch() {
+    // Errore nello schema: vincolo intero su un campo stringa
+    let mut schema = ValidationSchema::new();
+    schema.field("server", "port", FieldDefinition::new(ValueType::String)
+        .constraint(FieldConstraint::integer().min_int(1).max_int(65535)));
+
+    let mut config = Config::new("test");
+    config.set("server", "port", ConfigValue::String("8080".to_string()));
+
+    let errors = schema.validate(&config).unwrap_err();
+    assert_eq!(errors.0.len(), 1);
+    assert!(matches!(&errors.0[0], ValidationError::ConstraintTypeMismatch {
+        path, constraint: ValueType::Integer, actual: ValueType::String
+    } if path == "server.port"));
+
+    // Anche il vincolo usato da solo segnala il tipo errato
+    let error = FieldConstraint::string().min_length(3).validate(&ConfigValue::Integer(5), "app.name").unwrap_err();
+    assert_eq!(error.to_string(), "Constraint type mismatch for app.name: string constraint on a integer value");
+
+    // I vincoli personalizzati accettano qualsiasi tipo
+    let custom = FieldConstraint::custom(|_| Ok(()), "qualsiasi");
+    assert!(custom.validate(&ConfigValue::Boolean(true), "app.flag").is_ok());
+}
+
+#[test]
+fn test_integer_fits_width() {
+    let constraint = FieldConstraint::integer().fits::<u16>();
+
+    assert!(constraint.validate(&ConfigValue::Integer(0), "net.port").is_ok());
+    assert!(constraint.validate(&ConfigValue::Integer(65535), "net.port").is_ok());
+
+    // Fuori dall'intervallo di u16, in entrambe le direzioni
+    let error = constraint.validate(&ConfigValue::Integer(65536), "net.port").unwrap_err();
+    assert!(matches!(error, ValidationError::IntegerOutOfWidth { actual: 65536, .. }));
+    assert_eq!(error.to_string(), "Integer out of range for net.port: 65536 does not fit in u16 (0..=65535)");
+    assert!(constraint.validate(&ConfigValue::Integer(-1), "net.port").is_err());
+
+    // Si combina con gli altri limiti
+    let constraint = FieldConstraint::integer().min_int(1).fits::<i8>();
+    assert!(constraint.validate(&ConfigValue::Integer(127), "app.level").is_ok());
+    assert!(matches!(constraint.validate(&ConfigValue::Integer(128), "app.level"),
+                     Err(ValidationError::IntegerOutOfWidth { .. })));
+    assert!(matches!(constraint.validate(&ConfigValue::Integer(0), "app.level"),
+                     Err(ValidationError::IntegerTooSmall { .. })));
+}
+
+#[test]
+fn test_default_from_other_field() {
+    let mut schema = ValidationSchema::new();
+    schema.field("user", "username", FieldDefinition::new(ValueType::String)
+        .default(ConfigValue::String("guest".to_string())));
+    schema.field("user", "display_name", FieldDefinition::new(ValueType::String)
+        .default_from(|config| config.get_owned("user", "username")));
+
+    // Il default viene derivato dal valore presente
+    let mut config = Config::new("test");
+    config.set("user", "username", ConfigValue::String("mrossi".to_string()));
+    schema.apply_defaults(&mut config);
+    assert_eq!(config.get_string("user", "display_name", None), Some("mrossi".to_string()));
+
+    // Il default derivato vede i default già applicati
+    let mut empty = Config::new("test");
+    schema.apply_defaults(&mut empty);
+    assert_eq!(empty.get_string("user", "display_name", None), Some("guest".to_string()));
+
+    // Un valore già presente non viene sovrascritto
+    let mut existing = Config::new("test");
+    existing.set("user", "username", ConfigValue::String("mrossi".to_string()));
+    existing.set("user", "display_name", ConfigValue::String("Mario Rossi".to_string()));
+    schema.apply_defaults(&mut existing);
+    assert_eq!(existing.get_string("user", "display_name", None), Some("Mario Rossi".to_string()));
+
+    // Se la funzione non restituisce un valore, il campo resta mancante
+    let mut schema = ValidationSchema::new();
+    schema.field("user", "display_name", FieldDefinition::new(ValueType::String)
+        .default_from(|config| config.get_owned("user", "username")));
+    let mut missing = Config::new("test");
+    schema.apply_defaults(&mut missing);
+    assert!(missing.get("user", "display_name").is_none());
+}
+
+#[test]
+fn test_exactly_one_of() {
+    let mut schema = ValidationSchema::new();
+    schema.exactly_one_of("database", &["url", "socket", "host"]);
+
+    // Nessuna chiave presente: errore che elenca le alternative
+    let mut config = Config::new("test");
+    config.set("database", "user", ConfigValue::String("app".to_string()));
+    let errors = schema.validate(&config).expect_err("Nessuna alternativa dovrebbe essere un errore");
+    assert!(errors.0.iter().any(|e| matches!(e, ValidationError::ExactlyOneOf { present, .. } if present.is_empty())));
+    let message = errors.to_string();
+    assert!(message.contains("url, socket, host") && message.contains("found none"),
+            "Messaggio inatteso: {}", message);
+
+    // Una sola chiave presente: valido
+    config.set("database", "socket", ConfigValue::String("/run/db.sock".to_string()));
+    assert!(schema.validate(&config).is_ok());
+
+    // Due chiavi presenti: errore che elenca quelle in conflitto
+    config.set("database", "host", ConfigValue::String("db.local".to_string()));
+    let errors = schema.validate(&config).expect_err("Due alternative dovrebbero essere un errore");
+    let message = errors.to_string();
+    assert!(message.contains("found socket, host"), "Messaggio inatteso: {}", message);
+}